@@ -1,7 +1,9 @@
 mod builder;
 
 use crate::error::Result;
+use bongo_mong::config::secrets;
 use builder::ConfigBuilder;
+use std::fmt;
 use {once_cell::sync::Lazy, serde::Deserialize};
 
 pub static CONFIG: Lazy<Result<AppConfig>> = Lazy::new(AppConfig::read_config_for_env);
@@ -17,11 +19,19 @@ pub struct AppConfig {
     pub users: MongoOpts,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Clone)]
 pub struct MongoOpts {
     #[serde(flatten)]
     pub bongo: config::Value,
 }
+
+impl fmt::Debug for MongoOpts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MongoOpts")
+            .field("bongo", &secrets::redact(&self.bongo))
+            .finish()
+    }
+}
 impl<'de> ConfigBuilder<'de> for AppConfig {
     type Config = Self;
 }
@@ -1,9 +1,14 @@
 use {
     axum::{
-        http::StatusCode,
+        http::{Request, StatusCode},
+        middleware::Next,
         response::{IntoResponse, Response},
     },
+    bongo_mong::error::BongoError,
+    mongodb::error::{ErrorKind, WriteFailure},
+    sentry_wrapper::{AlertType, ErrorReport, Level},
     serde_json::json,
+    tower_request_id::RequestId,
 };
 
 use crate::Json;
@@ -11,6 +16,11 @@ use crate::Json;
 /// Alias for `std::result::Result` with an error type [`AppError`].
 pub type Result<T> = std::result::Result<T, AppError>;
 
+tokio::task_local! {
+    /// The id of the request being handled, reported along with errors.
+    static REQUEST_ID: String;
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum AppError {
     #[error("Value not found")]
@@ -29,28 +39,128 @@ pub enum AppError {
     Mongo(#[from] mongodb::error::Error),
 
     #[error("Bongo error: {0}")]
-    Bongo(#[from] bongo_mong::error::BongoError),
+    Bongo(#[from] BongoError),
 
     #[error("Not found user with id: {0}")]
     User(String),
+
+    #[error("User already exists with id: {0}")]
+    UserExists(String),
+
+    #[error("Invalid api key")]
+    InvalidApiKey,
+}
+
+impl AppError {
+    /// A machine-readable code identifying the kind of error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NotFound => "NOT_FOUND",
+            Self::User(_) => "USER_NOT_FOUND",
+            Self::UserExists(_) => "USER_EXISTS",
+            Self::InvalidApiKey => "INVALID_API_KEY",
+            Self::Mongo(_) | Self::Bongo(_) if self.is_database_unavailable() => {
+                "DATABASE_UNAVAILABLE"
+            }
+            Self::Mongo(_) | Self::Bongo(_) => "DATABASE_ERROR",
+            Self::Config(_) | Self::TcpBind | Self::Startup(_) | Self::Prometheus(_) => {
+                "INTERNAL_ERROR"
+            }
+        }
+    }
+
+    /// The HTTP status the error is reported with.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::NotFound | Self::User(_) => StatusCode::NOT_FOUND,
+            Self::UserExists(_) => StatusCode::CONFLICT,
+            Self::InvalidApiKey => StatusCode::UNAUTHORIZED,
+            _ if self.is_database_unavailable() => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Check if the error was caused by failing to reach the Mongo servers.
+    fn is_database_unavailable(&self) -> bool {
+        match self {
+            Self::Mongo(err) | Self::Bongo(BongoError::MongoDbError(err)) => {
+                is_connectivity_error(err)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Check if the driver error is a network or server selection failure.
+fn is_connectivity_error(err: &mongodb::error::Error) -> bool {
+    matches!(
+        *err.kind,
+        ErrorKind::Io(_)
+            | ErrorKind::ServerSelection { .. }
+            | ErrorKind::ConnectionPoolCleared { .. }
+            | ErrorKind::DnsResolve { .. }
+    )
+}
+
+/// Check if the driver error is a violation of a unique index.
+pub fn is_duplicate_key_error(err: &mongodb::error::Error) -> bool {
+    const DUPLICATE_KEY: i32 = 11000;
+
+    match &*err.kind {
+        ErrorKind::Write(WriteFailure::WriteError(err)) => err.code == DUPLICATE_KEY,
+        ErrorKind::Command(err) => err.code == DUPLICATE_KEY,
+        _ => false,
+    }
+}
+
+/// A middleware that runs the rest of the request within the scope of its id,
+/// so that errors turned into responses can report it.
+pub async fn scope_request_id<B>(req: Request<B>, next: Next<B>) -> impl IntoResponse {
+    let request_id = req
+        .extensions()
+        .get::<RequestId>()
+        .map(ToString::to_string)
+        .unwrap_or_else(|| "unknown".into());
+
+    REQUEST_ID.scope(request_id, next.run(req)).await
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let (status, err_message) = match self {
-            Self::NotFound => (StatusCode::NOT_FOUND, "Resource not found"),
-            Self::Mongo(_)
-            | Self::Bongo(_)
-            | Self::User(_)
-            | Self::Config(_)
-            | Self::TcpBind
-            | Self::Startup(_)
-            | Self::Prometheus(_) => {
-                unreachable!("This error can only occur during startup")
-            }
+        let status = self.status();
+        let code = self.code();
+        let request_id = REQUEST_ID
+            .try_with(Clone::clone)
+            .unwrap_or_else(|_| "unknown".into());
+
+        let report = ErrorReport::new(&self)
+            .add_tag("code", code)
+            .add_tag("request_id", &request_id);
+        if status.is_server_error() {
+            report
+                .set_level(Some(Level::Error))
+                .set_alert(AlertType::Medium)
+                .send();
+        } else {
+            report.send();
+        }
+
+        // Do not leak internal details to the client
+        let err_message = if status.is_server_error() {
+            status
+                .canonical_reason()
+                .unwrap_or("Internal error")
+                .to_string()
+        } else {
+            self.to_string()
         };
 
-        let body = Json(json!({ "error": err_message }));
+        let body = Json(json!({
+            "error": err_message,
+            "code": code,
+            "status": status.as_u16(),
+            "requestId": request_id,
+        }));
         (status, body).into_response()
     }
 }
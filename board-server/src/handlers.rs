@@ -10,10 +10,13 @@ use axum::{
 };
 use tokio::sync::broadcast;
 
-use crate::mongo::{client::Mongod, users::User};
-use crate::{error, AppError};
+use crate::mongo::{
+    client::Mongod,
+    users::{User, UserPatch, UserReplace},
+};
+use crate::{error, tenant::Tenant, AppError};
 use axum::extract::Query;
-use mongodb::results::InsertOneResult;
+use bongo_mong::results::InsertOneResult;
 use rand::Rng;
 use serde::Deserialize;
 use tracing_wrapper::tracing::{self, instrument};
@@ -41,6 +44,14 @@ pub fn routes() -> Router {
         .route("/api/hello", get(hello_name))
         .route("/api/addData", post(insert_user))
         .route("/api/getData/:id", get(get_user_with_id))
+        .route("/api/users", get(list_users).post(create_user))
+        .route(
+            "/api/users/:id",
+            get(get_user)
+                .put(replace_user)
+                .patch(patch_user)
+                .delete(delete_user),
+        )
         .route("/rand", post(rand_integer))
         .route("/ws/rand", get(find_all_integers))
         .layer(Extension(app_state))
@@ -64,24 +75,92 @@ pub async fn hello_name(user: Query<HelloNameGetName>) -> String {
 }
 
 pub async fn insert_user(
-    Extension(db_con): Extension<Mongod<'_>>,
+    Extension(db_con): Extension<Mongod>,
+    Extension(tenant): Extension<Tenant>,
     Json(payload): Json<User>,
 ) -> Result<Json<InsertOneResult>, error::AppError> {
     let x = db_con
-        .insert_user_in_base(payload.id, payload.name, payload.age)
+        .insert_user_in_base(payload.id, payload.name, payload.age, tenant.api_key())
         .await?;
 
     Ok(Json(x))
 }
 
 pub async fn get_user_with_id(
-    Extension(db_con): Extension<Mongod<'_>>,
+    Extension(db_con): Extension<Mongod>,
+    Extension(tenant): Extension<Tenant>,
     Path(id): Path<String>,
 ) -> Result<Json<User>, error::AppError> {
-    let x = db_con.find_use_in_base(id).await?;
+    let x = db_con.find_use_in_base(id, tenant.api_key()).await?;
     Ok(Json(x))
 }
 
+pub async fn list_users(
+    Extension(db_con): Extension<Mongod>,
+    Extension(tenant): Extension<Tenant>,
+) -> Result<Json<Vec<User>>, AppError> {
+    let users = db_con.get_users_from_base(tenant.api_key()).await?;
+    Ok(Json(users))
+}
+
+pub async fn create_user(
+    Extension(db_con): Extension<Mongod>,
+    Extension(tenant): Extension<Tenant>,
+    Json(payload): Json<User>,
+) -> Result<(StatusCode, Json<User>), AppError> {
+    db_con
+        .insert_user_in_base(
+            payload.id.clone(),
+            payload.name.clone(),
+            payload.age,
+            tenant.api_key(),
+        )
+        .await?;
+    Ok((StatusCode::CREATED, Json(payload)))
+}
+
+pub async fn get_user(
+    Extension(db_con): Extension<Mongod>,
+    Extension(tenant): Extension<Tenant>,
+    Path(id): Path<String>,
+) -> Result<Json<User>, AppError> {
+    let user = db_con.find_use_in_base(id, tenant.api_key()).await?;
+    Ok(Json(user))
+}
+
+pub async fn replace_user(
+    Extension(db_con): Extension<Mongod>,
+    Extension(tenant): Extension<Tenant>,
+    Path(id): Path<String>,
+    Json(payload): Json<UserReplace>,
+) -> Result<Json<User>, AppError> {
+    let user = db_con
+        .replace_user_in_base(id, payload, tenant.api_key())
+        .await?;
+    Ok(Json(user))
+}
+
+pub async fn patch_user(
+    Extension(db_con): Extension<Mongod>,
+    Extension(tenant): Extension<Tenant>,
+    Path(id): Path<String>,
+    Json(payload): Json<UserPatch>,
+) -> Result<Json<User>, AppError> {
+    let user = db_con
+        .patch_user_in_base(id, payload, tenant.api_key())
+        .await?;
+    Ok(Json(user))
+}
+
+pub async fn delete_user(
+    Extension(db_con): Extension<Mongod>,
+    Extension(tenant): Extension<Tenant>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, AppError> {
+    db_con.delete_user_from_base(id, tenant.api_key()).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
 pub async fn rand_integer(
     Extension(app_state): Extension<Arc<AppState>>,
 ) -> Result<Json<i32>, AppError> {
@@ -120,3 +199,147 @@ async fn handle_socket(socket: WebSocket, app_state: Arc<AppState>) {
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mongo::users::Users;
+    use axum::{
+        body::Body,
+        http::{header, Method, Request},
+    };
+    use bongo_mong::{testing::MemoryStore, PoolManager};
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    async fn app() -> Router {
+        let pool_manager =
+            PoolManager::with_memory_store(config::Config::default(), MemoryStore::new()).unwrap();
+        let mongod = Mongod::with_collection(Users::new(pool_manager));
+        mongod.ensure_indexes().await.unwrap();
+
+        routes()
+            .layer(Extension(mongod))
+            .layer(Extension(Tenant::default()))
+    }
+
+    async fn request(
+        app: &Router,
+        method: Method,
+        uri: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/json");
+        let body = body.map_or_else(Body::empty, |body| Body::from(body.to_string()));
+        let response = app
+            .clone()
+            .oneshot(request.body(body).unwrap())
+            .await
+            .unwrap();
+
+        let status = response.status();
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+        (status, body)
+    }
+
+    fn user(id: &str, name: &str, age: u8) -> Value {
+        json!({"id": id, "name": name, "age": age})
+    }
+
+    #[tokio::test]
+    async fn creates_and_gets_users() {
+        let app = app().await;
+        let created = request(&app, Method::POST, "/api/users", Some(user("1", "Ann", 30))).await;
+        assert_eq!(created, (StatusCode::CREATED, user("1", "Ann", 30)));
+        request(&app, Method::POST, "/api/users", Some(user("2", "Bob", 40))).await;
+
+        let found = request(&app, Method::GET, "/api/users/1", None).await;
+        assert_eq!(found, (StatusCode::OK, user("1", "Ann", 30)));
+        let (status, users) = request(&app, Method::GET, "/api/users", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(users.as_array().map(Vec::len), Some(2));
+
+        let (status, body) = request(&app, Method::GET, "/api/users/3", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["code"], "USER_NOT_FOUND");
+    }
+
+    #[tokio::test]
+    async fn rejects_existing_users() {
+        let app = app().await;
+        request(&app, Method::POST, "/api/users", Some(user("1", "Ann", 30))).await;
+
+        let (status, body) =
+            request(&app, Method::POST, "/api/users", Some(user("1", "Bob", 40))).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["code"], "USER_EXISTS");
+        let (status, body) = request(
+            &app,
+            Method::POST,
+            "/api/addData",
+            Some(user("1", "Bob", 40)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["code"], "USER_EXISTS");
+    }
+
+    #[tokio::test]
+    async fn adds_data() {
+        let app = app().await;
+        let (status, body) = request(
+            &app,
+            Method::POST,
+            "/api/addData",
+            Some(user("1", "Ann", 30)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.get("insertedId").is_some());
+
+        let found = request(&app, Method::GET, "/api/getData/1", None).await;
+        assert_eq!(found, (StatusCode::OK, user("1", "Ann", 30)));
+    }
+
+    #[tokio::test]
+    async fn replaces_and_patches_users() {
+        let app = app().await;
+        request(&app, Method::POST, "/api/users", Some(user("1", "Ann", 30))).await;
+
+        let replace = json!({"name": "Bob", "age": 40});
+        let replaced = request(&app, Method::PUT, "/api/users/1", Some(replace.clone())).await;
+        assert_eq!(replaced, (StatusCode::OK, user("1", "Bob", 40)));
+        let patched = request(
+            &app,
+            Method::PATCH,
+            "/api/users/1",
+            Some(json!({"age": 41})),
+        )
+        .await;
+        assert_eq!(patched, (StatusCode::OK, user("1", "Bob", 41)));
+
+        let (status, _) = request(&app, Method::PUT, "/api/users/2", Some(replace)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = request(&app, Method::PATCH, "/api/users/2", Some(json!({}))).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = request(&app, Method::PUT, "/api/users/1", Some(json!({}))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn deletes_users() {
+        let app = app().await;
+        request(&app, Method::POST, "/api/users", Some(user("1", "Ann", 30))).await;
+
+        let deleted = request(&app, Method::DELETE, "/api/users/1", None).await;
+        assert_eq!(deleted, (StatusCode::NO_CONTENT, Value::Null));
+        let (status, _) = request(&app, Method::GET, "/api/users/1", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = request(&app, Method::DELETE, "/api/users/1", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
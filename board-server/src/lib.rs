@@ -5,6 +5,7 @@ mod json;
 pub mod metrics;
 pub mod mongo;
 pub mod openapi;
+pub mod tenant;
 pub mod updown;
 
 pub use crate::{config::CONFIG, error::AppError, json::Json};
@@ -6,6 +6,7 @@ use std::net::TcpListener;
 use board_server::{
     error::{AppError, Result},
     metrics,
+    mongo::client::Mongod,
     updown::startup,
     CONFIG,
 };
@@ -46,7 +47,11 @@ async fn main() -> Result<()> {
     tracing::info!("Starting server on {}", addr);
     let listener = TcpListener::bind(&addr).or(Err(AppError::TcpBind))?;
 
-    startup::run(listener)?
+    let db_con = Mongod::new(config)?;
+    db_con.ensure_indexes().await?;
+    db_con.spawn_health_monitor();
+
+    startup::run(listener, db_con)?
         .with_graceful_shutdown(shutdown_signal())
         .await
         .map_err(|e| AppError::Startup(e.to_string()))?;
@@ -1,64 +1,325 @@
 use std::convert::TryFrom;
 
-use mongodb::results::InsertOneResult;
+use super::users::{User, UserPatch, UserReplace, Users};
+use crate::{config::AppConfig, error::is_duplicate_key_error, AppError};
+use bongo_mong::dao::{DbConnect, Query};
+use bongo_mong::error::BongoError;
+use bongo_mong::query::Update;
+use bongo_mong::results::InsertOneResult;
+use bongo_mong::{health::HealthCheck, PoolManager};
+use futures::stream::TryStreamExt;
+use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
+use tracing_wrapper::tracing;
 
-use mongodb::bson::doc;
+#[derive(Clone)]
+pub struct Mongod {
+    pub collection: Users,
+}
 
-use super::users::{User, Users};
-use crate::{AppError, CONFIG};
-use bongo_mong::dao::Query;
-use bongo_mong::PoolManager;
-use config::ConfigError;
-use once_cell::sync::Lazy;
+impl Mongod {
+    /// Connect to the collection with the pools of the given configuration.
+    ///
+    /// The pool configuration is validated first, reporting every problem at once.
+    pub fn new(config: &AppConfig) -> Result<Self, AppError> {
+        let pool_manager = PoolManager::try_from(config.users.bongo.clone())?;
+        pool_manager.validate()?;
 
-static POOLS: Lazy<Result<PoolManager, AppError>> = Lazy::new(|| {
-    let config_1 = Lazy::force(&CONFIG)
-        .as_ref()
-        .map_err(|e| ConfigError::Message(e.to_string()))?;
+        Ok(Self::with_collection(Users::with_name(
+            config.collection.as_str(),
+            pool_manager,
+        )))
+    }
 
-    Ok(PoolManager::try_from(config_1.users.bongo.clone())?)
-});
+    /// Use the given collection, e.g. one backed by a pool manager built in a test.
+    pub fn with_collection(collection: Users) -> Self {
+        Self { collection }
+    }
 
-#[derive(Clone)]
-pub struct Mongod<'a> {
-    pub collection: Users<'a>,
-}
+    /// Check if the pool configuration has an app section for the api key.
+    pub fn has_app(&self, api_key: &str) -> bool {
+        self.collection.pool_manager().has_app(api_key)
+    }
 
-impl<'a> Mongod<'a> {
-    pub fn new() -> Result<Self, AppError> {
-        let config_1 = Lazy::force(&CONFIG)
-            .as_ref()
-            .map_err(|e| ConfigError::Message(e.to_string()))?;
-        let pool_manager = Lazy::force(&POOLS)
-            .as_ref()
-            .map_err(|err| ConfigError::Message(err.to_string()))?;
-
-        Ok(Self {
-            collection: Users::new(config_1.collection.as_str(), pool_manager),
-        })
+    /// Create the indexes the collection declares, warning about the ones that differ.
+    pub async fn ensure_indexes(&self) -> Result<(), AppError> {
+        let reports = self
+            .collection
+            .pool_manager()
+            .ensure_indexes(&[&self.collection])
+            .await?;
+
+        for report in reports.iter().filter(|report| report.has_drift()) {
+            tracing::warn!(
+                collection = %report.collection,
+                api_key = ?report.api_key,
+                drifted = ?report.drifted,
+                undeclared = ?report.undeclared,
+                "indexes differ from the declared ones"
+            );
+        }
+        Ok(())
     }
 
+    /// Start checking the health of the pools in the background.
+    pub fn spawn_health_monitor(&self) {
+        self.collection
+            .pool_manager()
+            .spawn_health_monitor(HealthCheck::default());
+    }
+
+    pub async fn get_users_from_base(&self, api_key: Option<&str>) -> Result<Vec<User>, AppError> {
+        Ok(self
+            .collection
+            .find(None, None, api_key)
+            .await?
+            .try_collect()
+            .await?)
+    }
+
+    /// Insert a new user, rejecting ids that already exist.
+    ///
+    /// The unique index on the user `id` is what rejects them, see [`Mongod::ensure_indexes`].
     pub async fn insert_user_in_base(
         &self,
         id: String,
         name: String,
         age: u8,
+        api_key: Option<&str>,
     ) -> Result<InsertOneResult, AppError> {
-        let new_user = User { id, name, age };
+        let new_user = User {
+            id: id.clone(),
+            name,
+            age,
+        };
 
-        Ok(self.collection.insert_one(new_user, None, None).await?)
+        self.collection
+            .insert_one(new_user, None, api_key)
+            .await
+            .map_err(|err| match err {
+                BongoError::MongoDbError(err) if is_duplicate_key_error(&err) => {
+                    AppError::UserExists(id)
+                }
+                err => err.into(),
+            })
     }
 
-    pub async fn find_use_in_base(&self, id: String) -> Result<User, AppError> {
+    pub async fn find_use_in_base(
+        &self,
+        id: String,
+        api_key: Option<&str>,
+    ) -> Result<User, AppError> {
         self.collection
-            .find_one(
-                doc! {
-                    "id": id.as_str()
-                },
-                None,
+            .find_one(User::ID.eq(id.as_str()), None, api_key)
+            .await?
+            .ok_or(AppError::User(id))
+    }
+
+    /// Replace every field of the user with the given `id`.
+    pub async fn replace_user_in_base(
+        &self,
+        id: String,
+        user: UserReplace,
+        api_key: Option<&str>,
+    ) -> Result<User, AppError> {
+        let result = self
+            .collection
+            .update_one(
+                User::ID.eq(id.as_str()).into(),
+                User::NAME
+                    .set(user.name.as_str())
+                    .and(User::AGE.set(user.age))?,
                 None,
+                api_key,
             )
+            .await?;
+
+        if result.matched_count == 0 {
+            return Err(AppError::User(id));
+        }
+
+        Ok(User {
+            id,
+            name: user.name,
+            age: user.age,
+        })
+    }
+
+    /// Update only the fields of the user that are present in `patch`.
+    pub async fn patch_user_in_base(
+        &self,
+        id: String,
+        patch: UserPatch,
+        api_key: Option<&str>,
+    ) -> Result<User, AppError> {
+        let mut update = Update::default();
+        if let Some(name) = patch.name {
+            update = update.and(User::NAME.set(name))?;
+        }
+        if let Some(age) = patch.age {
+            update = update.and(User::AGE.set(age))?;
+        }
+
+        if update.is_empty() {
+            return self.find_use_in_base(id, api_key).await;
+        }
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        self.collection
+            .find_one_and_update(User::ID.eq(id.as_str()).into(), update, options, api_key)
             .await?
             .ok_or(AppError::User(id))
     }
+
+    pub async fn delete_user_from_base(
+        &self,
+        id: String,
+        api_key: Option<&str>,
+    ) -> Result<User, AppError> {
+        self.collection
+            .find_one_and_delete(User::ID.eq(id.as_str()).into(), None, api_key)
+            .await?
+            .ok_or(AppError::User(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bongo_mong::testing::MemoryStore;
+    use config::{Config, File, FileFormat};
+
+    fn mongod(store: &MemoryStore) -> Mongod {
+        let pool_manager =
+            PoolManager::with_memory_store(config::Config::default(), store.clone()).unwrap();
+        Mongod::with_collection(Users::new(pool_manager))
+    }
+
+    async fn insert(mongod: &Mongod, id: &str, api_key: Option<&str>) {
+        mongod
+            .insert_user_in_base(id.into(), format!("user {}", id), 30, api_key)
+            .await
+            .unwrap();
+    }
+
+    fn from_json<T: serde::de::DeserializeOwned>(source: &str) -> T {
+        Config::builder()
+            .add_source(File::from_str(source, FileFormat::Json))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap()
+    }
+
+    #[test]
+    fn new_validates_the_pool_config() {
+        let config: AppConfig = from_json(include_str!("../../configs/local.json"));
+        assert!(Mongod::new(&config).is_ok());
+
+        let mut config = config;
+        config.users.bongo =
+            from_json(r#"{"read": {"baseUri": "mongodb://localhost/db", "maxPoolSize": "lots"}}"#);
+        assert!(matches!(
+            Mongod::new(&config),
+            Err(AppError::Bongo(BongoError::InvalidConfig(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn inserts_and_finds_users() {
+        let store = MemoryStore::new();
+        let mongod = mongod(&store);
+        insert(&mongod, "1", None).await;
+        insert(&mongod, "2", None).await;
+
+        let user = mongod.find_use_in_base("1".into(), None).await.unwrap();
+        assert_eq!((user.name.as_str(), user.age), ("user 1", 30));
+        assert_eq!(mongod.get_users_from_base(None).await.unwrap().len(), 2);
+        assert!(matches!(
+            mongod.find_use_in_base("3".into(), None).await,
+            Err(AppError::User(id)) if id == "3"
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_existing_users() {
+        let store = MemoryStore::new();
+        let mongod = mongod(&store);
+        mongod.ensure_indexes().await.unwrap();
+        insert(&mongod, "1", None).await;
+
+        let inserted = mongod
+            .insert_user_in_base("1".into(), "other".into(), 40, None)
+            .await;
+        assert!(matches!(inserted, Err(AppError::UserExists(id)) if id == "1"));
+        assert_eq!(store.documents(None, "users").len(), 1);
+    }
+
+    #[tokio::test]
+    async fn replaces_and_patches_users() {
+        let mongod = mongod(&MemoryStore::new());
+        insert(&mongod, "1", None).await;
+
+        let replace = UserReplace {
+            name: "Ann".into(),
+            age: 41,
+        };
+        let user = mongod
+            .replace_user_in_base("1".into(), replace.clone(), None)
+            .await
+            .unwrap();
+        assert_eq!((user.name.as_str(), user.age), ("Ann", 41));
+
+        let patch = UserPatch {
+            age: Some(42),
+            ..Default::default()
+        };
+        let user = mongod
+            .patch_user_in_base("1".into(), patch.clone(), None)
+            .await
+            .unwrap();
+        assert_eq!((user.name.as_str(), user.age), ("Ann", 42));
+
+        assert!(matches!(
+            mongod.replace_user_in_base("2".into(), replace, None).await,
+            Err(AppError::User(_))
+        ));
+        assert!(matches!(
+            mongod.patch_user_in_base("2".into(), patch, None).await,
+            Err(AppError::User(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn deletes_users() {
+        let mongod = mongod(&MemoryStore::new());
+        insert(&mongod, "1", None).await;
+
+        let user = mongod
+            .delete_user_from_base("1".into(), None)
+            .await
+            .unwrap();
+        assert_eq!(user.id, "1");
+        assert!(mongod.get_users_from_base(None).await.unwrap().is_empty());
+        assert!(matches!(
+            mongod.delete_user_from_base("1".into(), None).await,
+            Err(AppError::User(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn separates_tenants() {
+        let mongod = mongod(&MemoryStore::new());
+        insert(&mongod, "1", Some("tenant")).await;
+
+        assert!(mongod.get_users_from_base(None).await.unwrap().is_empty());
+        assert_eq!(
+            mongod
+                .get_users_from_base(Some("tenant"))
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
 }
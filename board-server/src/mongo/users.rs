@@ -1,40 +1,34 @@
 //! The interface for the "Users" collection.
-use bongo_mong::dao;
+use bongo_mong::dao::Collection;
+use bongo_mong::query::Fields;
 use bongo_mong::PoolManager;
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, Fields)]
 pub struct User {
     pub id: String,
     pub name: String,
     pub age: u8,
 }
 
-#[derive(Clone, Debug)]
-pub struct Users<'a> {
-    name: String,
-    pool_manager: &'a PoolManager,
-}
-
-impl<'a> Users<'a> {
-    pub fn new(name: &'a str, pool_manager: &'a PoolManager) -> Self {
-        Self {
-            name: name.into(),
-            pool_manager,
-        }
-    }
+/// The body of a `PUT` request, replacing every field but the `id`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct UserReplace {
+    pub name: String,
+    pub age: u8,
 }
 
-impl<'a> dao::Collection for Users<'a> {
-    fn name(&self) -> &str {
-        &self.name
-    }
+/// The body of a `PATCH` request, updating only the given fields.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct UserPatch {
+    pub name: Option<String>,
+    pub age: Option<u8>,
 }
 
-impl<'a> dao::DbConnect for Users<'a> {
-    fn pool_manager(&self) -> &PoolManager {
-        self.pool_manager
-    }
+#[derive(Clone, Debug, Collection)]
+#[bongo(name = "users", model = User)]
+#[bongo(index(keys(id = 1), unique))]
+pub struct Users {
+    name: String,
+    pool_manager: PoolManager,
 }
-
-impl<'a> dao::Query<User> for Users<'a> {}
@@ -0,0 +1,227 @@
+//! The tenant a request is made for, identified by its api key, and who makes it.
+use axum::{http::Request, middleware::Next, response::Response};
+use bongo_mong::audit::with_actor;
+use sentry_wrapper::extract_api_key;
+use std::future::Future;
+
+use crate::{mongo::client::Mongod, AppError};
+
+/// The longest api key accepted.
+const MAX_API_KEY_LEN: usize = 128;
+
+/// The header naming the user a request claims to be made by.
+const USER_ID_HEADER: &str = "x-user-id";
+
+/// The tenant of a request, inserted in its extensions by [`extract_tenant`].
+///
+/// Requests without an api key use the default pools.
+#[derive(Clone, Debug, Default)]
+pub struct Tenant {
+    api_key: Option<String>,
+}
+
+impl Tenant {
+    /// The api key the pools of the request are resolved with.
+    pub fn api_key(&self) -> Option<&str> {
+        self.api_key.as_deref()
+    }
+}
+
+/// A middleware that reads the api key of the request, rejecting malformed ones and the ones
+/// without an app section in the pool configuration.
+///
+/// The [`Mongod`] of the server must be in the extensions of the request.
+pub async fn extract_tenant<B>(mut req: Request<B>, next: Next<B>) -> Result<Response, AppError> {
+    let api_key = match extract_api_key(req.headers()) {
+        None => None,
+        Some(Ok(api_key)) if is_valid(api_key) && is_known(&req, api_key) => {
+            Some(api_key.to_string())
+        }
+        Some(_) => return Err(AppError::InvalidApiKey),
+    };
+    req.extensions_mut().insert(Tenant { api_key });
+
+    Ok(next.run(req).await)
+}
+
+/// Who a request is made by, inserted in its extensions by [`scope_actor`].
+#[derive(Clone, Debug, Default)]
+pub struct Actor {
+    name: Option<String>,
+    claimed_user: Option<String>,
+}
+
+impl Actor {
+    /// The name the writes of the request are audited with, if known.
+    ///
+    /// This is the validated api key of the [`Tenant`] of the request.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The user named by the `x-user-id` header of the request.
+    ///
+    /// Anyone can set the header, so the user is only claimed, not verified, and is never
+    /// audited.
+    pub fn claimed_user(&self) -> Option<&str> {
+        self.claimed_user.as_deref()
+    }
+
+    /// Run `future`, recording the writes it makes as made by the actor, if known.
+    pub async fn scope<F: Future>(&self, future: F) -> F::Output {
+        match &self.name {
+            Some(name) => with_actor(name.as_str(), future).await,
+            None => future.await,
+        }
+    }
+}
+
+/// A middleware that runs the rest of the request as made by its actor, so that the writes it
+/// makes are audited with it.
+///
+/// The actor is named by the api key of the [`Tenant`] of the request, so the middleware must
+/// run within [`extract_tenant`].
+pub async fn scope_actor<B>(mut req: Request<B>, next: Next<B>) -> Response {
+    let name = req
+        .extensions()
+        .get::<Tenant>()
+        .and_then(|tenant| tenant.api_key().map(ToString::to_string));
+    let claimed_user = req
+        .headers()
+        .get(USER_ID_HEADER)
+        .and_then(|user_id| user_id.to_str().ok())
+        .filter(|user_id| !user_id.is_empty())
+        .map(ToString::to_string);
+    let actor = Actor { name, claimed_user };
+    req.extensions_mut().insert(actor.clone());
+
+    actor.scope(next.run(req)).await
+}
+
+/// Check that the api key can name an app section of the pool configuration.
+fn is_valid(api_key: &str) -> bool {
+    (1..=MAX_API_KEY_LEN).contains(&api_key.len())
+        && api_key
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_'))
+}
+
+/// Check that the pool configuration has an app section for the api key.
+///
+/// Pools of unknown api keys would silently fall back to the default ones.
+fn is_known<B>(req: &Request<B>, api_key: &str) -> bool {
+    matches!(req.extensions().get::<Mongod>(), Some(mongod) if mongod.has_app(api_key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mongo::users::Users;
+    use axum::{body::Body, http::StatusCode, middleware, routing::get, Extension, Router};
+    use bongo_mong::{audit::actor, testing::MemoryStore, PoolManager};
+    use config::{Config, File, FileFormat};
+    use tower::ServiceExt;
+
+    fn config() -> Config {
+        let source = r#"{
+            "mongodbPerApp": {
+                "wat": {}
+            }
+        }"#;
+        Config::builder()
+            .add_source(File::from_str(source, FileFormat::Json))
+            .build()
+            .unwrap()
+    }
+
+    fn app() -> Router {
+        let pool_manager = PoolManager::with_memory_store(config(), MemoryStore::new()).unwrap();
+        let mongod = Mongod::with_collection(Users::new(pool_manager));
+        Router::new()
+            .route(
+                "/",
+                get(|Extension(tenant): Extension<Tenant>| async move {
+                    tenant.api_key().unwrap_or("none").to_string()
+                }),
+            )
+            .route(
+                "/actor",
+                get(|| async { actor().unwrap_or_else(|| "none".into()) }),
+            )
+            .route(
+                "/claimed",
+                get(|Extension(actor): Extension<Actor>| async move {
+                    actor.claimed_user().unwrap_or("none").to_string()
+                }),
+            )
+            .layer(middleware::from_fn(scope_actor))
+            .layer(middleware::from_fn(extract_tenant))
+            .layer(Extension(mongod))
+    }
+
+    async fn request(api_key: Option<&str>) -> (StatusCode, String) {
+        let headers: Vec<_> = api_key
+            .map(|api_key| ("x-api-key", api_key))
+            .into_iter()
+            .collect();
+        request_with("/", &headers).await
+    }
+
+    async fn request_with(uri: &str, headers: &[(&str, &str)]) -> (StatusCode, String) {
+        let mut request = Request::builder().uri(uri);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let response = app()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        let status = response.status();
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, String::from_utf8(bytes.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn extract_tenant_accepts_known_keys() {
+        assert_eq!(request(Some("wat")).await, (StatusCode::OK, "wat".into()));
+    }
+
+    #[tokio::test]
+    async fn extract_tenant_accepts_missing_keys() {
+        assert_eq!(request(None).await, (StatusCode::OK, "none".into()));
+    }
+
+    #[tokio::test]
+    async fn extract_tenant_rejects_malformed_keys() {
+        for api_key in ["", "wat/../other", &"a".repeat(MAX_API_KEY_LEN + 1)] {
+            let (status, _) = request(Some(api_key)).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    #[tokio::test]
+    async fn extract_tenant_rejects_unknown_keys() {
+        let (status, _) = request(Some("other")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn scope_actor_names_the_api_key() {
+        let response = request_with("/actor", &[("x-api-key", "wat")]).await;
+        assert_eq!(response, (StatusCode::OK, "wat".into()));
+        let response = request_with("/actor", &[]).await;
+        assert_eq!(response, (StatusCode::OK, "none".into()));
+    }
+
+    #[tokio::test]
+    async fn scope_actor_only_claims_the_user() {
+        let headers = [("x-api-key", "wat"), ("x-user-id", "maria")];
+        let response = request_with("/actor", &headers).await;
+        assert_eq!(response, (StatusCode::OK, "wat".into()));
+        let response = request_with("/claimed", &headers).await;
+        assert_eq!(response, (StatusCode::OK, "maria".into()));
+        let response = request_with("/actor", &[("x-user-id", "maria")]).await;
+        assert_eq!(response, (StatusCode::OK, "none".into()));
+    }
+}
@@ -1,7 +1,7 @@
 use axum::Extension;
 use std::net::SocketAddr;
 
-use crate::mongo::client;
+use crate::mongo::client::Mongod;
 use {
     axum::{middleware, routing::IntoMakeService, Router, Server},
     hyper::server::conn::AddrIncoming,
@@ -22,14 +22,14 @@ use tower_request_id::{RequestId, RequestIdLayer};
 use tracing_wrapper::tracing::{self, Level};
 
 use crate::{
-    error::{AppError, Result},
-    handlers, metrics,
+    error::{self, AppError, Result},
+    handlers, metrics, tenant,
 };
 
 pub fn run(
-    listener: std::net::TcpListener, // db_con: &'static mongo::client::Mongod,
+    listener: std::net::TcpListener,
+    db_con: Mongod,
 ) -> Result<Server<AddrIncoming, IntoMakeService<Router>>> {
-    let db_con = client::Mongod::new()?;
     let router = handlers::routes();
     let app = router
         .layer(NewSentryLayer::new_from_top())
@@ -64,6 +64,9 @@ pub fn run(
                     },
                 ),
         )
+        .layer(middleware::from_fn(tenant::scope_actor))
+        .layer(middleware::from_fn(tenant::extract_tenant))
+        .layer(middleware::from_fn(error::scope_request_id))
         .layer(RequestIdLayer)
         .layer(Extension(db_con))
         .route_layer(middleware::from_fn(metrics::track_metrics));
@@ -89,6 +92,8 @@ pub fn start_metrics_server(addr: impl Into<SocketAddr>) -> Result<()> {
         metrics::REQUESTS_DURATION,
         "How long requests take to complete"
     );
+    // Describe database metrics
+    bongo_mong::metrics::describe();
 
     Ok(())
 }
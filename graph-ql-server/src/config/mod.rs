@@ -1,7 +1,10 @@
 mod builder;
 
 use crate::error::Result;
+use bongo_mong::config::secrets;
 use builder::ConfigBuilder;
+use config::{ConfigError, Map, Value, ValueKind};
+use std::fmt;
 use {once_cell::sync::Lazy, serde::Deserialize};
 
 pub static CONFIG: Lazy<Result<AppConfig>> = Lazy::new(AppConfig::read_config_for_env);
@@ -14,16 +17,186 @@ pub struct AppConfig {
     pub metrics_port: u16,
     pub collection_users: String,
     pub collection_languages: String,
-    pub users: MongoOpts,
-    pub languages: MongoOpts,
+    /// The pools of every collection, with a section per collection for its own options.
+    pub mongo: Option<MongoOpts>,
+    /// The pools of the users, in configurations from before the `mongo` section.
+    pub users: Option<MongoOpts>,
+    /// The pools of the languages, in configurations from before the `mongo` section.
+    pub languages: Option<MongoOpts>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+/// The section of the pool configuration with the pools of each app.
+const PER_APP: &str = "mongodbPerApp";
+
+/// The sections of the pool configuration with the default options of every collection.
+const PERMISSIONS: [&str; 2] = ["read", "write"];
+
+impl AppConfig {
+    /// The configuration of the pools shared by the collections.
+    ///
+    /// It is the `mongo` section, or else the `users` and `languages` sections of older
+    /// configurations merged together. The default `read` and `write` options of the
+    /// `languages` section are moved into its collection sections, so that they keep applying
+    /// to the languages only.
+    pub fn pools(&self) -> Result<Value> {
+        if let Some(mongo) = &self.mongo {
+            return Ok(mongo.bongo.clone());
+        }
+        let (users, languages) = match (&self.users, &self.languages) {
+            (Some(users), Some(languages)) => (users, languages),
+            (Some(opts), None) | (None, Some(opts)) => return Ok(opts.bongo.clone()),
+            (None, None) => return Err(ConfigError::NotFound("mongo".into()).into()),
+        };
+
+        let mut pools = users.bongo.clone().into_table()?;
+        let mut languages = languages.bongo.clone().into_table()?;
+        let defaults: Map<String, Value> = PERMISSIONS
+            .iter()
+            .filter_map(|permission| Some((permission.to_string(), languages.remove(*permission)?)))
+            .collect();
+        if !defaults.is_empty() {
+            languages
+                .entry(self.collection_languages.clone())
+                .or_insert_with(|| table(Map::new()));
+        }
+
+        for (key, section) in languages {
+            if key == PER_APP {
+                let mut apps = match pools.remove(PER_APP) {
+                    Some(apps) => apps.into_table()?,
+                    None => Map::new(),
+                };
+                for (api_key, collections) in section.into_table()? {
+                    let mut app = match apps.remove(&api_key) {
+                        Some(app) => app.into_table()?,
+                        None => Map::new(),
+                    };
+                    app.extend(collections.into_table()?);
+                    apps.insert(api_key, table(app));
+                }
+                pools.insert(key, table(apps));
+                continue;
+            }
+            let mut section = section.into_table()?;
+            for (permission, options) in &defaults {
+                let mut options = options.clone().into_table()?;
+                if let Some(own) = section.remove(permission) {
+                    options.extend(own.into_table()?);
+                }
+                section.insert(permission.clone(), table(options));
+            }
+            pools.insert(key, table(section));
+        }
+        Ok(table(pools))
+    }
+}
+
+fn table(map: Map<String, Value>) -> Value {
+    Value::new(None, ValueKind::Table(map))
+}
+
+#[derive(Deserialize, Clone)]
 pub struct MongoOpts {
     #[serde(flatten)]
     pub bongo: config::Value,
 }
 
+impl fmt::Debug for MongoOpts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MongoOpts")
+            .field("bongo", &secrets::redact(&self.bongo))
+            .finish()
+    }
+}
+
 impl<'de> ConfigBuilder<'de> for AppConfig {
     type Config = Self;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::{Config, File, FileFormat};
+
+    fn app_config(pools: &str) -> AppConfig {
+        let source = format!(
+            r#"{{
+                "port": 3000,
+                "metrics_port": 4000,
+                "collection_users": "users_graph",
+                "collection_languages": "languages",
+                {}
+            }}"#,
+            pools
+        );
+        Config::builder()
+            .add_source(File::from_str(&source, FileFormat::Json))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap()
+    }
+
+    fn get(value: &Value, path: &[&str]) -> Option<String> {
+        let (key, path) = path.split_first()?;
+        let value = value.clone().into_table().ok()?.remove(*key)?;
+        if path.is_empty() {
+            return value.into_string().ok();
+        }
+        get(&value, path)
+    }
+
+    #[test]
+    fn pools_of_the_mongo_section() {
+        let config = app_config(r#""mongo": {"read": {"baseUri": "mongodb://localhost/db"}}"#);
+        let pools = config.pools().unwrap();
+        assert_eq!(
+            get(&pools, &["read", "baseUri"]).as_deref(),
+            Some("mongodb://localhost/db")
+        );
+    }
+
+    #[test]
+    fn pools_of_the_users_and_languages_sections() {
+        let config = app_config(
+            r#""users": {
+                "read": {"baseUri": "mongodb://users/db", "maxPoolSize": "15"},
+                "users_graph": {"write": {"baseUri": "mongodb://users/db"}}
+            },
+            "languages": {
+                "read": {"baseUri": "mongodb://languages/db", "maxPoolSize": "20"},
+                "write": {"baseUri": "mongodb://languages/db"},
+                "mongodbPerApp": {"wat": {"languages": {"read": {"maxPoolSize": "5"}}}}
+            }"#,
+        );
+        let pools = config.pools().unwrap();
+        assert_eq!(
+            get(&pools, &["read", "baseUri"]).as_deref(),
+            Some("mongodb://users/db")
+        );
+        assert_eq!(get(&pools, &["write", "baseUri"]), None);
+        for permission in PERMISSIONS {
+            assert_eq!(
+                get(&pools, &["languages", permission, "baseUri"]).as_deref(),
+                Some("mongodb://languages/db")
+            );
+        }
+        assert_eq!(
+            get(&pools, &["languages", "read", "maxPoolSize"]).as_deref(),
+            Some("20")
+        );
+        assert_eq!(
+            get(
+                &pools,
+                &[PER_APP, "wat", "languages", "read", "maxPoolSize"]
+            )
+            .as_deref(),
+            Some("5")
+        );
+    }
+
+    #[test]
+    fn pools_need_a_section() {
+        assert!(app_config(r#""sentry_key": "key""#).pools().is_err());
+    }
+}
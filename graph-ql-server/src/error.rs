@@ -1,9 +1,15 @@
 use {
+    async_graphql::ErrorExtensions,
     axum::{
-        http::StatusCode,
+        http::{Request, StatusCode},
+        middleware::Next,
         response::{IntoResponse, Response},
     },
+    bongo_mong::error::BongoError,
+    mongodb::error::{ErrorKind, WriteFailure},
+    sentry_wrapper::{AlertType, ErrorReport, Level},
     serde_json::json,
+    tower_request_id::RequestId,
 };
 
 use std::num::TryFromIntError;
@@ -13,6 +19,11 @@ use crate::Json;
 /// Alias for `std::result::Result` with an error type [`AppError`].
 pub type Result<T> = std::result::Result<T, AppError>;
 
+tokio::task_local! {
+    /// The id of the request being handled, reported along with errors.
+    static REQUEST_ID: String;
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum AppError {
     #[error("Value not found")]
@@ -32,6 +43,9 @@ pub enum AppError {
     #[error("Error with id: {0}")]
     User(String),
 
+    #[error("User already exists with id: {0}")]
+    UserExists(String),
+
     #[error("Error Language collection in field: {0}")]
     Languages(String),
 
@@ -42,32 +56,142 @@ pub enum AppError {
     ContextData(String),
 
     #[error("Bongo error: {0}")]
-    Bongo(#[from] bongo_mong::error::BongoError),
+    Bongo(#[from] BongoError),
 
     #[error("Error {0}")]
     TryFrom(#[from] TryFromIntError),
+
+    #[error("Invalid api key")]
+    InvalidApiKey,
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        let (status, err_message) = match self {
-            Self::NotFound => (StatusCode::NOT_FOUND, "Resource not found"),
+impl AppError {
+    /// A machine-readable code identifying the kind of error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NotFound => "NOT_FOUND",
+            Self::User(_) => "USER_NOT_FOUND",
+            Self::UserExists(_) => "USER_EXISTS",
+            Self::Language(_) => "LANGUAGE_NOT_FOUND",
+            Self::InvalidApiKey => "INVALID_API_KEY",
+            Self::Mongo(_) | Self::Bongo(_) if self.is_database_unavailable() => {
+                "DATABASE_UNAVAILABLE"
+            }
+            Self::Mongo(_) | Self::Bongo(_) | Self::Languages(_) => "DATABASE_ERROR",
             Self::Config(_)
-            | Self::Bongo(_)
             | Self::TryFrom(_)
             | Self::ContextData(_)
-            | Self::Languages(_)
-            | Self::Language(_)
-            | Self::User(_)
-            | Self::Mongo(_)
             | Self::TcpBind
             | Self::Startup(_)
-            | Self::Prometheus(_) => {
-                unreachable!("This error can only occur during startup")
+            | Self::Prometheus(_) => "INTERNAL_ERROR",
+        }
+    }
+
+    /// The HTTP status the error is reported with.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::NotFound | Self::User(_) | Self::Language(_) => StatusCode::NOT_FOUND,
+            Self::UserExists(_) => StatusCode::CONFLICT,
+            Self::InvalidApiKey => StatusCode::UNAUTHORIZED,
+            _ if self.is_database_unavailable() => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// The message shown to clients.
+    fn public_message(&self) -> String {
+        let status = self.status();
+        // Do not leak internal details to the client
+        if status.is_server_error() {
+            status
+                .canonical_reason()
+                .unwrap_or("Internal error")
+                .to_string()
+        } else {
+            self.to_string()
+        }
+    }
+
+    /// Check if the error was caused by failing to reach the Mongo servers.
+    fn is_database_unavailable(&self) -> bool {
+        match self {
+            Self::Mongo(err) | Self::Bongo(BongoError::MongoDbError(err)) => {
+                is_connectivity_error(err)
             }
-        };
+            _ => false,
+        }
+    }
+}
 
-        let body = Json(json!({ "error": err_message }));
+/// Check if the driver error is a network or server selection failure.
+fn is_connectivity_error(err: &mongodb::error::Error) -> bool {
+    matches!(
+        *err.kind,
+        ErrorKind::Io(_)
+            | ErrorKind::ServerSelection { .. }
+            | ErrorKind::ConnectionPoolCleared { .. }
+            | ErrorKind::DnsResolve { .. }
+    )
+}
+
+/// Check if the driver error is a violation of a unique index.
+pub fn is_duplicate_key_error(err: &mongodb::error::Error) -> bool {
+    const DUPLICATE_KEY: i32 = 11000;
+
+    match &*err.kind {
+        ErrorKind::Write(WriteFailure::WriteError(err)) => err.code == DUPLICATE_KEY,
+        ErrorKind::Command(err) => err.code == DUPLICATE_KEY,
+        _ => false,
+    }
+}
+
+/// A middleware that runs the rest of the request within the scope of its id,
+/// so that errors turned into responses can report it.
+pub async fn scope_request_id<B>(req: Request<B>, next: Next<B>) -> impl IntoResponse {
+    let request_id = req
+        .extensions()
+        .get::<RequestId>()
+        .map(ToString::to_string)
+        .unwrap_or_else(|| "unknown".into());
+
+    REQUEST_ID.scope(request_id, next.run(req)).await
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let code = self.code();
+        let request_id = REQUEST_ID
+            .try_with(Clone::clone)
+            .unwrap_or_else(|_| "unknown".into());
+
+        let report = ErrorReport::new(&self)
+            .add_tag("code", code)
+            .add_tag("request_id", &request_id);
+        if status.is_server_error() {
+            report
+                .set_level(Some(Level::Error))
+                .set_alert(AlertType::Medium)
+                .send();
+        } else {
+            report.send();
+        }
+
+        let body = Json(json!({
+            "error": self.public_message(),
+            "code": code,
+            "status": status.as_u16(),
+            "requestId": request_id,
+        }));
         (status, body).into_response()
     }
 }
+
+impl ErrorExtensions for AppError {
+    /// The GraphQL error, with the `code` of the error as an extension.
+    fn extend(&self) -> async_graphql::Error {
+        async_graphql::Error::new(self.public_message()).extend_with(|_, extensions| {
+            extensions.set("code", self.code());
+        })
+    }
+}
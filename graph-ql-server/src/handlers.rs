@@ -1,8 +1,10 @@
 use async_graphql::{
-    http::{playground_source, GraphQLPlaygroundConfig},
-    Request, Response,
+    http::{playground_source, GraphQLPlaygroundConfig, ALL_WEBSOCKET_PROTOCOLS},
+    Data, Request, Response,
 };
+use async_graphql_axum::{GraphQLProtocol, GraphQLWebSocket};
 use axum::{
+    extract::ws::WebSocketUpgrade,
     http::StatusCode,
     response::{Html, IntoResponse},
     routing::get,
@@ -10,9 +12,11 @@ use axum::{
 };
 
 // use crate::user_schema;
-use crate::user_schema::{Mutation, QueryRoot};
+use crate::{
+    tenant::{Actor, Tenant},
+    user_schema::UserSchema,
+};
 
-use async_graphql::{EmptySubscription, Schema};
 pub fn routes() -> Router {
     Router::new()
         .route("/health-check", get(health_check))
@@ -20,6 +24,7 @@ pub fn routes() -> Router {
             "/api/graphql",
             get(graphql_playground).post(graphql_handler),
         )
+        .route("/api/graphql/ws", get(graphql_ws_handler))
 }
 
 #[utoipa::path(
@@ -33,14 +38,43 @@ pub async fn health_check() -> impl IntoResponse {
     (StatusCode::OK, "Service is healthy")
 }
 
+/// Execute a query or mutation, recording the writes it makes as made by the actor.
 pub async fn graphql_handler(
-    Extension(schema): Extension<Schema<QueryRoot, Mutation, EmptySubscription>>,
+    Extension(schema): Extension<UserSchema>,
+    Extension(tenant): Extension<Tenant>,
+    Extension(actor): Extension<Actor>,
     Json(request): Json<Request>,
 ) -> Json<Response> {
-    schema.execute(request).await.into()
+    actor
+        .scope(schema.execute(request.data(tenant)))
+        .await
+        .into()
+}
+
+/// Serve subscriptions over the `graphql-ws` and `graphql-transport-ws` protocols.
+///
+/// The connection outlives the request, so it is served in the scope of the actor again.
+pub async fn graphql_ws_handler(
+    Extension(schema): Extension<UserSchema>,
+    Extension(tenant): Extension<Tenant>,
+    Extension(actor): Extension<Actor>,
+    protocol: GraphQLProtocol,
+    websocket: WebSocketUpgrade,
+) -> impl IntoResponse {
+    let mut data = Data::default();
+    data.insert(tenant);
+    websocket
+        .protocols(ALL_WEBSOCKET_PROTOCOLS)
+        .on_upgrade(move |stream| async move {
+            let connection = GraphQLWebSocket::new(stream, schema, protocol)
+                .with_data(data)
+                .serve();
+            actor.scope(connection).await
+        })
 }
+
 pub async fn graphql_playground() -> impl IntoResponse {
-    Html(playground_source(GraphQLPlaygroundConfig::new(
-        "/api/graphql",
-    )))
+    Html(playground_source(
+        GraphQLPlaygroundConfig::new("/api/graphql").subscription_endpoint("/api/graphql/ws"),
+    ))
 }
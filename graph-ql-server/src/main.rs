@@ -4,13 +4,13 @@ use once_cell::sync::Lazy;
 use sentry_wrapper::sentry;
 use tracing_wrapper::tracing;
 
-use async_graphql::{EmptySubscription, Schema};
+use async_graphql::{dataloader::DataLoader, Schema};
 use graph_ql_server::{
     error::{AppError, Result},
     metrics,
     mongo::client::{self},
     updown::{shutdown, startup},
-    user_schema::{Mutation, QueryRoot},
+    user_schema::{Mutation, QueryRoot, Subscription, UsersByLanguage},
     CONFIG,
 };
 
@@ -47,15 +47,20 @@ async fn main() -> Result<()> {
     let listener = TcpListener::bind(&addr).or(Err(AppError::TcpBind))?;
     //
     //connection with database
-    let db_con = client::Mongod::new()?;
+    let db_con = client::Mongod::new(config)?;
+    db_con.ensure_indexes().await?;
+    db_con.spawn_health_monitor();
 
-    let schema = Schema::build(QueryRoot, Mutation, EmptySubscription)
-        .data(db_con)
+    let users_by_language = DataLoader::new(UsersByLanguage::new(db_con.clone()), tokio::spawn);
+
+    let schema = Schema::build(QueryRoot, Mutation, Subscription)
+        .data(db_con.clone())
+        .data(users_by_language)
         .finish();
 
     //build schema
 
-    startup::run(listener, schema)?
+    startup::run(listener, schema, db_con)?
         .with_graceful_shutdown(shutdown::signal())
         .await
         .map_err(|e| AppError::Startup(e.to_string()))?;
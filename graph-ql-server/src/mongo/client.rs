@@ -1,93 +1,157 @@
+use std::collections::HashMap;
 use std::convert::TryFrom;
 
-use crate::{AppError, CONFIG};
+use crate::{config::AppConfig, error::is_duplicate_key_error, AppError};
 
 use super::languages::*;
 use super::users_graph::*;
 use async_graphql::futures_util::TryStreamExt;
-use bongo_mong::dao::Query;
-use bongo_mong::PoolManager;
-use config::ConfigError;
-use mongodb::bson::doc;
-use once_cell::sync::Lazy;
+use bongo_mong::dao::{DbConnect, Query, DELETED_AT};
+use bongo_mong::error::BongoError;
+use bongo_mong::query::{Filter, Update};
+use bongo_mong::{health::HealthCheck, PoolManager};
+use mongodb::bson::Document;
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument};
+use tokio::sync::broadcast;
+use tracing_wrapper::tracing;
+
+/// How many user events are buffered for slow subscribers before they lag.
+const USER_EVENTS_CAPACITY: usize = 100;
 
 #[derive(Clone)]
-pub struct Mongod<'a> {
-    pub collection_users: UserGraphs<'a>,
-    pub collection_languages: Languages<'a>,
+pub struct Mongod {
+    pub collection_users: UserGraphs,
+    pub collection_languages: Languages,
+    user_events: broadcast::Sender<TenantUserEvent>,
 }
 
-static POOLS_USERS: Lazy<Result<PoolManager, AppError>> = Lazy::new(|| {
-    let config_1 = Lazy::force(&CONFIG)
-        .as_ref()
-        .map_err(|e| ConfigError::Message(e.to_string()))?;
-
-    Ok(PoolManager::try_from(config_1.users.bongo.clone())?)
-});
-
-static POOLS_LANGUAGES: Lazy<Result<PoolManager, AppError>> = Lazy::new(|| {
-    let config_1 = Lazy::force(&CONFIG)
-        .as_ref()
-        .map_err(|e| ConfigError::Message(e.to_string()))?;
-
-    Ok(PoolManager::try_from(config_1.languages.bongo.clone())?)
-});
-impl<'a> Mongod<'a> {
-
+/// A user event, along with the api key of the tenant whose user changed.
+pub type TenantUserEvent = (Option<String>, UserEvent);
+
+impl Mongod {
+    /// Connect to the collections with the pools of the given configuration.
+    ///
+    /// The collections share a pool manager, so that those on the same cluster share a client,
+    /// and a session started on one of them can take part in operations on the other. The pool
+    /// configuration is validated first, reporting every problem at once.
+    pub fn new(config: &AppConfig) -> Result<Self, AppError> {
+        let pool_manager = PoolManager::try_from(config.pools()?)?;
+        pool_manager.validate()?;
+
+        Ok(Self::with_collections(
+            UserGraphs::with_name(config.collection_users.as_str(), pool_manager.clone()),
+            Languages::with_name(config.collection_languages.as_str(), pool_manager),
+        ))
+    }
 
-    pub fn new() -> Result<Self, AppError> {
-        let config_1 = Lazy::force(&CONFIG)
-            .as_ref()
-            .map_err(|e| ConfigError::Message(e.to_string()))?;
-        let pool_manager_users = Lazy::force(&POOLS_USERS)
-            .as_ref()
-            .map_err(|err| ConfigError::Message(err.to_string()))?;
+    /// Use the given collections, e.g. ones backed by pool managers built in a test.
+    pub fn with_collections(collection_users: UserGraphs, collection_languages: Languages) -> Self {
+        Self {
+            collection_users,
+            collection_languages,
+            user_events: broadcast::channel(USER_EVENTS_CAPACITY).0,
+        }
+    }
 
-        let pool_manager_languages = Lazy::force(&POOLS_LANGUAGES)
-            .as_ref()
-            .map_err(|err| ConfigError::Message(err.to_string()))?;
+    /// Subscribe to the changes made to the users of every tenant from now on.
+    pub fn subscribe_user_events(&self) -> broadcast::Receiver<TenantUserEvent> {
+        self.user_events.subscribe()
+    }
 
-        Ok(Self {
-            collection_users: UserGraphs::new(
-                config_1.collection_users.as_str(),
-                pool_manager_users,
-            ),
-            collection_languages: Languages::new(
-                config_1.collection_languages.as_str(),
-                pool_manager_languages,
-            ),
-        })
+    fn publish_user_event(&self, event: UserEvent, api_key: Option<&str>) {
+        // Sending fails only when nobody is subscribed
+        let _ = self
+            .user_events
+            .send((api_key.map(ToString::to_string), event));
     }
 
     //User
-    pub async fn get_users_from_base(&self) -> Result<Vec<UserGraph>, AppError> {
+    pub async fn get_users_from_base(
+        &self,
+        filter: Document,
+        options: FindOptions,
+        api_key: Option<&str>,
+    ) -> Result<Vec<UserGraph>, AppError> {
         Ok(self
             .collection_users
-            .find(None, None, None)
+            .find(filter, options, api_key)
             .await?
             .try_collect()
             .await?)
     }
 
-    pub async fn find_user_in_base(&self, id: String) -> Result<Option<UserGraph>, AppError> {
+    pub async fn count_users_in_base(
+        &self,
+        filter: Document,
+        api_key: Option<&str>,
+    ) -> Result<usize, AppError> {
+        let count = self
+            .collection_users
+            .count_documents(filter, None, api_key)
+            .await?;
+        Ok(usize::try_from(count)?)
+    }
+
+    pub async fn find_user_in_base(
+        &self,
+        id: String,
+        api_key: Option<&str>,
+    ) -> Result<Option<UserGraph>, AppError> {
         Ok(self
             .collection_users
-            .find_one(
-                doc! {
-                    "id": id
-                },
-                None,
-                None,
-            )
+            .find_one(UserGraph::ID.eq(id), None, api_key)
             .await?)
     }
 
+    /// Check if the pool configuration has an app section for the api key.
+    pub fn has_app(&self, api_key: &str) -> bool {
+        self.collection_users.pool_manager().has_app(api_key)
+    }
+
+    /// Create the indexes the collections declare, warning about the ones that differ.
+    ///
+    /// The unique index on the user `id` is what rejects duplicate users.
+    pub async fn ensure_indexes(&self) -> Result<(), AppError> {
+        let mut reports = self
+            .collection_users
+            .pool_manager()
+            .ensure_indexes(&[&self.collection_users])
+            .await?;
+        reports.extend(
+            self.collection_languages
+                .pool_manager()
+                .ensure_indexes(&[&self.collection_languages])
+                .await?,
+        );
+
+        for report in reports.iter().filter(|report| report.has_drift()) {
+            tracing::warn!(
+                collection = %report.collection,
+                api_key = ?report.api_key,
+                drifted = ?report.drifted,
+                undeclared = ?report.undeclared,
+                "indexes differ from the declared ones"
+            );
+        }
+        Ok(())
+    }
+
+    /// Start checking the health of the pools in the background.
+    ///
+    /// The collections share a pool manager, see [`Mongod::new`].
+    pub fn spawn_health_monitor(&self) {
+        self.collection_users
+            .pool_manager()
+            .spawn_health_monitor(HealthCheck::default());
+    }
+
     pub async fn add_user_in_base(
         &self,
         id: String,
         name: String,
         age: u8,
         language_id: String,
+        api_key: Option<&str>,
     ) -> Result<UserGraph, AppError> {
         let new_user = UserGraph {
             id: id.to_string(),
@@ -96,132 +160,410 @@ impl<'a> Mongod<'a> {
             language_id,
         };
 
-        let user = self
+        match self
             .collection_users
-            .find_one(doc! {"id": id}, None, None)
-            .await?;
-
-        match user {
-            Some(user_in) => Err(AppError::User(user_in.id)),
-            None => {
-                self.collection_users
-                    .insert_one(new_user.clone(), None, None)
-                    .await?;
-                Ok(new_user)
+            .insert_one(&new_user, None, api_key)
+            .await
+        {
+            Ok(_) => {}
+            Err(BongoError::MongoDbError(err)) if is_duplicate_key_error(&err) => {
+                self.replace_deleted_user(&new_user, api_key).await?
             }
+            Err(err) => return Err(err.into()),
         }
+
+        self.publish_user_event(UserEvent::Added(new_user.clone()), api_key);
+        Ok(new_user)
     }
 
-    pub async fn delete_user_from_base(&self, id: String) -> Result<Option<UserGraph>, AppError> {
-        let user = self
+    /// Add a user in place of a deleted one with the same id.
+    ///
+    /// Deleted users are kept, so their id is still taken in the unique index. The deleted
+    /// user is replaced in a single write, which restores it with the new data.
+    async fn replace_deleted_user(
+        &self,
+        user: &UserGraph,
+        api_key: Option<&str>,
+    ) -> Result<(), AppError> {
+        let filter = UserGraph::ID
+            .eq(user.id.as_str())
+            .and(Filter::exists(DELETED_AT, true));
+        let replaced = self
             .collection_users
-            .find_one(doc! {"id": id.as_str()}, None, None)
+            .replace_one_including_deleted(filter.into(), user, None, api_key)
             .await?;
+        if replaced.matched_count == 0 {
+            return Err(AppError::UserExists(user.id.clone()));
+        }
+        Ok(())
+    }
 
-        self.collection_users
-            .delete_one(doc! {"id": id.as_str()}, None, None)
+    pub async fn delete_user_from_base(
+        &self,
+        id: String,
+        api_key: Option<&str>,
+    ) -> Result<Option<UserGraph>, AppError> {
+        let user = self
+            .collection_users
+            .find_one_and_delete(UserGraph::ID.eq(id.as_str()).into(), None, api_key)
             .await?;
 
+        if let Some(user) = &user {
+            self.publish_user_event(UserEvent::Deleted(user.clone()), api_key);
+        }
         Ok(user)
     }
 
+    /// Update the given fields of a user, returning the user as stored after the update.
     pub async fn update_user_in_base(
         &self,
         id: String,
         name: Option<String>,
         age: Option<u8>,
         language_id: Option<String>,
+        api_key: Option<&str>,
     ) -> Result<Option<UserGraph>, AppError> {
-        let user = self
-            .collection_users
-            .find_one(doc! {"id": id.as_str()}, None, None)
-            .await?
-            .ok_or_else(|| AppError::User(id.to_string()))?;
-
-        let name_old = user.name;
-
-        let name_new = name.unwrap_or_else(|| name_old.to_string());
-
-        let age_old = user.age;
-
-        let age_new = age.unwrap_or(age_old);
-
-        let language_old = user.language_id;
-
-        let language_new = language_id.unwrap_or_else(|| language_old.to_string());
-
-        let user_new = UserGraph {
-            id: id.to_string(),
-            name: name_new.to_string(),
-            age: age_new,
-            language_id: language_new.to_string(),
-        };
+        let mut update = Update::default();
+        if let Some(name) = name {
+            update = update.and(UserGraph::NAME.set(name))?;
+        }
+        if let Some(age) = age {
+            update = update.and(UserGraph::AGE.set(age))?;
+        }
+        if let Some(language_id) = language_id {
+            update = update.and(UserGraph::LANGUAGE_ID.set(language_id))?;
+        }
 
-        //impl from docu
-        let _update_res =
-                self.collection_users
-                .update_one(
-                    doc!{ "id": id.as_str()},
-                    doc! {
-                        "$set": {"name": name_new.as_str(), "age": age_new as i32, "language_id": language_new.as_str()} 
-                    },
-                    None,
-                    None
+        let user = if update.is_empty() {
+            self.find_user_in_base(id.to_string(), api_key).await?
+        } else {
+            let options = FindOneAndUpdateOptions::builder()
+                .return_document(ReturnDocument::After)
+                .build();
+            self.collection_users
+                .find_one_and_update(
+                    UserGraph::ID.eq(id.as_str()).into(),
+                    update,
+                    options,
+                    api_key,
                 )
-            .await?;
+                .await?
+        };
+        let user = user.ok_or(AppError::User(id))?;
 
-        Ok(Some(user_new))
+        self.publish_user_event(UserEvent::Updated(user.clone()), api_key);
+        Ok(Some(user))
     }
 
     pub async fn find_lang_for_use_in_base(
         &self,
         language_id: String,
+        api_key: Option<&str>,
     ) -> Result<Vec<UserGraph>, AppError> {
         Ok(self
             .collection_users
-            .find(doc! {"language_id": language_id}, None, None)
+            .find(UserGraph::LANGUAGE_ID.eq(language_id), None, api_key)
             .await?
             .try_collect()
             .await?)
     }
 
-    //Language
-    pub async fn get_languages_from_base(&self) -> Result<Vec<Language>, AppError> {
-        let mut lang = self.collection_languages.find(None, None, None).await?;
-        let mut vec = Vec::new();
-        while let Some(language) = lang.try_next().await? {
-            let id = language.id;
-            let users = self.find_lang_for_use_in_base(id.to_string()).await?;
-
-            let language_new = Language {
-                id,
-                name: language.name,
-                users,
-            };
-            vec.push(language_new);
+    /// Find the users of every given language with a single query.
+    ///
+    /// Languages without users are mapped to an empty list.
+    pub async fn find_users_for_languages_in_base(
+        &self,
+        language_ids: &[String],
+        api_key: Option<&str>,
+    ) -> Result<HashMap<String, Vec<UserGraph>>, AppError> {
+        let mut users: HashMap<String, Vec<UserGraph>> = language_ids
+            .iter()
+            .map(|id| (id.to_string(), Vec::new()))
+            .collect();
+
+        let mut cursor = self
+            .collection_users
+            .find(UserGraph::LANGUAGE_ID.is_in(language_ids), None, api_key)
+            .await?;
+        while let Some(user) = cursor.try_next().await? {
+            users
+                .entry(user.language_id.to_string())
+                .or_default()
+                .push(user);
         }
-        Ok(vec)
+        Ok(users)
     }
 
-    pub async fn find_language_in_base(&self, id: String) -> Result<Option<Language>, AppError> {
-        let lang = self
+    //Language
+    pub async fn get_languages_from_base(
+        &self,
+        filter: Document,
+        options: FindOptions,
+        api_key: Option<&str>,
+    ) -> Result<Vec<Language>, AppError> {
+        Ok(self
             .collection_languages
-            .find_one(doc! {"id": id.as_str()}, None, None)
+            .find(filter, options, api_key)
             .await?
-            .ok_or_else(|| AppError::User(id))?;
+            .try_collect()
+            .await?)
+    }
 
-        let id_ = lang.id;
+    pub async fn count_languages_in_base(
+        &self,
+        filter: Document,
+        api_key: Option<&str>,
+    ) -> Result<usize, AppError> {
+        let count = self
+            .collection_languages
+            .count_documents(filter, None, api_key)
+            .await?;
+        Ok(usize::try_from(count)?)
+    }
 
-        let name_ = lang.name;
+    pub async fn find_language_in_base(
+        &self,
+        id: String,
+        api_key: Option<&str>,
+    ) -> Result<Option<Language>, AppError> {
+        Ok(self
+            .collection_languages
+            .find_one(Language::ID.eq(id.as_str()), None, api_key)
+            .await?)
+    }
+}
 
-        let users = self.find_lang_for_use_in_base(id_.clone()).await?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bongo_mong::testing::MemoryStore;
+    use config::{Config, File, FileFormat};
+    use mongodb::bson::doc;
+
+    fn mongod(store: &MemoryStore) -> Mongod {
+        let pool_manager =
+            PoolManager::with_memory_store(config::Config::default(), store.clone()).unwrap();
+        Mongod::with_collections(
+            UserGraphs::new(pool_manager.clone()),
+            Languages::new(pool_manager),
+        )
+    }
 
-        let language = Language {
-            id: id_,
-            name: name_.to_string(),
-            users,
-        };
+    async fn add(mongod: &Mongod, id: &str, language_id: &str) -> UserGraph {
+        mongod
+            .add_user_in_base(
+                id.into(),
+                format!("user {}", id),
+                30,
+                language_id.into(),
+                None,
+            )
+            .await
+            .unwrap()
+    }
+
+    fn from_json<T: serde::de::DeserializeOwned>(source: &str) -> T {
+        Config::builder()
+            .add_source(File::from_str(source, FileFormat::Json))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap()
+    }
+
+    #[test]
+    fn new_validates_the_pool_config() {
+        let config: AppConfig = from_json(include_str!("../../configs/local.json"));
+        assert!(Mongod::new(&config).is_ok());
+
+        let mut config = config;
+        config.mongo =
+            from_json(r#"{"read": {"baseUri": "mongodb://localhost/db", "maxPoolSize": "lots"}}"#);
+        assert!(matches!(
+            Mongod::new(&config),
+            Err(AppError::Bongo(BongoError::InvalidConfig(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn adds_and_finds_users() {
+        let mongod = mongod(&MemoryStore::new());
+        let mut events = mongod.subscribe_user_events();
+        add(&mongod, "1", "en").await;
+        add(&mongod, "2", "fr").await;
+        add(&mongod, "3", "en").await;
+
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            (None, UserEvent::Added(user)) if user.id == "1"
+        ));
+        let user = mongod.find_user_in_base("2".into(), None).await.unwrap();
+        assert_eq!(user.map(|user| user.language_id), Some("fr".into()));
+
+        let options = FindOptions::builder()
+            .sort(doc! {"id": -1})
+            .limit(2)
+            .build();
+        let users = mongod
+            .get_users_from_base(Document::new(), options, None)
+            .await
+            .unwrap();
+        let ids: Vec<_> = users.iter().map(|user| user.id.as_str()).collect();
+        assert_eq!(ids, ["3", "2"]);
+        let count = mongod
+            .count_users_in_base(UserGraph::LANGUAGE_ID.eq("en").into(), None)
+            .await
+            .unwrap();
+        assert_eq!(count, 2);
+    }
+
+    #[tokio::test]
+    async fn rejects_existing_users() {
+        let mongod = mongod(&MemoryStore::new());
+        mongod.ensure_indexes().await.unwrap();
+        add(&mongod, "1", "en").await;
 
-        Ok(Some(language))
+        let added = mongod
+            .add_user_in_base("1".into(), "other".into(), 40, "fr".into(), None)
+            .await;
+        assert!(matches!(added, Err(AppError::UserExists(id)) if id == "1"));
+    }
+
+    #[tokio::test]
+    async fn updates_users() {
+        let mongod = mongod(&MemoryStore::new());
+        add(&mongod, "1", "en").await;
+
+        let user = mongod
+            .update_user_in_base("1".into(), None, Some(41), None, None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((user.name.as_str(), user.age), ("user 1", 41));
+        assert!(matches!(
+            mongod
+                .update_user_in_base("2".into(), None, Some(41), None, None)
+                .await,
+            Err(AppError::User(id)) if id == "2"
+        ));
+    }
+
+    #[tokio::test]
+    async fn deletes_users_softly() {
+        let store = MemoryStore::new();
+        let mongod = mongod(&store);
+        add(&mongod, "1", "en").await;
+
+        let user = mongod
+            .delete_user_from_base("1".into(), None)
+            .await
+            .unwrap();
+        assert_eq!(user.map(|user| user.id), Some("1".into()));
+        let stored = store.documents(None, "users_graph");
+        assert!(stored[0].contains_key("deletedAt"));
+        let operations: Vec<_> = store
+            .documents(None, "audit")
+            .iter()
+            .map(|record| record.get_str("operation").unwrap().to_string())
+            .collect();
+        assert_eq!(operations, ["insert_one", "find_one_and_delete"]);
+
+        assert!(mongod
+            .find_user_in_base("1".into(), None)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(mongod
+            .delete_user_from_base("1".into(), None)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn readds_deleted_users() {
+        let store = MemoryStore::new();
+        let mongod = mongod(&store);
+        mongod.ensure_indexes().await.unwrap();
+        add(&mongod, "1", "en").await;
+        mongod
+            .delete_user_from_base("1".into(), None)
+            .await
+            .unwrap();
+
+        mongod
+            .add_user_in_base("1".into(), "other".into(), 40, "fr".into(), None)
+            .await
+            .unwrap();
+        let user = mongod
+            .find_user_in_base("1".into(), None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((user.name.as_str(), user.age), ("other", 40));
+        let stored = store.documents(None, "users_graph");
+        assert_eq!(stored.len(), 1);
+        assert!(!stored[0].contains_key("deletedAt"));
+        let operations: Vec<_> = store
+            .documents(None, "audit")
+            .iter()
+            .map(|record| record.get_str("operation").unwrap().to_string())
+            .collect();
+        assert_eq!(
+            operations,
+            [
+                "insert_one",
+                "find_one_and_delete",
+                "replace_one_including_deleted"
+            ]
+        );
+
+        let added = mongod
+            .add_user_in_base("1".into(), "again".into(), 50, "fr".into(), None)
+            .await;
+        assert!(matches!(added, Err(AppError::UserExists(id)) if id == "1"));
+    }
+
+    #[tokio::test]
+    async fn finds_users_of_languages() {
+        let mongod = mongod(&MemoryStore::new());
+        add(&mongod, "1", "en").await;
+        add(&mongod, "2", "fr").await;
+        add(&mongod, "3", "en").await;
+        mongod
+            .collection_languages
+            .insert_one(
+                Language {
+                    id: "en".into(),
+                    name: "English".into(),
+                },
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let language = mongod
+            .find_language_in_base("en".into(), None)
+            .await
+            .unwrap();
+        assert_eq!(
+            language.map(|language| language.name),
+            Some("English".into())
+        );
+        let users = mongod
+            .find_users_for_languages_in_base(&["en".into(), "de".into()], None)
+            .await
+            .unwrap();
+        assert_eq!(users["en"].len(), 2);
+        assert!(users["de"].is_empty());
+        assert_eq!(
+            mongod
+                .find_lang_for_use_in_base("fr".into(), None)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
     }
 }
@@ -1,11 +1,12 @@
 //! The interface for the "UserGraphs" collection.
-use bongo_mong::dao;
+use bongo_mong::dao::Collection;
+use bongo_mong::query::Fields;
 use bongo_mong::PoolManager;
 
 use async_graphql::SimpleObject;
 use serde::{Deserialize, Serialize};
 
-#[derive(SimpleObject, Clone, Debug, Deserialize, Serialize)]
+#[derive(SimpleObject, Clone, Debug, Deserialize, Serialize, Fields)]
 pub struct UserGraph {
     pub id: String,
     pub name: String,
@@ -13,31 +14,19 @@ pub struct UserGraph {
     pub language_id: String,
 }
 
+/// A change to a user, published after it is stored.
 #[derive(Clone, Debug)]
-pub struct UserGraphs<'a> {
-    name: String,
-    pool_manager: &'a PoolManager,
-}
-
-impl<'a> UserGraphs<'a> {
-    pub fn new(name: &'a str, pool_manager: &'a PoolManager) -> Self {
-        Self {
-            name: name.into(),
-            pool_manager,
-        }
-    }
+pub enum UserEvent {
+    Added(UserGraph),
+    Updated(UserGraph),
+    Deleted(UserGraph),
 }
 
-impl<'a> dao::Collection for UserGraphs<'a> {
-    fn name(&self) -> &str {
-        &self.name
-    }
-}
-
-impl<'a> dao::DbConnect for UserGraphs<'a> {
-    fn pool_manager(&self) -> &PoolManager {
-        self.pool_manager
-    }
+/// The users, kept when deleted so that they can be restored, with their changes recorded.
+#[derive(Clone, Debug, Collection)]
+#[bongo(name = "users_graph", model = UserGraph, soft_delete, audit = "audit")]
+#[bongo(index(keys(id = 1), unique))]
+pub struct UserGraphs {
+    name: String,
+    pool_manager: PoolManager,
 }
-
-impl<'a> dao::Query<UserGraph> for UserGraphs<'a> {}
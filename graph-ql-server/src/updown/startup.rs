@@ -1,32 +1,37 @@
 use std::net::SocketAddr;
 
-use crate::user_schema;
-
+use crate::user_schema::UserSchema;
 use crate::{
-    error::{AppError, Result},
+    error::{self, AppError, Result},
     handlers, metrics,
+    mongo::client::Mongod,
+    tenant,
 };
-use user_schema::{Mutation, QueryRoot};
 use {
     axum::{middleware, routing::IntoMakeService, Extension, Router, Server},
     hyper::server::conn::AddrIncoming,
     metrics_exporter_prometheus::PrometheusBuilder,
     sentry_wrapper::{NewSentryLayer, SentryHttpLayer},
     tower_http::trace::TraceLayer,
+    tower_request_id::RequestIdLayer,
 };
 
-use async_graphql::{EmptySubscription, Schema};
-
 pub fn run(
     listener: std::net::TcpListener,
-    schema: Schema<QueryRoot, Mutation, EmptySubscription>,
+    schema: UserSchema,
+    db_con: Mongod,
 ) -> Result<Server<AddrIncoming, IntoMakeService<Router>>> {
     let router = handlers::routes();
     let app = router
         .layer(NewSentryLayer::new_from_top())
         .layer(SentryHttpLayer::new())
         .layer(TraceLayer::new_for_http())
+        .layer(middleware::from_fn(tenant::scope_actor))
+        .layer(middleware::from_fn(tenant::extract_tenant))
+        .layer(middleware::from_fn(error::scope_request_id))
+        .layer(RequestIdLayer)
         .layer(Extension(schema))
+        .layer(Extension(db_con))
         .route_layer(middleware::from_fn(metrics::track_metrics));
 
     Ok(axum::Server::from_tcp(listener)
@@ -50,6 +55,8 @@ pub fn start_metrics_server(addr: impl Into<SocketAddr>) -> Result<()> {
         metrics::REQUESTS_DURATION,
         "How long requests take to complete"
     );
+    // Describe database metrics
+    bongo_mong::metrics::describe();
 
     Ok(())
 }
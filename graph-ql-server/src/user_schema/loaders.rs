@@ -0,0 +1,67 @@
+//! Data loaders batching the lookups of nested fields.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_graphql::dataloader::Loader;
+use async_trait::async_trait;
+
+use crate::{
+    mongo::{client::Mongod, users_graph::UserGraph},
+    AppError,
+};
+
+/// The id of a language, along with the api key of the tenant it is loaded for.
+///
+/// The loader is shared by every request, so keys of different tenants must not collide.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct LanguageKey {
+    pub api_key: Option<String>,
+    pub language_id: String,
+}
+
+/// Load the users of many languages with a single `$in` query per tenant.
+pub struct UsersByLanguage {
+    mongod: Mongod,
+}
+
+impl UsersByLanguage {
+    pub fn new(mongod: Mongod) -> Self {
+        Self { mongod }
+    }
+}
+
+#[async_trait]
+impl Loader<LanguageKey> for UsersByLanguage {
+    type Value = Vec<UserGraph>;
+    type Error = Arc<AppError>;
+
+    async fn load(
+        &self,
+        keys: &[LanguageKey],
+    ) -> Result<HashMap<LanguageKey, Self::Value>, Self::Error> {
+        let mut by_tenant: HashMap<Option<&str>, Vec<String>> = HashMap::new();
+        for key in keys {
+            by_tenant
+                .entry(key.api_key.as_deref())
+                .or_default()
+                .push(key.language_id.clone());
+        }
+
+        let mut users = HashMap::with_capacity(keys.len());
+        for (api_key, language_ids) in by_tenant {
+            let found = self
+                .mongod
+                .find_users_for_languages_in_base(&language_ids, api_key)
+                .await
+                .map_err(Arc::new)?;
+            users.extend(found.into_iter().map(|(language_id, users)| {
+                let key = LanguageKey {
+                    api_key: api_key.map(ToString::to_string),
+                    language_id,
+                };
+                (key, users)
+            }));
+        }
+        Ok(users)
+    }
+}
@@ -1,2 +1,10 @@
+mod loaders;
 mod model;
-pub use model::{Mutation, QueryRoot};
+mod pagination;
+pub use loaders::{LanguageKey, UsersByLanguage};
+pub use model::{Mutation, QueryRoot, Subscription};
+
+use async_graphql::Schema;
+
+/// The schema served by the application.
+pub type UserSchema = Schema<QueryRoot, Mutation, Subscription>;
@@ -1,9 +1,25 @@
 // use super::{ UserGraph};
 use crate::{
-    mongo::{client::Mongod, languages::Language, users_graph::UserGraph},
+    mongo::{
+        client::Mongod,
+        languages::Language,
+        users_graph::{UserEvent, UserGraph},
+    },
+    tenant::Tenant,
     AppError,
 };
-use async_graphql::{Context, Object};
+use async_graphql::{
+    connection::{query, Connection},
+    dataloader::DataLoader,
+    futures_util::{stream, Stream, StreamExt},
+    ComplexObject, Context, ErrorExtensions, Object, Subscription,
+};
+use tokio::sync::broadcast::error::RecvError;
+
+use super::loaders::{LanguageKey, UsersByLanguage};
+use super::pagination::{
+    KeyCursor, LanguageFilter, LanguageOrderBy, Page, UserFilter, UserOrderBy,
+};
 
 pub struct QueryRoot;
 
@@ -11,50 +27,119 @@ pub struct QueryRoot;
 impl QueryRoot {
     async fn user(&self, ctx: &Context<'_>, id: String) -> Result<Option<UserGraph>, AppError> {
         match ctx.data::<Mongod>() {
-            Ok(it) => it.find_user_in_base(id).await,
+            Ok(it) => it.find_user_in_base(id, api_key(ctx)).await,
             Err(_err) => Err(AppError::ContextData(
                 "in data context with Mongod".to_string(),
             )),
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn users(
         &self,
         ctx: &Context<'_>,
-        language_id: Option<String>,
-    ) -> Result<Vec<UserGraph>, AppError> {
-        match language_id {
-            Some(x) => match ctx.data::<Mongod>() {
-                Ok(it) => it.find_lang_for_use_in_base(x).await,
-                Err(_err) => Err(AppError::ContextData(
-                    "in data context with Mongod".to_string(),
-                )),
-            },
-            None => match ctx.data::<Mongod>() {
-                Ok(it) => it.get_users_from_base().await,
-                Err(_err) => Err(AppError::ContextData(
-                    "in data context with Mongod".to_string(),
-                )),
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+        #[graphql(default)] filter: UserFilter,
+        order_by: Option<UserOrderBy>,
+    ) -> async_graphql::Result<Connection<KeyCursor, UserGraph>> {
+        let mongod = match ctx.data::<Mongod>() {
+            Ok(it) => it,
+            Err(_err) => {
+                return Err(AppError::ContextData("in data context with Mongod".to_string()).into())
+            }
+        };
+        let api_key = api_key(ctx);
+        let filter = filter.to_filter();
+        let order = order_by
+            .map(|order_by| order_by.to_order())
+            .unwrap_or_default();
+
+        query(
+            after,
+            before,
+            first,
+            last,
+            |after, before, first, last| async move {
+                let page = Page::new(order, after, before, first, last);
+                let users = mongod
+                    .get_users_from_base(page.filter(filter), page.find_options(), api_key)
+                    .await?;
+                page.connection(users)
             },
-        }
+        )
+        .await
     }
 
     async fn language(&self, ctx: &Context<'_>, id: String) -> Result<Option<Language>, AppError> {
         match ctx.data::<Mongod>() {
-            Ok(it) => it.find_language_in_base(id).await,
+            Ok(it) => it.find_language_in_base(id, api_key(ctx)).await,
             Err(_err) => Err(AppError::ContextData(
                 "in data context with Mongod".to_string(),
             )),
         }
     }
 
-    async fn languages(&self, ctx: &Context<'_>) -> Result<Vec<Language>, AppError> {
-        match ctx.data::<Mongod>() {
-            Ok(it) => it.get_languages_from_base().await,
-            Err(_err) => Err(AppError::ContextData(
-                "in data context with Mongod".to_string(),
-            )),
-        }
+    #[allow(clippy::too_many_arguments)]
+    async fn languages(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+        #[graphql(default)] filter: LanguageFilter,
+        order_by: Option<LanguageOrderBy>,
+    ) -> async_graphql::Result<Connection<KeyCursor, Language>> {
+        let mongod = match ctx.data::<Mongod>() {
+            Ok(it) => it,
+            Err(_err) => {
+                return Err(AppError::ContextData("in data context with Mongod".to_string()).into())
+            }
+        };
+        let api_key = api_key(ctx);
+        let filter = filter.to_filter();
+        let order = order_by
+            .map(|order_by| order_by.to_order())
+            .unwrap_or_default();
+
+        query(
+            after,
+            before,
+            first,
+            last,
+            |after, before, first, last| async move {
+                let page = Page::new(order, after, before, first, last);
+                let languages = mongod
+                    .get_languages_from_base(page.filter(filter), page.find_options(), api_key)
+                    .await?;
+                page.connection(languages)
+            },
+        )
+        .await
+    }
+}
+
+#[ComplexObject]
+impl Language {
+    /// The users speaking the language, batched across every language in the response.
+    async fn users(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<UserGraph>> {
+        let loader = match ctx.data::<DataLoader<UsersByLanguage>>() {
+            Ok(it) => it,
+            Err(_err) => {
+                return Err(AppError::ContextData(
+                    "in data context with UsersByLanguage".to_string(),
+                )
+                .into())
+            }
+        };
+        let key = LanguageKey {
+            api_key: api_key(ctx).map(ToString::to_string),
+            language_id: self.id.clone(),
+        };
+        Ok(loader.load_one(key).await?.unwrap_or_default())
     }
 }
 
@@ -69,26 +154,31 @@ impl Mutation {
         name: String,
         age: u8,
         language_id: String,
-    ) -> Result<UserGraph, AppError> {
-        match ctx.data::<Mongod>() {
-            Ok(it) => it.add_user_in_base(id, name, age, language_id).await,
+    ) -> async_graphql::Result<UserGraph> {
+        let result = match ctx.data::<Mongod>() {
+            Ok(it) => {
+                it.add_user_in_base(id, name, age, language_id, api_key(ctx))
+                    .await
+            }
             Err(_err) => Err(AppError::ContextData(
                 "in data context with Mongod".to_string(),
             )),
-        }
+        };
+        result.map_err(|err| err.extend())
     }
 
     async fn delete_user(
         &self,
         ctx: &Context<'_>,
         id: String,
-    ) -> Result<Option<UserGraph>, AppError> {
-        match ctx.data::<Mongod>() {
-            Ok(it) => it.delete_user_from_base(id).await,
+    ) -> async_graphql::Result<Option<UserGraph>> {
+        let result = match ctx.data::<Mongod>() {
+            Ok(it) => it.delete_user_from_base(id, api_key(ctx)).await,
             Err(_err) => Err(AppError::ContextData(
                 "in data context with Mongod".to_string(),
             )),
-        }
+        };
+        result.map_err(|err| err.extend())
     }
 
     async fn update_user(
@@ -98,12 +188,217 @@ impl Mutation {
         name: Option<String>,
         age: Option<u8>,
         language_id: Option<String>,
-    ) -> Result<Option<UserGraph>, AppError> {
-        match ctx.data::<Mongod>() {
-            Ok(it) => it.update_user_in_base(id, name, age, language_id).await,
+    ) -> async_graphql::Result<Option<UserGraph>> {
+        let result = match ctx.data::<Mongod>() {
+            Ok(it) => {
+                it.update_user_in_base(id, name, age, language_id, api_key(ctx))
+                    .await
+            }
             Err(_err) => Err(AppError::ContextData(
                 "in data context with Mongod".to_string(),
             )),
+        };
+        result.map_err(|err| err.extend())
+    }
+}
+
+pub struct Subscription;
+
+#[Subscription]
+impl Subscription {
+    async fn user_added(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<impl Stream<Item = UserGraph>> {
+        Ok(user_events(ctx)?.filter_map(|event| async move {
+            match event {
+                UserEvent::Added(user) => Some(user),
+                _ => None,
+            }
+        }))
+    }
+
+    async fn user_updated(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+    ) -> async_graphql::Result<impl Stream<Item = UserGraph>> {
+        Ok(user_events(ctx)?.filter_map(move |event| {
+            let id = id.clone();
+            async move {
+                match event {
+                    UserEvent::Updated(user) if user.id == id => Some(user),
+                    _ => None,
+                }
+            }
+        }))
+    }
+
+    async fn user_deleted(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<impl Stream<Item = UserGraph>> {
+        Ok(user_events(ctx)?.filter_map(|event| async move {
+            match event {
+                UserEvent::Deleted(user) => Some(user),
+                _ => None,
+            }
+        }))
+    }
+}
+
+/// The api key of the tenant the request is made for, if any.
+fn api_key<'a>(ctx: &Context<'a>) -> Option<&'a str> {
+    ctx.data_opt::<Tenant>().and_then(Tenant::api_key)
+}
+
+/// Stream the user events of the tenant published from now on.
+///
+/// Events missed by a lagging subscriber are skipped.
+fn user_events(ctx: &Context<'_>) -> Result<impl Stream<Item = UserEvent>, AppError> {
+    let receiver = match ctx.data::<Mongod>() {
+        Ok(it) => it.subscribe_user_events(),
+        Err(_err) => {
+            return Err(AppError::ContextData(
+                "in data context with Mongod".to_string(),
+            ))
+        }
+    };
+    let tenant = api_key(ctx).map(ToString::to_string);
+    Ok(stream::unfold(
+        (receiver, tenant),
+        |(mut receiver, tenant)| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok((api_key, event)) if api_key == tenant => {
+                        return Some((event, (receiver, tenant)))
+                    }
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mongo::{languages::Languages, users_graph::UserGraphs};
+    use crate::user_schema::UserSchema;
+    use async_graphql::Schema;
+    use bongo_mong::{testing::MemoryStore, PoolManager};
+    use serde_json::{json, Value};
+
+    async fn schema(users: &[(&str, &str, u8)]) -> UserSchema {
+        let pool_manager =
+            PoolManager::with_memory_store(config::Config::default(), MemoryStore::new()).unwrap();
+        let mongod = Mongod::with_collections(
+            UserGraphs::new(pool_manager.clone()),
+            Languages::new(pool_manager),
+        );
+        for (id, name, age) in users {
+            mongod
+                .add_user_in_base(id.to_string(), name.to_string(), *age, "en".into(), None)
+                .await
+                .unwrap();
         }
+        Schema::build(QueryRoot, Mutation, Subscription)
+            .data(mongod)
+            .finish()
+    }
+
+    async fn execute(schema: &UserSchema, query: &str) -> Value {
+        let response = schema.execute(query).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        response.data.into_json().unwrap()
+    }
+
+    fn ids(page: &Value) -> Vec<&str> {
+        page["edges"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|edge| edge["node"]["id"].as_str().unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn users_are_filtered() {
+        let schema = schema(&[
+            ("1", "Maria", 30),
+            ("2", "Mario", 20),
+            ("3", "Anna", 40),
+            ("4", "MARIANNE", 35),
+            ("5", "Mar.a", 50),
+        ])
+        .await;
+
+        let data = execute(
+            &schema,
+            r#"{ users(filter: {nameContains: "mar", ageGte: 25}) { edges { node { id } } } }"#,
+        )
+        .await;
+        assert_eq!(ids(&data["users"]), ["1", "4", "5"]);
+
+        let data = execute(
+            &schema,
+            r#"{ users(filter: {nameContains: "r.a", ageLte: 40}) { edges { node { id } } } }"#,
+        )
+        .await;
+        assert!(ids(&data["users"]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn users_are_paged_with_cursors() {
+        let schema = schema(&[
+            ("1", "Maria", 30),
+            ("2", "Mario", 20),
+            ("3", "Anna", 40),
+            ("4", "Nikos", 30),
+        ])
+        .await;
+        let query = |arguments: &str| {
+            format!(
+                "{{ users({}, orderBy: {{field: AGE, direction: DESC}}) {{
+                    edges {{ node {{ id }} }}
+                    pageInfo {{ hasPreviousPage hasNextPage startCursor endCursor }}
+                }} }}",
+                arguments
+            )
+        };
+
+        let data = execute(&schema, &query("first: 2")).await;
+        let page = &data["users"];
+        assert_eq!(ids(page), ["3", "1"]);
+        assert_eq!(page["pageInfo"]["hasPreviousPage"], json!(false));
+        assert_eq!(page["pageInfo"]["hasNextPage"], json!(true));
+
+        let after = page["pageInfo"]["endCursor"].as_str().unwrap();
+        let data = execute(&schema, &query(&format!("first: 2, after: \"{}\"", after))).await;
+        let page = &data["users"];
+        assert_eq!(ids(page), ["4", "2"]);
+        assert_eq!(page["pageInfo"]["hasPreviousPage"], json!(true));
+        assert_eq!(page["pageInfo"]["hasNextPage"], json!(false));
+
+        let before = page["pageInfo"]["startCursor"].as_str().unwrap();
+        let data = execute(&schema, &query(&format!("last: 1, before: \"{}\"", before))).await;
+        let page = &data["users"];
+        assert_eq!(ids(page), ["1"]);
+        assert_eq!(page["pageInfo"]["hasPreviousPage"], json!(true));
+        assert_eq!(page["pageInfo"]["hasNextPage"], json!(true));
+
+        let data = execute(&schema, &query("last: 3")).await;
+        assert_eq!(ids(&data["users"]), ["1", "4", "2"]);
+    }
+
+    #[tokio::test]
+    async fn users_reject_invalid_cursors() {
+        let schema = schema(&[]).await;
+        let response = schema
+            .execute(r#"{ users(after: "9") { edges { node { id } } } }"#)
+            .await;
+        assert_eq!(response.errors.len(), 1);
     }
 }
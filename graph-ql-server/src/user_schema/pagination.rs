@@ -0,0 +1,443 @@
+//! Arguments of the paginated queries, and their mapping to Mongo find options.
+use async_graphql::{
+    connection::{Connection, CursorType, Edge},
+    Enum, InputObject, OutputType,
+};
+use bongo_mong::query::{FieldPath, Filter};
+use mongodb::{
+    bson::{self, doc, Bson, Document},
+    options::FindOptions,
+};
+use serde::Serialize;
+
+use crate::mongo::{languages::Language, users_graph::UserGraph};
+use crate::AppError;
+
+/// The page size used when neither `first` nor `last` is given.
+const DEFAULT_PAGE_SIZE: usize = 20;
+
+/// The largest page a client may request.
+const MAX_PAGE_SIZE: usize = 100;
+
+#[derive(Enum, Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum OrderDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl OrderDirection {
+    fn as_i32(self) -> i32 {
+        match self {
+            Self::Asc => 1,
+            Self::Desc => -1,
+        }
+    }
+
+    fn reverse(self) -> Self {
+        match self {
+            Self::Asc => Self::Desc,
+            Self::Desc => Self::Asc,
+        }
+    }
+}
+
+#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq)]
+pub enum UserOrderField {
+    Id,
+    Name,
+    Age,
+}
+
+#[derive(InputObject, Clone, Debug)]
+pub struct UserOrderBy {
+    pub field: UserOrderField,
+    #[graphql(default)]
+    pub direction: OrderDirection,
+}
+
+impl UserOrderBy {
+    /// The order of the users.
+    pub fn to_order(&self) -> Order {
+        let field: FieldPath = match self.field {
+            UserOrderField::Id => UserGraph::ID.into(),
+            UserOrderField::Name => UserGraph::NAME.into(),
+            UserOrderField::Age => UserGraph::AGE.into(),
+        };
+        Order::new(field, self.direction)
+    }
+}
+
+#[derive(InputObject, Clone, Debug, Default)]
+pub struct UserFilter {
+    pub age_gte: Option<u8>,
+    pub age_lte: Option<u8>,
+    pub name_contains: Option<String>,
+    pub language_id: Option<String>,
+}
+
+impl UserFilter {
+    /// The filter selecting the users that match every given field.
+    pub fn to_filter(&self) -> Filter {
+        let filters = [
+            self.age_gte.map(|age| UserGraph::AGE.gte(age)),
+            self.age_lte.map(|age| UserGraph::AGE.lte(age)),
+            self.name_contains
+                .as_deref()
+                .map(|name| contains(UserGraph::NAME, name)),
+            self.language_id
+                .as_deref()
+                .map(|language_id| UserGraph::LANGUAGE_ID.eq(language_id)),
+        ];
+        all(filters.into_iter().flatten())
+    }
+}
+
+#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LanguageOrderField {
+    Id,
+    Name,
+}
+
+#[derive(InputObject, Clone, Debug)]
+pub struct LanguageOrderBy {
+    pub field: LanguageOrderField,
+    #[graphql(default)]
+    pub direction: OrderDirection,
+}
+
+impl LanguageOrderBy {
+    /// The order of the languages.
+    pub fn to_order(&self) -> Order {
+        let field: FieldPath = match self.field {
+            LanguageOrderField::Id => Language::ID.into(),
+            LanguageOrderField::Name => Language::NAME.into(),
+        };
+        Order::new(field, self.direction)
+    }
+}
+
+#[derive(InputObject, Clone, Debug, Default)]
+pub struct LanguageFilter {
+    pub name_contains: Option<String>,
+}
+
+impl LanguageFilter {
+    /// The filter selecting the languages that match every given field.
+    pub fn to_filter(&self) -> Filter {
+        let filters = [self
+            .name_contains
+            .as_deref()
+            .map(|name| contains(Language::NAME, name))];
+        all(filters.into_iter().flatten())
+    }
+}
+
+/// The order of the nodes of a connection, by a field then by ascending `id`, so that every
+/// node has its own position.
+#[derive(Clone, Debug)]
+pub struct Order {
+    field: FieldPath,
+    direction: OrderDirection,
+}
+
+impl Default for Order {
+    /// The order by ascending `id`.
+    fn default() -> Self {
+        Self::new("id", OrderDirection::Asc)
+    }
+}
+
+impl Order {
+    fn new(field: impl Into<FieldPath>, direction: OrderDirection) -> Self {
+        Self {
+            field: field.into(),
+            direction,
+        }
+    }
+
+    fn is_by_id(&self) -> bool {
+        self.field.as_str() == "id"
+    }
+
+    /// The sort document of the order, or of the reverse order if `backwards`.
+    fn sort_document(&self, backwards: bool) -> Document {
+        let (direction, id) = if backwards {
+            (self.direction.reverse(), OrderDirection::Desc)
+        } else {
+            (self.direction, OrderDirection::Asc)
+        };
+        let mut sort = doc! {self.field.as_str(): direction.as_i32()};
+        if !self.is_by_id() {
+            sort.insert("id", id.as_i32());
+        }
+        sort
+    }
+
+    /// Match the nodes that come after `cursor` in the order, or before it if not `forward`.
+    fn beyond(&self, cursor: &KeyCursor, forward: bool) -> Filter {
+        let direction = if forward {
+            self.direction
+        } else {
+            self.direction.reverse()
+        };
+        let field = match direction {
+            OrderDirection::Asc => Filter::gt(self.field.clone(), cursor.key.clone()),
+            OrderDirection::Desc => Filter::lt(self.field.clone(), cursor.key.clone()),
+        };
+        if self.is_by_id() {
+            return field;
+        }
+        // Ties on the field are broken by ascending `id`, whatever the direction
+        let id = if forward {
+            Filter::gt("id", cursor.id.as_str())
+        } else {
+            Filter::lt("id", cursor.id.as_str())
+        };
+        field.or(Filter::eq(self.field.clone(), cursor.key.clone()).and(id))
+    }
+
+    /// The cursor of `node` in the order.
+    fn cursor<T: Serialize>(&self, node: &T) -> Result<KeyCursor, AppError> {
+        let document = bson::to_document(node).map_err(mongodb::error::Error::from)?;
+        Ok(KeyCursor {
+            key: document
+                .get(self.field.as_str())
+                .cloned()
+                .unwrap_or(Bson::Null),
+            id: document.get_str("id").unwrap_or_default().to_string(),
+        })
+    }
+}
+
+/// Match the documents matching every filter.
+fn all(filters: impl Iterator<Item = Filter>) -> Filter {
+    filters.fold(Filter::default(), |all, filter| {
+        if all.is_empty() {
+            filter
+        } else {
+            all.and(filter)
+        }
+    })
+}
+
+/// A case insensitive substring match on a field.
+fn contains(field: impl Into<FieldPath>, value: &str) -> Filter {
+    let mut pattern = String::with_capacity(value.len());
+    for c in value.chars() {
+        if "\\^$.|?*+()[]{}".contains(c) {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    Filter::eq(field, doc! {"$regex": pattern, "$options": "i"})
+}
+
+/// The position of a node in an [`Order`]: its value of the sorted field, and its `id`.
+///
+/// Cursors are opaque to clients, and stay valid when nodes are added or removed before them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyCursor {
+    key: Bson,
+    id: String,
+}
+
+/// A cursor that was not given by a previous page.
+#[derive(Debug, thiserror::Error)]
+#[error("invalid cursor")]
+pub struct InvalidCursor;
+
+impl CursorType for KeyCursor {
+    type Error = InvalidCursor;
+
+    fn decode_cursor(s: &str) -> Result<Self, Self::Error> {
+        let bytes = base64::decode_config(s, base64::URL_SAFE_NO_PAD).map_err(|_| InvalidCursor)?;
+        let mut document = bson::from_slice::<Document>(&bytes).map_err(|_| InvalidCursor)?;
+        match (document.remove("k"), document.remove("id")) {
+            (Some(key), Some(Bson::String(id))) => Ok(Self { key, id }),
+            _ => Err(InvalidCursor),
+        }
+    }
+
+    fn encode_cursor(&self) -> String {
+        let document = doc! {"k": self.key.clone(), "id": self.id.as_str()};
+        // Documents of a string and a value of another document always serialize
+        let bytes = bson::to_vec(&document).unwrap_or_default();
+        base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+    }
+}
+
+/// The page of the matching nodes selected by the connection arguments.
+///
+/// A page is read after its `after` cursor and before its `before` one. Pages of `last` nodes
+/// are read backwards, from the end. One more node than needed is read, to know if there are
+/// more pages in the reading direction without counting the nodes.
+#[derive(Clone, Debug)]
+pub struct Page {
+    order: Order,
+    after: Option<KeyCursor>,
+    before: Option<KeyCursor>,
+    size: usize,
+    last: Option<usize>,
+    backwards: bool,
+}
+
+impl Page {
+    /// Resolve the page of the connection arguments, over the nodes sorted in `order`.
+    pub fn new(
+        order: Order,
+        after: Option<KeyCursor>,
+        before: Option<KeyCursor>,
+        first: Option<usize>,
+        last: Option<usize>,
+    ) -> Self {
+        let last = last.map(|last| last.min(MAX_PAGE_SIZE));
+        let (size, backwards) = match (first, last) {
+            (Some(first), _) => (first.min(MAX_PAGE_SIZE), false),
+            (None, Some(last)) => (last, true),
+            (None, None) => (DEFAULT_PAGE_SIZE, false),
+        };
+        Self {
+            order,
+            after,
+            before,
+            size,
+            last: last.filter(|_| !backwards),
+            backwards,
+        }
+    }
+
+    /// The query document selecting the nodes of the page matching `filter`.
+    pub fn filter(&self, filter: Filter) -> Document {
+        let after = self
+            .after
+            .iter()
+            .map(|after| self.order.beyond(after, true));
+        let before = self
+            .before
+            .iter()
+            .map(|before| self.order.beyond(before, false));
+        all(std::iter::once(filter).chain(after).chain(before)).into()
+    }
+
+    /// Find options reading the nodes of the page, in the reading direction.
+    pub fn find_options(&self) -> FindOptions {
+        FindOptions::builder()
+            .sort(self.order.sort_document(self.backwards))
+            .limit(self.size as i64 + 1)
+            .build()
+    }
+
+    /// The connection of the nodes read with [`Page::find_options`].
+    pub fn connection<T>(self, mut nodes: Vec<T>) -> Result<Connection<KeyCursor, T>, AppError>
+    where
+        T: OutputType + Serialize,
+    {
+        let has_more = nodes.len() > self.size;
+        nodes.truncate(self.size);
+        let (has_previous_page, has_next_page) = if self.backwards {
+            nodes.reverse();
+            (has_more, self.before.is_some())
+        } else {
+            let skipped = match self.last {
+                Some(last) => nodes.len().saturating_sub(last),
+                None => 0,
+            };
+            nodes.drain(..skipped);
+            (self.after.is_some() || skipped > 0, has_more)
+        };
+
+        let mut connection = Connection::new(has_previous_page, has_next_page);
+        for node in nodes {
+            let cursor = self.order.cursor(&node)?;
+            connection.edges.push(Edge::new(cursor, node));
+        }
+        Ok(connection)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cursor(key: impl Into<Bson>, id: &str) -> KeyCursor {
+        KeyCursor {
+            key: key.into(),
+            id: id.into(),
+        }
+    }
+
+    fn page(first: Option<usize>, last: Option<usize>) -> Page {
+        Page::new(Order::default(), None, None, first, last)
+    }
+
+    #[test]
+    fn cursors_are_opaque() {
+        let cursor = cursor(30, "maria");
+        let encoded = cursor.encode_cursor();
+        assert!(!encoded.contains("maria"));
+        assert_eq!(KeyCursor::decode_cursor(&encoded).unwrap(), cursor);
+        assert!(KeyCursor::decode_cursor("9").is_err());
+        assert!(KeyCursor::decode_cursor("not a cursor").is_err());
+    }
+
+    #[test]
+    fn page_sizes() {
+        assert_eq!(page(None, None).find_options().limit, Some(21));
+        assert_eq!(page(Some(5), None).find_options().limit, Some(6));
+        assert_eq!(page(Some(500), None).find_options().limit, Some(101));
+        assert_eq!(page(None, Some(500)).find_options().limit, Some(101));
+    }
+
+    #[test]
+    fn filters_match_every_given_field() {
+        let filter = UserFilter {
+            age_gte: Some(18),
+            name_contains: Some("ma.ia".to_string()),
+            language_id: Some("en".to_string()),
+            ..UserFilter::default()
+        };
+        assert_eq!(
+            Document::from(filter.to_filter()),
+            doc! {
+                "age": {"$gte": 18},
+                "name": {"$regex": "ma\\.ia", "$options": "i"},
+                "language_id": "en",
+            }
+        );
+        assert!(UserFilter::default().to_filter().is_empty());
+        assert!(LanguageFilter::default().to_filter().is_empty());
+    }
+
+    #[test]
+    fn pages_read_backwards_from_the_end() {
+        let order = UserOrderBy {
+            field: UserOrderField::Age,
+            direction: OrderDirection::Desc,
+        };
+        let page = Page::new(order.to_order(), None, None, None, Some(5));
+        assert_eq!(page.find_options().sort, Some(doc! {"age": 1, "id": -1}));
+    }
+
+    #[test]
+    fn cursors_bound_the_page() {
+        let order = UserOrderBy {
+            field: UserOrderField::Age,
+            direction: OrderDirection::Desc,
+        };
+        let after = Some(cursor(40, "b"));
+        let before = Some(cursor(20, "a"));
+        let page = Page::new(order.to_order(), after, before, None, None);
+        assert_eq!(
+            page.filter(UserGraph::NAME.eq("maria")),
+            doc! {"$and": [
+                {"name": "maria"},
+                {"$or": [{"age": {"$lt": 40}}, {"age": 40, "id": {"$gt": "b"}}]},
+                {"$or": [{"age": {"$gt": 20}}, {"age": 20, "id": {"$lt": "a"}}]},
+            ]}
+        );
+
+        let page = Page::new(Order::default(), Some(cursor("b", "b")), None, None, None);
+        assert_eq!(page.filter(Filter::default()), doc! {"id": {"$gt": "b"}});
+    }
+}
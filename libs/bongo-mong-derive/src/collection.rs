@@ -0,0 +1,499 @@
+//! The expansion of `#[derive(Collection)]`.
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::{
+    ext::IdentExt,
+    parenthesized,
+    parse::{Parse, ParseStream},
+    punctuated::Punctuated,
+    Data, DeriveInput, Error, Fields, Ident, LitInt, LitStr, Result, Token, Type,
+};
+
+const NAME_FIELD: &str = "name";
+const POOL_MANAGER_FIELD: &str = "pool_manager";
+
+/// Expand the derive into an inherent impl and the `dao` trait impls.
+pub(crate) fn expand(input: &DeriveInput) -> Result<TokenStream> {
+    let attributes = Attributes::from_input(input)?;
+    let fields = CollectionFields::from_input(input)?;
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let name = &attributes.name;
+    let model = &attributes.model;
+    let pool_manager = fields.pool_manager;
+    let others = fields.others;
+
+    let indexes = if attributes.indexes.is_empty() {
+        None
+    } else {
+        let indexes = attributes.indexes.iter().map(Index::to_tokens);
+        Some(quote! {
+            fn indexes(&self) -> ::std::vec::Vec<::bongo_mong::mongodb::IndexModel> {
+                ::std::vec![#(#indexes),*]
+            }
+        })
+    };
+    let permission = attributes.permission.map(|permission| {
+        let variant = permission.variant();
+        quote! {
+            fn default_permission(&self) -> ::bongo_mong::config::options::PoolPermissionType {
+                ::bongo_mong::config::options::PoolPermissionType::#variant
+            }
+        }
+    });
+    let soft_delete = attributes.soft_delete.then(|| {
+        quote! {
+            fn soft_delete(&self) -> bool {
+                true
+            }
+        }
+    });
+    let audit = attributes.audit.map(|audit| {
+        quote! {
+            fn audit_collection(&self) -> ::std::option::Option<&str> {
+                ::std::option::Option::Some(#audit)
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics #ident #ty_generics #where_clause {
+            /// The default name of the collection.
+            pub const NAME: &'static str = #name;
+
+            /// Create the collection with its default name.
+            pub fn new(pool_manager: #pool_manager) -> Self {
+                Self::with_name(Self::NAME, pool_manager)
+            }
+
+            /// Create the collection with the given name, e.g. one read from a configuration.
+            pub fn with_name(
+                name: impl ::std::convert::Into<::std::string::String>,
+                pool_manager: #pool_manager,
+            ) -> Self {
+                Self {
+                    name: name.into(),
+                    pool_manager,
+                    #(#others: ::std::default::Default::default(),)*
+                }
+            }
+        }
+
+        impl #impl_generics ::bongo_mong::dao::Collection for #ident #ty_generics #where_clause {
+            fn name(&self) -> &str {
+                &self.name
+            }
+
+            #indexes
+            #permission
+            #soft_delete
+            #audit
+        }
+
+        impl #impl_generics ::bongo_mong::dao::DbConnect for #ident #ty_generics #where_clause {
+            fn pool_manager(&self) -> &::bongo_mong::PoolManager {
+                &self.pool_manager
+            }
+        }
+
+        impl #impl_generics ::bongo_mong::dao::Query<#model> for #ident #ty_generics #where_clause {}
+    })
+}
+
+/// The arguments of every `bongo` attribute of a collection.
+struct Attributes {
+    name: LitStr,
+    model: Box<Type>,
+    permission: Option<Permission>,
+    indexes: Vec<Index>,
+    soft_delete: bool,
+    audit: Option<LitStr>,
+}
+
+impl Attributes {
+    fn from_input(input: &DeriveInput) -> Result<Self> {
+        let mut name = None;
+        let mut model = None;
+        let mut permission = None;
+        let mut indexes = Vec::new();
+        let mut soft_delete = false;
+        let mut audit = None;
+
+        for attr in input
+            .attrs
+            .iter()
+            .filter(|attr| attr.path.is_ident("bongo"))
+        {
+            let arguments =
+                attr.parse_args_with(Punctuated::<Argument, Token![,]>::parse_terminated)?;
+            for Argument { key, value } in arguments {
+                let duplicate = match value {
+                    Value::Name(value) => name.replace(value).is_some(),
+                    Value::Model(value) => model.replace(value).is_some(),
+                    Value::Permission(value) => permission.replace(value).is_some(),
+                    Value::Index(value) => {
+                        indexes.push(value);
+                        false
+                    }
+                    Value::SoftDelete => std::mem::replace(&mut soft_delete, true),
+                    Value::Audit(value) => audit.replace(value).is_some(),
+                };
+                if duplicate {
+                    return Err(Error::new(key.span(), format!("duplicate `{}`", key)));
+                }
+            }
+        }
+
+        Ok(Self {
+            name: name.ok_or_else(|| missing(input, "name = \"...\""))?,
+            model: model.ok_or_else(|| missing(input, "model = Type"))?,
+            permission,
+            indexes,
+            soft_delete,
+            audit,
+        })
+    }
+}
+
+fn missing(input: &DeriveInput, argument: &str) -> Error {
+    Error::new(
+        input.ident.span(),
+        format!("missing `#[bongo({})]` attribute", argument),
+    )
+}
+
+/// An argument of a `bongo` attribute.
+struct Argument {
+    key: Ident,
+    value: Value,
+}
+
+enum Value {
+    Name(LitStr),
+    Model(Box<Type>),
+    Permission(Permission),
+    Index(Index),
+    SoftDelete,
+    Audit(LitStr),
+}
+
+impl Parse for Argument {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let key: Ident = input.parse()?;
+        let value = match key.to_string().as_str() {
+            "name" => {
+                input.parse::<Token![=]>()?;
+                Value::Name(input.parse()?)
+            }
+            "model" => {
+                input.parse::<Token![=]>()?;
+                Value::Model(input.parse()?)
+            }
+            "permission" => {
+                input.parse::<Token![=]>()?;
+                Value::Permission(input.parse()?)
+            }
+            "index" => {
+                let content;
+                parenthesized!(content in input);
+                Value::Index(content.parse()?)
+            }
+            "soft_delete" => Value::SoftDelete,
+            "audit" => {
+                input.parse::<Token![=]>()?;
+                Value::Audit(input.parse()?)
+            }
+            _ => {
+                return Err(Error::new(
+                    key.span(),
+                    "expected `name`, `model`, `permission`, `index`, `soft_delete` or `audit`",
+                ))
+            }
+        };
+        Ok(Self { key, value })
+    }
+}
+
+/// The permission of the pools reads go through.
+#[derive(Clone, Copy)]
+enum Permission {
+    Read,
+    Write,
+}
+
+impl Permission {
+    fn variant(self) -> Ident {
+        let variant = match self {
+            Self::Read => "Read",
+            Self::Write => "Write",
+        };
+        Ident::new(variant, Span::call_site())
+    }
+}
+
+impl Parse for Permission {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let permission: LitStr = input.parse()?;
+        match permission.value().as_str() {
+            "read" => Ok(Self::Read),
+            "write" => Ok(Self::Write),
+            _ => Err(Error::new(
+                permission.span(),
+                "expected \"read\" or \"write\"",
+            )),
+        }
+    }
+}
+
+/// An index declared with `index(...)`.
+#[derive(Default)]
+struct Index {
+    /// The keys of the index, with their direction or type.
+    keys: Vec<(LitStr, TokenStream)>,
+    unique: bool,
+    sparse: bool,
+    name: Option<LitStr>,
+}
+
+impl Index {
+    fn to_tokens(&self) -> TokenStream {
+        let keys = self.keys.iter().map(|(key, value)| quote!(#key: #value));
+        let mut options = Vec::new();
+        if self.unique {
+            options.push(quote!(.unique(true)));
+        }
+        if self.sparse {
+            options.push(quote!(.sparse(true)));
+        }
+        if let Some(name) = &self.name {
+            options.push(quote!(.name(::std::string::String::from(#name))));
+        }
+        let options = if options.is_empty() {
+            None
+        } else {
+            Some(quote! {
+                .options(
+                    ::bongo_mong::mongodb::options::IndexOptions::builder()
+                        #(#options)*
+                        .build()
+                )
+            })
+        };
+
+        quote! {
+            ::bongo_mong::mongodb::IndexModel::builder()
+                .keys(::bongo_mong::mongodb::bson::doc! { #(#keys),* })
+                #options
+                .build()
+        }
+    }
+}
+
+impl Parse for Index {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let span = input.span();
+        let mut index = Index::default();
+        while !input.is_empty() {
+            let option: Ident = input.parse()?;
+            match option.to_string().as_str() {
+                "keys" => {
+                    let content;
+                    parenthesized!(content in input);
+                    let keys = Punctuated::<IndexKey, Token![,]>::parse_terminated(&content)?;
+                    index
+                        .keys
+                        .extend(keys.into_iter().map(|key| (key.key, key.value)));
+                }
+                "unique" => index.unique = true,
+                "sparse" => index.sparse = true,
+                "name" => {
+                    input.parse::<Token![=]>()?;
+                    index.name = Some(input.parse()?);
+                }
+                _ => {
+                    return Err(Error::new(
+                        option.span(),
+                        "expected `keys`, `unique`, `sparse` or `name`",
+                    ))
+                }
+            }
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        if index.keys.is_empty() {
+            return Err(Error::new(span, "an index needs `keys(...)`"));
+        }
+        Ok(index)
+    }
+}
+
+/// A key of an index, as `field = 1`, `"nested.field" = -1` or `field = "text"`.
+struct IndexKey {
+    key: LitStr,
+    value: TokenStream,
+}
+
+impl Parse for IndexKey {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let key = if input.peek(LitStr) {
+            input.parse()?
+        } else {
+            let key = Ident::parse_any(input)?.unraw();
+            LitStr::new(&key.to_string(), key.span())
+        };
+        input.parse::<Token![=]>()?;
+        let value = if input.peek(LitStr) {
+            let value: LitStr = input.parse()?;
+            quote!(#value)
+        } else {
+            let minus: Option<Token![-]> = input.parse()?;
+            let value: LitInt = input.parse()?;
+            value.base10_parse::<i32>()?;
+            quote!(#minus #value)
+        };
+        Ok(Self { key, value })
+    }
+}
+
+/// The fields of a collection struct.
+struct CollectionFields<'a> {
+    /// The type of the `pool_manager` field.
+    pool_manager: &'a Type,
+    /// The fields set to their defaults by the constructors.
+    others: Vec<&'a Ident>,
+}
+
+impl<'a> CollectionFields<'a> {
+    fn from_input(input: &'a DeriveInput) -> Result<Self> {
+        let fields = match &input.data {
+            Data::Struct(data) => match &data.fields {
+                Fields::Named(fields) => &fields.named,
+                _ => return Err(not_a_collection(input)),
+            },
+            _ => return Err(not_a_collection(input)),
+        };
+
+        let mut name = false;
+        let mut pool_manager = None;
+        let mut others = Vec::new();
+        for field in fields {
+            match field.ident.as_ref() {
+                Some(ident) if ident == NAME_FIELD => name = true,
+                Some(ident) if ident == POOL_MANAGER_FIELD => pool_manager = Some(&field.ty),
+                Some(ident) => others.push(ident),
+                None => {}
+            }
+        }
+
+        match pool_manager {
+            Some(pool_manager) if name => Ok(Self {
+                pool_manager,
+                others,
+            }),
+            _ => Err(not_a_collection(input)),
+        }
+    }
+}
+
+fn not_a_collection(input: &DeriveInput) -> Error {
+    Error::new(
+        input.ident.span(),
+        "`Collection` can only be derived for structs with `name` and `pool_manager` fields",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    fn error(input: DeriveInput) -> String {
+        expand(&input).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn expand_collection() {
+        let input: DeriveInput = parse_quote! {
+            #[bongo(name = "installations", model = Installation, permission = "write")]
+            #[bongo(index(keys(wappierId = 1, "sessions.active" = -1), unique, name = "wat"))]
+            pub struct Installations {
+                name: String,
+                pool_manager: PoolManager,
+                cached: Option<String>,
+            }
+        };
+        let tokens = expand(&input).unwrap().to_string();
+
+        assert!(tokens.contains("pub const NAME : & 'static str = \"installations\""));
+        assert!(tokens.contains("pool_manager : PoolManager"));
+        assert!(tokens.contains("cached : :: std :: default :: Default :: default ()"));
+        assert!(tokens.contains("\"wappierId\" : 1 , \"sessions.active\" : - 1"));
+        assert!(tokens.contains(". unique (true)"));
+        assert!(tokens.contains("PoolPermissionType :: Write"));
+        assert!(tokens.contains(":: bongo_mong :: dao :: Query < Installation >"));
+        assert!(!tokens.contains("fn soft_delete"));
+        assert!(!tokens.contains("fn audit_collection"));
+    }
+
+    #[test]
+    fn expand_soft_delete_and_audit() {
+        let input: DeriveInput = parse_quote! {
+            #[bongo(name = "users", model = User, soft_delete, audit = "audit")]
+            pub struct Users {
+                name: String,
+                pool_manager: PoolManager,
+            }
+        };
+        let tokens = expand(&input).unwrap().to_string();
+
+        assert!(tokens.contains("fn soft_delete (& self) -> bool { true }"));
+        assert!(tokens.contains(":: std :: option :: Option :: Some (\"audit\")"));
+        assert_eq!(
+            error(parse_quote! {
+                #[bongo(name = "users", model = User, soft_delete, soft_delete)]
+                struct Users { name: String, pool_manager: PoolManager }
+            }),
+            "duplicate `soft_delete`"
+        );
+    }
+
+    #[test]
+    fn expand_reports_invalid_attributes() {
+        let fields = quote! {{ name: String, pool_manager: PoolManager }};
+        let input = |attrs: TokenStream| -> DeriveInput {
+            syn::parse2(quote!(#attrs struct Users #fields)).unwrap()
+        };
+
+        assert_eq!(
+            error(input(quote!(#[bongo(model = User)]))),
+            "missing `#[bongo(name = \"...\")]` attribute"
+        );
+        assert_eq!(
+            error(input(
+                quote!(#[bongo(name = "users", model = User, name = "u")])
+            )),
+            "duplicate `name`"
+        );
+        assert_eq!(
+            error(input(
+                quote!(#[bongo(name = "users", model = User, permission = "admin")])
+            )),
+            "expected \"read\" or \"write\""
+        );
+        assert_eq!(
+            error(input(
+                quote!(#[bongo(name = "users", model = User, index(unique))])
+            )),
+            "an index needs `keys(...)`"
+        );
+        assert_eq!(
+            error(parse_quote! {
+                #[bongo(name = "users", model = User)]
+                struct Users { pool_manager: PoolManager }
+            }),
+            "`Collection` can only be derived for structs with `name` and `pool_manager` fields"
+        );
+    }
+}
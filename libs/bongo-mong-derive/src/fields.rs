@@ -0,0 +1,223 @@
+//! The expansion of `#[derive(Fields)]`.
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{
+    ext::IdentExt, Attribute, Data, DeriveInput, Error, Fields, Lit, LitStr, Meta, NestedMeta,
+    Result,
+};
+
+/// Expand the derive into a constant for every serialized field.
+pub(crate) fn expand(input: &DeriveInput) -> Result<TokenStream> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(not_a_model(input)),
+        },
+        _ => return Err(not_a_model(input)),
+    };
+    let rename_all = match Serde::from_attrs(&input.attrs)?.rename_all {
+        Some(rule) => Some(RenameRule::from_lit(&rule)?),
+        None => None,
+    };
+
+    let mut constants = Vec::new();
+    for field in fields {
+        let serde = Serde::from_attrs(&field.attrs)?;
+        if serde.skip {
+            continue;
+        }
+        let ident = match &field.ident {
+            Some(ident) => ident.unraw(),
+            None => continue,
+        };
+        let name = match (serde.rename, rename_all) {
+            (Some(rename), _) => rename.value(),
+            (None, Some(rule)) => rule.apply(&ident.to_string()),
+            (None, None) => ident.to_string(),
+        };
+        let constant = format_ident!("{}", ident.to_string().to_uppercase(), span = ident.span());
+        let ty = &field.ty;
+        let doc = format!("The `{}` field.", name);
+        constants.push(quote! {
+            #[doc = #doc]
+            pub const #constant: ::bongo_mong::query::Field<Self, #ty> =
+                ::bongo_mong::query::Field::new(#name);
+        });
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #ident #ty_generics #where_clause {
+            #(#constants)*
+        }
+    })
+}
+
+fn not_a_model(input: &DeriveInput) -> Error {
+    Error::new(
+        input.ident.span(),
+        "`Fields` can only be derived for structs with named fields",
+    )
+}
+
+/// The serde attributes that change the serialized names of fields.
+#[derive(Default)]
+struct Serde {
+    rename: Option<LitStr>,
+    rename_all: Option<LitStr>,
+    /// Whether the field is not serialized on its own, including flattened fields.
+    skip: bool,
+}
+
+impl Serde {
+    fn from_attrs(attrs: &[Attribute]) -> Result<Self> {
+        let mut serde = Self::default();
+        for attr in attrs.iter().filter(|attr| attr.path.is_ident("serde")) {
+            let items = match attr.parse_meta()? {
+                Meta::List(list) => list.nested,
+                _ => continue,
+            };
+            for item in items {
+                let meta = match item {
+                    NestedMeta::Meta(meta) => meta,
+                    NestedMeta::Lit(_) => continue,
+                };
+                let key = match meta.path().get_ident() {
+                    Some(key) => key.to_string(),
+                    None => continue,
+                };
+                match (key.as_str(), &meta) {
+                    ("rename", meta) => serde.rename = serialized_name(meta),
+                    ("rename_all", meta) => serde.rename_all = serialized_name(meta),
+                    ("skip" | "skip_serializing" | "flatten", Meta::Path(_)) => serde.skip = true,
+                    _ => {}
+                }
+            }
+        }
+        Ok(serde)
+    }
+}
+
+/// The serialization name of `rename = "..."` or `rename(serialize = "...")`.
+fn serialized_name(meta: &Meta) -> Option<LitStr> {
+    match meta {
+        Meta::NameValue(value) => string(&value.lit),
+        Meta::List(list) => list.nested.iter().find_map(|item| match item {
+            NestedMeta::Meta(Meta::NameValue(value)) if value.path.is_ident("serialize") => {
+                string(&value.lit)
+            }
+            _ => None,
+        }),
+        Meta::Path(_) => None,
+    }
+}
+
+fn string(lit: &Lit) -> Option<LitStr> {
+    match lit {
+        Lit::Str(lit) => Some(lit.clone()),
+        _ => None,
+    }
+}
+
+/// The case conversions of serde's `rename_all`, applied to snake case field names.
+#[derive(Clone, Copy)]
+enum RenameRule {
+    Lower,
+    Upper,
+    Pascal,
+    Camel,
+    Snake,
+    ScreamingSnake,
+    Kebab,
+    ScreamingKebab,
+}
+
+impl RenameRule {
+    fn from_lit(lit: &LitStr) -> Result<Self> {
+        Ok(match lit.value().as_str() {
+            "lowercase" => Self::Lower,
+            "UPPERCASE" => Self::Upper,
+            "PascalCase" => Self::Pascal,
+            "camelCase" => Self::Camel,
+            "snake_case" => Self::Snake,
+            "SCREAMING_SNAKE_CASE" => Self::ScreamingSnake,
+            "kebab-case" => Self::Kebab,
+            "SCREAMING-KEBAB-CASE" => Self::ScreamingKebab,
+            _ => return Err(Error::new(lit.span(), "unknown rename rule")),
+        })
+    }
+
+    fn apply(self, field: &str) -> String {
+        match self {
+            Self::Lower | Self::Snake => field.to_string(),
+            Self::Upper | Self::ScreamingSnake => field.to_uppercase(),
+            Self::Pascal => field.split('_').map(capitalize).collect(),
+            Self::Camel => {
+                let pascal: String = field.split('_').map(capitalize).collect();
+                let mut chars = pascal.chars();
+                match chars.next() {
+                    Some(first) => first.to_lowercase().chain(chars).collect(),
+                    None => pascal,
+                }
+            }
+            Self::Kebab => field.replace('_', "-"),
+            Self::ScreamingKebab => field.replace('_', "-").to_uppercase(),
+        }
+    }
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    #[test]
+    fn expand_fields() {
+        let input: DeriveInput = parse_quote! {
+            #[serde(rename_all = "camelCase")]
+            pub struct Installation {
+                pub wappier_id: String,
+                #[serde(rename = "key")]
+                pub api_key: String,
+                #[serde(skip)]
+                pub cached: bool,
+                #[serde(rename(serialize = "ses", deserialize = "sessions"))]
+                pub sessions: Document,
+            }
+        };
+        let tokens = expand(&input).unwrap().to_string();
+
+        assert!(tokens.contains("pub const WAPPIER_ID : :: bongo_mong :: query :: Field < Self , String > = :: bongo_mong :: query :: Field :: new (\"wappierId\")"));
+        assert!(tokens.contains("Field :: new (\"key\")"));
+        assert!(tokens.contains("Field :: new (\"ses\")"));
+        assert!(!tokens.contains("CACHED"));
+    }
+
+    #[test]
+    fn rename_rules() {
+        let rule =
+            |rule: &str| RenameRule::from_lit(&LitStr::new(rule, proc_macro2::Span::call_site()));
+        assert_eq!(
+            rule("camelCase").unwrap().apply("language_id"),
+            "languageId"
+        );
+        assert_eq!(
+            rule("PascalCase").unwrap().apply("language_id"),
+            "LanguageId"
+        );
+        assert_eq!(
+            rule("SCREAMING-KEBAB-CASE").unwrap().apply("language_id"),
+            "LANGUAGE-ID"
+        );
+        assert!(rule("Title Case").is_err());
+    }
+}
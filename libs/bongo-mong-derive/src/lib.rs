@@ -0,0 +1,68 @@
+//! Derive macros for the `bongo-mong` dao traits and query builders.
+//!
+//! Re-exported by `bongo-mong` when its `derive` feature is enabled, so depend on that rather
+//! than on this crate.
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput};
+
+mod collection;
+mod fields;
+
+/// Implement `Collection`, `DbConnect` and `Query` for a struct with a `name` and a
+/// `pool_manager` field.
+///
+/// The struct also gets a `NAME` constant, a `new` constructor using it, and a `with_name`
+/// constructor for names read from configurations. Other fields are set to their defaults.
+///
+/// ```ignore
+/// use bongo_mong::{dao::Collection, PoolManager};
+///
+/// #[derive(Clone, Debug, Collection)]
+/// #[bongo(name = "users", model = User)]
+/// #[bongo(index(keys(id = 1), unique))]
+/// pub struct Users {
+///     name: String,
+///     pool_manager: PoolManager,
+/// }
+/// ```
+///
+/// The `bongo` attribute takes:
+/// * `name = "..."`: the default name of the collection, required.
+/// * `model = Type`: the type of the documents, required.
+/// * `permission = "read" | "write"`: the permission of the pools reads go through.
+/// * `index(keys(field = 1, "nested.field" = -1), unique, sparse, name = "...")`: an index
+///   the collection declares, once per index.
+/// * `soft_delete`: mark deleted documents instead of removing them.
+/// * `audit = "..."`: the name of the collection the writes are recorded in.
+#[proc_macro_derive(Collection, attributes(bongo))]
+pub fn derive_collection(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    collection::expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Add a constant to a model for every field it serializes, named after the field in upper case.
+///
+/// The constants are `bongo_mong::query::Field`s, naming the fields as serde serializes them,
+/// following the `rename`, `rename_all`, `skip` and `flatten` serde attributes.
+///
+/// ```ignore
+/// use bongo_mong::query::Fields;
+///
+/// #[derive(Deserialize, Serialize, Fields)]
+/// #[serde(rename_all = "camelCase")]
+/// pub struct User {
+///     pub id: String,
+///     pub language_id: String,
+/// }
+///
+/// let filter = User::LANGUAGE_ID.eq("en");
+/// ```
+#[proc_macro_derive(Fields, attributes(serde))]
+pub fn derive_fields(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    fields::expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
@@ -11,19 +11,14 @@ use bongo_mong::error::Result;
 use bongo_mong::PoolManager;
 use config::{Config, File};
 use mongodb::bson::doc;
-use once_cell::sync::Lazy;
 
-static POOLS: Lazy<PoolManager> = Lazy::new(|| {
+#[tokio::main]
+async fn main() -> Result<()> {
     let config = Config::builder()
         .add_source(File::with_name("examples/collection/mongo_pools.json"))
         .build()
         .unwrap();
-    PoolManager::new(config).unwrap()
-});
-
-#[tokio::main]
-async fn main() -> Result<()> {
-    let collection = Redemptions::new("redemptions", &POOLS);
+    let collection = Redemptions::new(PoolManager::new(config)?);
 
     // Insert documents
     let mut insertions = vec![];
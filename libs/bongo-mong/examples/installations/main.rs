@@ -11,21 +11,16 @@ use bongo_mong::error::Result;
 use bongo_mong::PoolManager;
 use config::{Config, File};
 use mongodb::bson;
-use once_cell::sync::Lazy;
 
 mod fixtures;
 
-static POOLS: Lazy<PoolManager> = Lazy::new(|| {
+#[tokio::main]
+async fn main() -> Result<()> {
     let config = Config::builder()
         .add_source(File::with_name("examples/installations/mongo_pools.json"))
         .build()
         .unwrap();
-    PoolManager::new(config).unwrap()
-});
-
-#[tokio::main]
-async fn main() -> Result<()> {
-    let collection = Installations::new(&POOLS);
+    let collection = Installations::new(PoolManager::new(config)?);
     let api_key = "cff9dc8b-1e74-461e-9f76-0337f77d77ed";
 
     // Insert documents
@@ -0,0 +1,102 @@
+//! An audit trail of the writes made through [`Query`](crate::dao::Query).
+//!
+//! Collections with an [`audit_collection`](crate::dao::Collection::audit_collection) record
+//! their inserts, updates and deletes in it as [`AuditRecord`]s. Who made a write is read
+//! from the task making it, see [`with_actor`].
+use crate::error::{BongoError::DaoError, Result};
+use mongodb::bson::{self, Bson, DateTime, Document};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+
+tokio::task_local! {
+    /// Who the writes of the current task are made by.
+    static ACTOR: String;
+}
+
+/// Run `future`, recording the writes it makes as made by `actor`.
+pub async fn with_actor<F: Future>(actor: impl Into<String>, future: F) -> F::Output {
+    ACTOR.scope(actor.into(), future).await
+}
+
+/// Get who the writes of the current task are made by, if set by [`with_actor`].
+pub fn actor() -> Option<String> {
+    ACTOR.try_with(Clone::clone).ok()
+}
+
+/// A write to a collection, as recorded in its audit collection.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditRecord {
+    /// The name of the collection written to.
+    pub collection: String,
+    /// The dao operation that made the write, e.g. `update_one`.
+    pub operation: String,
+    /// Who made the write, if known.
+    pub actor: Option<String>,
+    /// The filter of the documents written to, if any.
+    pub filter: Option<Document>,
+    /// The inserted documents, the update or the replacement, if any.
+    pub change: Option<Bson>,
+    /// When the write was made.
+    pub at: DateTime,
+}
+
+impl AuditRecord {
+    /// Record a write made now, by the actor of the current task.
+    pub fn new(
+        collection: impl Into<String>,
+        operation: impl Into<String>,
+        filter: Option<Document>,
+        change: Option<Bson>,
+    ) -> Self {
+        Self {
+            collection: collection.into(),
+            operation: operation.into(),
+            actor: actor(),
+            filter,
+            change,
+            at: DateTime::now(),
+        }
+    }
+}
+
+/// Encode the change of a write, as recorded.
+pub(crate) fn change<T: Serialize + ?Sized>(change: &T) -> Result<Bson> {
+    bson::to_bson(change).map_err(|err| DaoError(err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::{bson::doc, options::UpdateModifications};
+
+    #[tokio::test]
+    async fn records_actor_of_task() {
+        let record = |operation| AuditRecord::new("users", operation, None, None);
+        assert_eq!(record("insert_one").actor, None);
+
+        let record = with_actor("maria", async { record("delete_one") }).await;
+        assert_eq!(record.actor.as_deref(), Some("maria"));
+        assert_eq!(actor(), None);
+    }
+
+    #[test]
+    fn encodes_changes() {
+        let update = UpdateModifications::Document(doc! {"$set": {"age": 41}});
+        assert_eq!(
+            change(&update).unwrap(),
+            Bson::Document(doc! {"$set": {"age": 41}})
+        );
+
+        let pipeline = UpdateModifications::Pipeline(vec![doc! {"$unset": "age"}]);
+        assert_eq!(
+            change(&pipeline).unwrap(),
+            Bson::Array(vec![Bson::Document(doc! {"$unset": "age"})])
+        );
+
+        let record = AuditRecord::new("users", "insert_one", None, None);
+        let stored = bson::to_document(&record).unwrap();
+        assert!(stored.contains_key("at"));
+        assert_eq!(bson::from_document::<AuditRecord>(stored).unwrap(), record);
+    }
+}
@@ -0,0 +1,210 @@
+//! The collections the operations of [`Query`](crate::dao::Query) run on.
+use crate::dao::Cursor;
+use crate::error::Result;
+use crate::results::{DeleteResult, InsertManyResult, InsertOneResult, UpdateResult};
+#[cfg(feature = "testing")]
+use crate::testing::MemoryCollection;
+use mongodb::{
+    bson::{Bson, Document},
+    options,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::borrow::Borrow;
+
+/// A collection of a Mongo Db database, or of a memory store with the `testing` feature.
+///
+/// See [`Pool::backend`](crate::Pool::backend).
+#[derive(Debug)]
+pub(crate) enum Backend<D> {
+    Mongo(mongodb::Collection<D>),
+    #[cfg(feature = "testing")]
+    Memory(MemoryCollection<D>),
+}
+
+impl<D> Backend<D>
+where
+    D: DeserializeOwned + Serialize + Send + Sync + Unpin,
+{
+    pub(crate) async fn find(
+        &self,
+        filter: Option<Document>,
+        options: Option<options::FindOptions>,
+    ) -> Result<Cursor<D>> {
+        match self {
+            Self::Mongo(collection) => Ok(collection.find(filter, options).await?.into()),
+            #[cfg(feature = "testing")]
+            Self::Memory(collection) => Ok(collection.find(filter, options)?.into()),
+        }
+    }
+
+    pub(crate) async fn find_one(
+        &self,
+        filter: Option<Document>,
+        options: Option<options::FindOneOptions>,
+    ) -> Result<Option<D>> {
+        match self {
+            Self::Mongo(collection) => Ok(collection.find_one(filter, options).await?),
+            #[cfg(feature = "testing")]
+            Self::Memory(collection) => collection.find_one(filter, options),
+        }
+    }
+
+    pub(crate) async fn insert_one(
+        &self,
+        doc: impl Borrow<D>,
+        options: Option<options::InsertOneOptions>,
+    ) -> Result<InsertOneResult> {
+        match self {
+            Self::Mongo(collection) => Ok(collection.insert_one(doc, options).await?.into()),
+            #[cfg(feature = "testing")]
+            Self::Memory(collection) => collection.insert_one(doc.borrow()),
+        }
+    }
+
+    pub(crate) async fn insert_many<B: Borrow<D>>(
+        &self,
+        docs: Vec<B>,
+        options: Option<options::InsertManyOptions>,
+    ) -> Result<InsertManyResult> {
+        match self {
+            Self::Mongo(collection) => Ok(collection.insert_many(docs, options).await?.into()),
+            #[cfg(feature = "testing")]
+            Self::Memory(collection) => collection.insert_many(docs),
+        }
+    }
+
+    pub(crate) async fn update_one(
+        &self,
+        query: Document,
+        update: options::UpdateModifications,
+        options: Option<options::UpdateOptions>,
+    ) -> Result<UpdateResult> {
+        match self {
+            Self::Mongo(collection) => {
+                Ok(collection.update_one(query, update, options).await?.into())
+            }
+            #[cfg(feature = "testing")]
+            Self::Memory(collection) => collection.update_one(query, update, options),
+        }
+    }
+
+    pub(crate) async fn update_many(
+        &self,
+        query: Document,
+        update: options::UpdateModifications,
+        options: Option<options::UpdateOptions>,
+    ) -> Result<UpdateResult> {
+        match self {
+            Self::Mongo(collection) => {
+                Ok(collection.update_many(query, update, options).await?.into())
+            }
+            #[cfg(feature = "testing")]
+            Self::Memory(collection) => collection.update_many(query, update, options),
+        }
+    }
+
+    pub(crate) async fn replace_one(
+        &self,
+        query: Document,
+        replacement: impl Borrow<D>,
+        options: Option<options::ReplaceOptions>,
+    ) -> Result<UpdateResult> {
+        match self {
+            Self::Mongo(collection) => Ok(collection
+                .replace_one(query, replacement, options)
+                .await?
+                .into()),
+            #[cfg(feature = "testing")]
+            Self::Memory(collection) => {
+                collection.replace_one(query, replacement.borrow(), options)
+            }
+        }
+    }
+
+    pub(crate) async fn delete_one(
+        &self,
+        query: Document,
+        options: Option<options::DeleteOptions>,
+    ) -> Result<DeleteResult> {
+        match self {
+            Self::Mongo(collection) => Ok(collection.delete_one(query, options).await?.into()),
+            #[cfg(feature = "testing")]
+            Self::Memory(collection) => collection.delete_one(query),
+        }
+    }
+
+    pub(crate) async fn delete_many(
+        &self,
+        query: Document,
+        options: Option<options::DeleteOptions>,
+    ) -> Result<DeleteResult> {
+        match self {
+            Self::Mongo(collection) => Ok(collection.delete_many(query, options).await?.into()),
+            #[cfg(feature = "testing")]
+            Self::Memory(collection) => collection.delete_many(query),
+        }
+    }
+
+    pub(crate) async fn find_one_and_update(
+        &self,
+        filter: Document,
+        update: options::UpdateModifications,
+        options: Option<options::FindOneAndUpdateOptions>,
+    ) -> Result<Option<D>> {
+        match self {
+            Self::Mongo(collection) => Ok(collection
+                .find_one_and_update(filter, update, options)
+                .await?),
+            #[cfg(feature = "testing")]
+            Self::Memory(collection) => collection.find_one_and_update(filter, update, options),
+        }
+    }
+
+    pub(crate) async fn find_one_and_delete(
+        &self,
+        filter: Document,
+        options: Option<options::FindOneAndDeleteOptions>,
+    ) -> Result<Option<D>> {
+        match self {
+            Self::Mongo(collection) => Ok(collection.find_one_and_delete(filter, options).await?),
+            #[cfg(feature = "testing")]
+            Self::Memory(collection) => collection.find_one_and_delete(filter, options),
+        }
+    }
+
+    pub(crate) async fn count_documents(
+        &self,
+        filter: Option<Document>,
+        options: Option<options::CountOptions>,
+    ) -> Result<u64> {
+        match self {
+            Self::Mongo(collection) => Ok(collection.count_documents(filter, options).await?),
+            #[cfg(feature = "testing")]
+            Self::Memory(collection) => collection.count_documents(filter),
+        }
+    }
+
+    pub(crate) async fn estimated_document_count(
+        &self,
+        options: Option<options::EstimatedDocumentCountOptions>,
+    ) -> Result<u64> {
+        match self {
+            Self::Mongo(collection) => Ok(collection.estimated_document_count(options).await?),
+            #[cfg(feature = "testing")]
+            Self::Memory(collection) => collection.estimated_document_count(),
+        }
+    }
+
+    pub(crate) async fn distinct(
+        &self,
+        field_name: &str,
+        filter: Option<Document>,
+        options: Option<options::DistinctOptions>,
+    ) -> Result<Vec<Bson>> {
+        match self {
+            Self::Mongo(collection) => Ok(collection.distinct(field_name, filter, options).await?),
+            #[cfg(feature = "testing")]
+            Self::Memory(collection) => collection.distinct(field_name, filter),
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! The cache of pools of a pool manager.
+use crate::config::options::MongoDbUri;
+use crate::error::Result;
+use crate::health::CachedPool;
+#[cfg(feature = "testing")]
+use crate::testing::MemoryStore;
+use mongodb::{options::ClientOptions, Client};
+use parking_lot::{Mutex, RwLock};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+
+/// A cached pool, built once by the first caller that needs it.
+pub(crate) type Slot = OnceCell<CachedPool>;
+
+/// Pools by the path of their configuration, and the clients they share.
+#[derive(Debug, Default)]
+pub(crate) struct Cache {
+    pub(crate) pools: RwLock<HashMap<String, Arc<Slot>>>,
+    /// Clients by connection string, shared by the pools connecting with the same one.
+    clients: Mutex<HashMap<MongoDbUri, Client>>,
+    /// The store the pools are on instead, in tests.
+    #[cfg(feature = "testing")]
+    pub(crate) memory: Option<MemoryStore>,
+}
+
+impl Cache {
+    /// Create a cache whose pools are on a memory store.
+    #[cfg(feature = "testing")]
+    pub(crate) fn with_memory_store(store: MemoryStore) -> Self {
+        Self {
+            memory: Some(store),
+            ..Default::default()
+        }
+    }
+
+    /// Get the slot of the pool cached under `key`, adding an empty one if missing.
+    pub(crate) fn slot(&self, key: String) -> Arc<Slot> {
+        if let Some(slot) = self.pools.read().get(&key) {
+            return slot.clone();
+        }
+        self.pools.write().entry(key).or_default().clone()
+    }
+
+    /// A snapshot of the cached slots and their keys.
+    pub(crate) fn slots(&self) -> Vec<(String, Arc<Slot>)> {
+        self.pools
+            .read()
+            .iter()
+            .map(|(key, slot)| (key.clone(), slot.clone()))
+            .collect()
+    }
+
+    /// Get the client connecting with `uri`, building it from `options` if there is none.
+    ///
+    /// With the `metrics` feature, the connection pool events of the client are reported, and
+    /// with the `tracing` feature, its command events are.
+    pub(crate) fn client(&self, uri: &MongoDbUri, options: ClientOptions) -> Result<Client> {
+        let mut clients = self.clients.lock();
+        if let Some(client) = clients.get(uri) {
+            return Ok(client.clone());
+        }
+        #[cfg(feature = "metrics")]
+        let options = {
+            let mut options = options;
+            options.cmap_event_handler = Some(Arc::new(crate::metrics::CmapMetrics));
+            options
+        };
+        #[cfg(feature = "tracing")]
+        let options = {
+            let mut options = options;
+            options.command_event_handler = Some(Arc::new(crate::tracing::CommandTracing));
+            options
+        };
+        let client = Client::with_options(options)?;
+        clients.insert(uri.clone(), client.clone());
+        Ok(client)
+    }
+
+    /// The number of distinct clients.
+    pub(crate) fn client_count(&self) -> usize {
+        self.clients.lock().len()
+    }
+
+    /// Remove the pool cached under `key`, unless it was replaced in the meantime.
+    ///
+    /// The client of the pool is dropped as well if it `failed`, so that it is not reused when
+    /// the pool is rebuilt, or if no other pool shares it.
+    pub(crate) fn evict(&self, key: &str, slot: &Arc<Slot>, failed: bool) {
+        let mut pools = self.pools.write();
+        if !matches!(pools.get(key), Some(cached) if Arc::ptr_eq(cached, slot)) {
+            return;
+        }
+        pools.remove(key);
+        #[cfg(feature = "metrics")]
+        if let Some(entry) = slot.get() {
+            crate::metrics::record_pool_removed(entry.path());
+        }
+
+        if let Some(uri) = slot.get().map(CachedPool::uri) {
+            let shared = pools
+                .values()
+                .filter_map(|slot| slot.get())
+                .any(|entry| entry.uri() == uri);
+            if failed || !shared {
+                self.clients.lock().remove(uri);
+            }
+        }
+    }
+}
@@ -1,11 +1,12 @@
 //! The interface for the "installations" collection.
-use crate::dao::{self, Query};
+use crate::dao::{Collection, Query};
 use crate::error::Result;
+use crate::query::{Fields, Filter};
 use crate::PoolManager;
 use mongodb::{bson, options};
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, Fields)]
 pub struct Installation {
     #[serde(rename = "wappierId")]
     pub wappier_id: String,
@@ -14,35 +15,28 @@ pub struct Installation {
     pub sessions: bson::Document,
 }
 
-#[derive(Clone, Debug)]
-pub struct Installations<'a> {
+#[derive(Clone, Debug, Collection)]
+#[bongo(name = "installations", model = Installation)]
+#[bongo(index(keys(wappierId = 1, apiKey = 1), unique))]
+pub struct Installations {
     name: String,
-    pool_manager: &'a PoolManager,
+    pool_manager: PoolManager,
 }
 
-impl Installations<'_> {
-    const NAME: &'static str = "installations";
-}
-
-impl<'a> Installations<'a> {
-    pub fn new(pool_manager: &'a PoolManager) -> Self {
-        Self {
-            name: Installations::NAME.into(),
-            pool_manager,
-        }
-    }
-
+impl Installations {
     pub async fn assert_session_active(
         &self,
         wappier_id: &str,
         api_key: &str,
         session_id: &str,
     ) -> Result<bool> {
-        let query = bson::doc! {
-            "wappierId": wappier_id,
-            "apiKey": api_key,
-            format!("sessions.{}.active", session_id): true
-        };
+        let query = Installation::WAPPIER_ID
+            .eq(wappier_id)
+            .and(Installation::API_KEY.eq(api_key))
+            .and(Filter::eq(
+                Installation::SESSIONS.key(session_id).key("active"),
+                true,
+            ));
         let projection = bson::doc! {
             "_id": 0_i32,
             "wappierId": 1_i32,
@@ -55,17 +49,3 @@ impl<'a> Installations<'a> {
         Ok(self.find_one(query, opts, Some(api_key)).await?.is_some())
     }
 }
-
-impl<'a> dao::Collection for Installations<'a> {
-    fn name(&self) -> &str {
-        &self.name
-    }
-}
-
-impl<'a> dao::DbConnect for Installations<'a> {
-    fn pool_manager(&self) -> &PoolManager {
-        self.pool_manager
-    }
-}
-
-impl<'a> dao::Query<Installation> for Installations<'a> {}
@@ -9,3 +9,16 @@
 //! in the `dependencies` section of your `Cargo.toml`.
 pub mod installations;
 pub mod redemptions;
+
+#[cfg(test)]
+mod tests {
+    use super::{installations::Installations, redemptions::Redemptions};
+
+    fn assert_owned<T: Clone + Send + Sync + 'static>() {}
+
+    #[test]
+    fn collections_are_owned() {
+        assert_owned::<Installations>();
+        assert_owned::<Redemptions>();
+    }
+}
@@ -4,12 +4,15 @@
 //! the crate.
 pub mod options;
 pub mod path;
+pub mod secrets;
+mod validation;
 
-use super::error::Result;
-use config::{Config, ConfigError, Value};
+use super::error::{BongoError, Result};
+use config::{Config, Value};
 use options::{BongoClientOptions, BongoOptions, PoolPermissionType};
 use path::{AppPath, CollectionPath, ConfigPath, PermissionPath};
 use std::collections::HashMap;
+use std::fmt;
 
 type LeafConfig = HashMap<String, Value>;
 type PermissionConfig = HashMap<PoolPermissionType, LeafConfig>;
@@ -17,7 +20,9 @@ type CollectionConfig = HashMap<String, PermissionConfig>;
 type AppConfig = HashMap<String, CollectionConfig>;
 
 /// Configuration repository for the pool manager.
-#[derive(Clone, Debug, Default)]
+///
+/// The `Debug` output redacts passwords, see [`secrets::redact`].
+#[derive(Clone, Default)]
 pub struct BongoConfig {
     pub src: Config,
     global: Option<PermissionConfig>,
@@ -25,6 +30,14 @@ pub struct BongoConfig {
     app: Option<AppConfig>,
 }
 
+impl fmt::Debug for BongoConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BongoConfig")
+            .field("src", &secrets::redact(self.cache()))
+            .finish_non_exhaustive()
+    }
+}
+
 impl BongoConfig {
     /// Create a new configuration.
     ///
@@ -57,7 +70,21 @@ impl BongoConfig {
     ///     }
     /// }
     /// ```
+    ///
+    /// If the configuration does not follow the schema, every problem found is reported at once
+    /// with [`BongoError::InvalidConfig`].
     pub fn new(config: Config) -> Result<Self> {
+        Self::parse(config.clone()).map_err(|err| {
+            let issues = validation::check(&config.cache);
+            if issues.is_empty() {
+                err
+            } else {
+                BongoError::InvalidConfig(issues)
+            }
+        })
+    }
+
+    fn parse(config: Config) -> Result<Self> {
         let mut config = Self {
             src: config,
             ..Default::default()
@@ -76,7 +103,7 @@ impl BongoConfig {
             config.global = Some(global);
         }
         // Cache per-app configuration
-        if let Some(cfg) = cfg_map.remove("mongodbPerApp") {
+        if let Some(cfg) = cfg_map.remove(validation::PER_APP) {
             config.app = Some(cfg.try_deserialize()?);
         }
         // Cache collections
@@ -90,6 +117,23 @@ impl BongoConfig {
         Ok(config)
     }
 
+    /// Validate the configuration against the supported options.
+    ///
+    /// Unknown options, values of the wrong type, unknown permissions and sections without a
+    /// base URI to inherit are all collected, rather than failing on the first one.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`BongoError::InvalidConfig`] listing every problem found.
+    pub fn validate(&self) -> Result<()> {
+        let issues = validation::check(self.cache());
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(BongoError::InvalidConfig(issues))
+        }
+    }
+
     /// Resolve configuration path based on set of given parameters.
     ///
     /// The method checks if the application path exists, othewise it checks
@@ -151,6 +195,11 @@ impl BongoConfig {
         false
     }
 
+    /// Check if the configuration has a section for the application of `api_key`.
+    pub fn has_app(&self, api_key: &str) -> bool {
+        matches!(self.app(), Some(cfg) if cfg.contains_key(api_key))
+    }
+
     /// Check if the configuration contains the application options
     /// for the given path.
     pub fn contains_app(&self, path: &AppPath) -> bool {
@@ -195,66 +244,27 @@ impl BongoConfig {
 
     /// Get global configuration for the given permission path.
     fn global_config(&self, path: &PermissionPath) -> Result<&LeafConfig> {
-        Ok(self
-            .global()
-            .ok_or_else(|| ConfigError::NotFound("global configuration missing".into()))?
-            .get(&path.permission())
-            .ok_or_else(|| {
-                ConfigError::NotFound(format!(
-                    "missing global configuration for {}",
-                    path.permission()
-                ))
-            })?)
+        self.global()
+            .and_then(|cfg| cfg.get(&path.permission()))
+            .ok_or_else(|| BongoError::MissingConfig(path.into()))
     }
 
     /// Get collections configuration for the given collection path.
     fn collection_config(&self, path: &CollectionPath) -> Result<&LeafConfig> {
-        Ok(self
-            .collections()
-            .ok_or_else(|| {
-                ConfigError::NotFound("collection-specific configuration missing".into())
-            })?
-            .get(path.collection())
-            .ok_or_else(|| {
-                ConfigError::NotFound(format!(
-                    "missing configuration for {} in collections",
-                    path.collection()
-                ))
-            })?
-            .get(&path.permission_path().permission())
-            .ok_or_else(|| {
-                ConfigError::NotFound(format!(
-                    "missing configuration for {} permission in {}",
-                    path.permission_path().permission(),
-                    path.collection()
-                ))
-            })?)
+        self.collections()
+            .and_then(|cfg| cfg.get(path.collection()))
+            .and_then(|cfg| cfg.get(&path.permission_path().permission()))
+            .ok_or_else(|| BongoError::MissingConfig(path.into()))
     }
 
     /// Get collection configuration for the given applicatin path.
     fn app_config(&self, path: &AppPath) -> Result<&LeafConfig> {
-        let api_key = path.api_key();
-        let collection = path.collection_path().collection();
-        let permission = path.collection_path().permission_path().permission();
-        Ok(self
-            .app()
-            .ok_or_else(|| ConfigError::NotFound("per-app configuration missing".into()))?
-            .get(api_key)
-            .ok_or_else(|| ConfigError::NotFound(format!("missing configuration for {}", api_key)))?
-            .get(collection)
-            .ok_or_else(|| {
-                ConfigError::NotFound(format!(
-                    "missing configuration for {} in {}",
-                    collection, api_key
-                ))
-            })?
-            .get(&permission)
-            .ok_or_else(|| {
-                ConfigError::NotFound(format!(
-                    "missing configuration for {} permission in {}-{}",
-                    permission, api_key, collection
-                ))
-            })?)
+        let collection_path = path.collection_path();
+        self.app()
+            .and_then(|cfg| cfg.get(path.api_key()))
+            .and_then(|cfg| cfg.get(collection_path.collection()))
+            .and_then(|cfg| cfg.get(&collection_path.permission_path().permission()))
+            .ok_or_else(|| BongoError::MissingConfig(path.into()))
     }
 
     /// Merge base values onto a configuration
@@ -433,6 +443,8 @@ mod tests {
         assert!(!config.contains_app(&r));
         let w = AppPath::new("wat", "redemptions", PoolPermissionType::Write);
         assert!(config.contains_app(&w));
+        assert!(config.has_app("wat"));
+        assert!(!config.has_app("redemptions"));
     }
 
     #[test]
@@ -670,4 +682,27 @@ mod tests {
         let opts = config.to_global_opts(&path).await;
         assert!(opts.is_err());
     }
+
+    #[test]
+    fn bongo_config_new_reports_invalid_config() {
+        let source = r#"{
+            "read" : {
+                "baseUri": "mongodb://wat.com/"
+            },
+            "redemptions": "mongodb://wat.com/"
+        }
+        "#;
+
+        let config = Config::builder()
+            .add_source(File::from_str(source, FileFormat::Json))
+            .build()
+            .unwrap();
+        match BongoConfig::new(config) {
+            Err(BongoError::InvalidConfig(issues)) => {
+                assert_eq!(issues.len(), 1);
+                assert_eq!(issues[0].path().to_string(), "redemptions");
+            }
+            other => panic!("expected an invalid configuration, got {:?}", other),
+        }
+    }
 }
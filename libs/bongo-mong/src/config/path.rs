@@ -230,6 +230,17 @@ pub enum ConfigPath {
     App(AppPath),
 }
 
+impl ConfigPath {
+    /// The kind of the path, either `global`, `collection` or `app`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Global(_) => "global",
+            Self::Collection(_) => "collection",
+            Self::App(_) => "app",
+        }
+    }
+}
+
 impl fmt::Display for ConfigPath {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -0,0 +1,378 @@
+//! Validation of configurations against the supported options.
+use super::options::LooseOption;
+use super::path::TraversalPath;
+use super::secrets::{self, CREDENTIAL_OPTIONS, FILE_SUFFIX, PASSWORD, USERNAME};
+use crate::error::{BongoError, ConfigIssue};
+use config::{Map, Value, ValueKind};
+
+/// The key of the connection string, without its options.
+pub(crate) const BASE_URI: &str = "baseUri";
+/// The key of the per-app configurations.
+pub(crate) const PER_APP: &str = "mongodbPerApp";
+
+/// The kind of value an option takes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Kind {
+    Integer,
+    Boolean,
+    Text,
+}
+
+impl Kind {
+    fn accepts(self, value: &str) -> bool {
+        match self {
+            Self::Integer => value.parse::<i64>().is_ok(),
+            Self::Boolean => matches!(value, "true" | "false"),
+            Self::Text => true,
+        }
+    }
+
+    fn expected(self) -> &'static str {
+        match self {
+            Self::Integer => "an integer",
+            Self::Boolean => "true or false",
+            Self::Text => "a string",
+        }
+    }
+}
+
+/// The connection string options the driver supports.
+///
+/// See the [MongoDb documentation][mdb-opts].
+///
+/// [mdb-opts]: https://docs.mongodb.com/manual/reference/connection-string
+const CONNECTION_OPTIONS: &[(&str, Kind)] = &[
+    ("appName", Kind::Text),
+    ("authMechanism", Kind::Text),
+    ("authMechanismProperties", Kind::Text),
+    ("authSource", Kind::Text),
+    ("compressors", Kind::Text),
+    ("connectTimeoutMS", Kind::Integer),
+    ("directConnection", Kind::Boolean),
+    ("heartbeatFrequencyMS", Kind::Integer),
+    ("journal", Kind::Boolean),
+    ("loadBalanced", Kind::Boolean),
+    ("localThresholdMS", Kind::Integer),
+    ("maxConnecting", Kind::Integer),
+    ("maxIdleTimeMS", Kind::Integer),
+    ("maxPoolSize", Kind::Integer),
+    ("maxStalenessSeconds", Kind::Integer),
+    ("minPoolSize", Kind::Integer),
+    ("readConcernLevel", Kind::Text),
+    ("replicaSet", Kind::Text),
+    ("retryReads", Kind::Boolean),
+    ("retryWrites", Kind::Boolean),
+    ("serverSelectionTimeoutMS", Kind::Integer),
+    ("socketTimeoutMS", Kind::Integer),
+    ("srvMaxHosts", Kind::Integer),
+    ("ssl", Kind::Boolean),
+    ("tls", Kind::Boolean),
+    ("tlsAllowInvalidCertificates", Kind::Boolean),
+    ("tlsCAFile", Kind::Text),
+    ("tlsCertificateKeyFile", Kind::Text),
+    ("tlsInsecure", Kind::Boolean),
+    ("uuidRepresentation", Kind::Text),
+    ("w", Kind::Text),
+    ("waitQueueTimeoutMS", Kind::Integer),
+    ("wTimeoutMS", Kind::Integer),
+    ("zlibCompressionLevel", Kind::Integer),
+];
+
+/// Check a configuration source, returning every problem found.
+pub(crate) fn check(source: &Value) -> Vec<ConfigIssue> {
+    let mut issues = Vec::new();
+    let root = match table(source, &[], "sections", &mut issues) {
+        Some(root) => root,
+        None => return issues,
+    };
+
+    for (key, value) in root {
+        if key == PER_APP {
+            check_apps(root, value, &mut issues);
+        } else if is_permission(key) {
+            check_options(value, &[key], &mut issues);
+        } else {
+            check_collection(root, value, &[key], &mut issues);
+        }
+    }
+    issues
+}
+
+fn check_apps(root: &Map<String, Value>, apps: &Value, issues: &mut Vec<ConfigIssue>) {
+    let apps = match table(apps, &[PER_APP], "apps", issues) {
+        Some(apps) => apps,
+        None => return,
+    };
+    for (api_key, collections) in apps {
+        let path = [PER_APP, api_key.as_str()];
+        if let Some(collections) = table(collections, &path, "collections", issues) {
+            for (collection, value) in collections {
+                let path = [PER_APP, api_key.as_str(), collection.as_str()];
+                check_collection(root, value, &path, issues);
+            }
+        }
+    }
+}
+
+/// Check the sections of a collection, inheriting the base URI from the sections above.
+fn check_collection(
+    root: &Map<String, Value>,
+    collection: &Value,
+    path: &[&str],
+    issues: &mut Vec<ConfigIssue>,
+) {
+    let permissions = match table(collection, path, "permission sections", issues) {
+        Some(permissions) => permissions,
+        None => return,
+    };
+    for (permission, options) in permissions {
+        let path: Vec<&str> = path.iter().copied().chain([permission.as_str()]).collect();
+        if !is_permission(permission) {
+            issues.push(ConfigIssue::UnknownPermission {
+                path: path.iter().copied().collect(),
+            });
+            continue;
+        }
+        if !check_options(options, &path, issues) {
+            continue;
+        }
+        if !inherits_base_uri(root, &path) {
+            issues.push(ConfigIssue::MissingBaseUri {
+                path: path.iter().copied().collect(),
+            });
+        }
+    }
+}
+
+/// Check the options of a section, returning whether it is a table at all.
+fn check_options(options: &Value, path: &[&str], issues: &mut Vec<ConfigIssue>) -> bool {
+    let options = match table(options, path, "options", issues) {
+        Some(options) => options,
+        None => return false,
+    };
+    for (option, value) in options {
+        let path: TraversalPath = path.iter().copied().chain([option.as_str()]).collect();
+        if let Ok(loose) = option.parse::<LooseOption>() {
+            if let Err(BongoError::InvalidOption {
+                expected, found, ..
+            }) = loose.check(value)
+            {
+                issues.push(ConfigIssue::InvalidValue {
+                    path,
+                    expected,
+                    found,
+                });
+            }
+            continue;
+        }
+        let kind = if option == BASE_URI || is_credential(option) {
+            Kind::Text
+        } else if let Some((_, kind)) = CONNECTION_OPTIONS.iter().find(|(name, _)| name == option) {
+            *kind
+        } else {
+            issues.push(ConfigIssue::UnknownOption {
+                path,
+                suggestion: suggestion(option),
+            });
+            continue;
+        };
+
+        let value = secrets::redact_option(option, value);
+        let found = match &value.kind {
+            ValueKind::Table(_) | ValueKind::Array(_) | ValueKind::Nil => None,
+            _ => value.clone().into_string().ok(),
+        };
+        match found {
+            Some(found) if option == BASE_URI && !is_connection_string(&found) => {
+                issues.push(ConfigIssue::InvalidValue {
+                    path,
+                    expected: "a mongodb:// or mongodb+srv:// connection string",
+                    found,
+                })
+            }
+            Some(found) if kind.accepts(&found) => {}
+            found => issues.push(ConfigIssue::InvalidValue {
+                path,
+                expected: kind.expected(),
+                found: found.unwrap_or_else(|| value.to_string()),
+            }),
+        }
+    }
+    true
+}
+
+/// Check if the section at `path`, or a section it inherits from, sets the base URI.
+///
+/// App sections inherit from the same collection and permission at the top level, and
+/// collection sections inherit from the global section of their permission.
+fn inherits_base_uri(root: &Map<String, Value>, path: &[&str]) -> bool {
+    let (collection, permission) = match path {
+        [PER_APP, _, collection, permission] | [collection, permission] => {
+            (*collection, *permission)
+        }
+        _ => return false,
+    };
+    let sections: [&[&str]; 3] = [path, &[collection, permission], &[permission]];
+    sections.iter().any(
+        |section| matches!(lookup(root, section), Some(options) if options.contains_key(BASE_URI)),
+    )
+}
+
+fn lookup<'a>(root: &'a Map<String, Value>, path: &[&str]) -> Option<&'a Map<String, Value>> {
+    let mut current = root;
+    for key in path {
+        current = match &current.get(*key)?.kind {
+            ValueKind::Table(table) => table,
+            _ => return None,
+        };
+    }
+    Some(current)
+}
+
+/// Get the table of a section, reporting it if the value is not one.
+fn table<'a>(
+    value: &'a Value,
+    path: &[&str],
+    expected: &'static str,
+    issues: &mut Vec<ConfigIssue>,
+) -> Option<&'a Map<String, Value>> {
+    match &value.kind {
+        ValueKind::Table(table) => Some(table),
+        _ => {
+            issues.push(ConfigIssue::InvalidSection {
+                path: path.iter().copied().collect(),
+                expected,
+            });
+            None
+        }
+    }
+}
+
+/// Check if the option is a credential given apart from the connection string.
+fn is_credential(option: &str) -> bool {
+    matches!(option, USERNAME | PASSWORD)
+        || matches!(option.strip_suffix(FILE_SUFFIX), Some(stem) if CREDENTIAL_OPTIONS.contains(&stem))
+}
+
+/// Check if the key names a permission section, as spelled in configurations.
+fn is_permission(key: &str) -> bool {
+    matches!(key, "read" | "write")
+}
+
+fn is_connection_string(uri: &str) -> bool {
+    uri.starts_with("mongodb://") || uri.starts_with("mongodb+srv://")
+}
+
+/// The supported option the given one differs from only by case, if any.
+fn suggestion(option: &str) -> Option<&'static str> {
+    CONNECTION_OPTIONS
+        .iter()
+        .map(|(name, _)| *name)
+        .chain([BASE_URI, USERNAME, PASSWORD])
+        .find(|name| name.eq_ignore_ascii_case(option))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::{Config, File, FileFormat};
+
+    fn issues(source: &str) -> Vec<ConfigIssue> {
+        let config = Config::builder()
+            .add_source(File::from_str(source, FileFormat::Json))
+            .build()
+            .unwrap();
+        check(&config.cache)
+    }
+
+    fn path(keys: &[&str]) -> TraversalPath {
+        keys.iter().copied().collect()
+    }
+
+    #[test]
+    fn check_valid_config() {
+        let source = r#"{
+            "read" : {
+                "baseUri": "mongodb://wat.com/",
+                "maxPoolSize": "15"
+            },
+            "redemptions": {
+                "write" : {
+                    "baseUri": "mongodb://wat.com/",
+                    "username": "wat",
+                    "password_FILE": "/run/secrets/wat",
+                    "retryWrites": "true",
+                    "collection": "redemptions_v2",
+                    "writeConcern": {"w": "majority", "wTimeoutMS": "500"}
+                }
+            },
+            "mongodbPerApp": {
+                "wat": {
+                    "redemptions": {
+                        "read" : {
+                            "connectTimeoutMS": 150,
+                            "readPreference": "secondaryPreferred",
+                            "readPreferenceTags": ["dc:ny,rack:1", ""],
+                            "maxTimeMS": "2000"
+                        }
+                    }
+                }
+            }
+        }"#;
+        assert_eq!(issues(source), vec![]);
+    }
+
+    #[test]
+    fn check_reports_every_issue() {
+        let source = r#"{
+            "read" : {
+                "baseUri": "wat.com",
+                "maxPoolsize": "15"
+            },
+            "redemptions": {
+                "raed" : {},
+                "write" : {
+                    "connectTimeoutMS": "soon",
+                    "readPreference": "secondary-ish"
+                }
+            },
+            "maxPoolSize": "10"
+        }"#;
+        let mut found = issues(source);
+        found.sort_by_key(|issue| issue.path().to_string());
+        assert_eq!(
+            found,
+            vec![
+                ConfigIssue::InvalidSection {
+                    path: path(&["maxPoolSize"]),
+                    expected: "permission sections",
+                },
+                ConfigIssue::InvalidValue {
+                    path: path(&["read", "baseUri"]),
+                    expected: "a mongodb:// or mongodb+srv:// connection string",
+                    found: "wat.com".into(),
+                },
+                ConfigIssue::UnknownOption {
+                    path: path(&["read", "maxPoolsize"]),
+                    suggestion: Some("maxPoolSize"),
+                },
+                ConfigIssue::UnknownPermission {
+                    path: path(&["redemptions", "raed"]),
+                },
+                ConfigIssue::MissingBaseUri {
+                    path: path(&["redemptions", "write"]),
+                },
+                ConfigIssue::InvalidValue {
+                    path: path(&["redemptions", "write", "connectTimeoutMS"]),
+                    expected: "an integer",
+                    found: "soon".into(),
+                },
+                ConfigIssue::InvalidValue {
+                    path: path(&["redemptions", "write", "readPreference"]),
+                    expected: "primary, primaryPreferred, secondary, secondaryPreferred or nearest",
+                    found: "secondary-ish".into(),
+                },
+            ]
+        );
+    }
+}
@@ -1,32 +1,110 @@
 //! Module implementing the data-access object pattern.
 
+use super::audit::{self, AuditRecord};
 use super::config::options::PoolPermissionType;
-use super::error::{BongoError::DaoError, Result};
+use super::error::{
+    BongoError::{self, DaoError},
+    Result,
+};
+use super::query::{Filter, Update};
+use super::results::{DeleteResult, InsertManyResult, InsertOneResult, UpdateResult};
 use super::{Pool, PoolManager};
 use async_trait::async_trait;
+use futures_core::Stream;
 use mongodb::{
     bson::{self, Document},
-    options, results, Cursor, Database,
+    change_stream::{
+        event::{ChangeStreamEvent, OperationType, ResumeToken, UpdateDescription},
+        ChangeStream,
+    },
+    options, ClientSession, Database, IndexModel, SessionCursor,
 };
 use serde::{de::DeserializeOwned, Serialize};
 use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
 
 /// An interface to Mongo db collections.
 pub trait Collection {
     /// Get the name of the collection
     fn name(&self) -> &str;
+
+    /// Get the indexes the collection is expected to have.
+    ///
+    /// See [`PoolManager::ensure_indexes`].
+    fn indexes(&self) -> Vec<IndexModel> {
+        Vec::new()
+    }
+
+    /// Get the permission of the pools read operations go through.
+    ///
+    /// Collections that must read their own writes can read from the write pools instead.
+    fn default_permission(&self) -> PoolPermissionType {
+        PoolPermissionType::default()
+    }
+
+    /// Check if the collection marks deleted documents with a [`DELETED_AT`] date instead of
+    /// removing them.
+    ///
+    /// In soft-delete mode, [`Query::delete_one`], [`Query::delete_many`] and
+    /// [`Query::find_one_and_delete`] mark the documents, and the other operations filtering
+    /// documents skip the marked ones. See [`Query::find_including_deleted`] and
+    /// [`Query::restore_one`] to get them back, and [`Query::purge_many`] to remove them.
+    fn soft_delete(&self) -> bool {
+        false
+    }
+
+    /// Get the name of the collection the writes to this collection are recorded in, if any.
+    ///
+    /// See [`DbConnect::audit`].
+    fn audit_collection(&self) -> Option<&str> {
+        None
+    }
+
+    /// Restrict a filter to the documents that are not deleted, in soft-delete mode.
+    ///
+    /// Applied by the operations of [`Query`], use it for the `$match` stages of
+    /// aggregations too.
+    fn live_filter(&self, filter: Option<Document>) -> Option<Document> {
+        if !self.soft_delete() {
+            return filter;
+        }
+        let live = Filter::exists(DELETED_AT, false);
+        let filter = match filter {
+            Some(filter) => Filter::from(filter).and(live),
+            None => live,
+        };
+        Some(filter.into())
+    }
 }
 
+/// The field holding when the documents of soft-delete collections were deleted.
+///
+/// See [`Collection::soft_delete`].
+pub const DELETED_AT: &str = "deletedAt";
+
+/// Derive [`Collection`], [`DbConnect`] and [`Query`] for a collection struct.
+///
+/// Available only if the `derive` feature is enabled.
+#[cfg(feature = "derive")]
+pub use bongo_mong_derive::Collection;
+
 /// An interface to handle connections to a Mongo Db
 #[async_trait]
 pub trait DbConnect: Collection {
     /// Get reference to a connection pool manager.
     fn pool_manager(&self) -> &PoolManager;
 
-    /// Get connection pool with read permissions.
+    /// Get the connection pool read operations go through.
+    ///
+    /// See [`Collection::default_permission`].
     async fn read_pool(&self, api_key: Option<&str>) -> Result<Pool> {
         self.pool_manager()
-            .collection_pool(PoolPermissionType::Read, self.name(), api_key)
+            .collection_pool(self.default_permission(), self.name(), api_key)
             .await
     }
 
@@ -37,38 +115,102 @@ pub trait DbConnect: Collection {
             .await
     }
 
+    /// Start a session on the write pool.
+    ///
+    /// The `*_with_session` operations of [`Query`] run on the write pool, so that they can
+    /// take part in the transactions of the session. The session can only be used with
+    /// collections on the same client, see [`session`](crate::session).
+    async fn start_session(
+        &self,
+        options: Option<options::SessionOptions>,
+        api_key: Option<&str>,
+    ) -> Result<ClientSession> {
+        self.write_pool(api_key).await?.start_session(options).await
+    }
+
     /// Get a connection to the read database.
     async fn read_database(&self, api_key: Option<&str>) -> Result<Database> {
-        self.read_pool(api_key)
-            .await?
-            .client()
-            .default_database()
-            .ok_or_else(|| DaoError("No read database configured".into()))
+        self.read_pool(api_key).await?.database()
     }
 
     /// Get a connection to the read database.
     async fn write_database(&self, api_key: Option<&str>) -> Result<Database> {
-        self.write_pool(api_key)
-            .await?
-            .client()
-            .default_database()
-            .ok_or_else(|| DaoError("No read database configured".into()))
+        self.write_pool(api_key).await?.database()
+    }
+
+    /// Record a write to the collection.
+    ///
+    /// Called by the write operations of [`Query`] once they succeed, for collections with an
+    /// [`audit_collection`](Collection::audit_collection), and with the session of the write
+    /// if any. Records are inserted in the audit collection of the database written to, so
+    /// that they take part in the transactions of the session. The collection name of the
+    /// loose options of the pool applies to the audited collection only.
+    ///
+    /// The write is not undone when recording it fails. Without a session, the failure is
+    /// logged with the `tracing` feature and the write succeeds. With a session, the error is
+    /// returned, so that the transaction can be aborted along with the write.
+    async fn audit(
+        &self,
+        record: AuditRecord,
+        session: Option<&mut ClientSession>,
+        api_key: Option<&str>,
+    ) -> Result<()> {
+        let name = match self.audit_collection() {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+        match session {
+            Some(session) => {
+                self.write_pool(api_key)
+                    .await?
+                    .named_collection::<AuditRecord>(name)?
+                    .insert_one_with_session(record, None, session)
+                    .await?;
+            }
+            None => {
+                let recorded = async {
+                    self.write_pool(api_key)
+                        .await?
+                        .named_backend::<AuditRecord>(name)?
+                        .insert_one(record, None)
+                        .await
+                };
+                if let Err(err) = recorded.await {
+                    #[cfg(feature = "tracing")]
+                    ::tracing::warn!(
+                        collection = self.name(),
+                        audit_collection = name,
+                        error = %err,
+                        "recording a write failed"
+                    );
+                    #[cfg(not(feature = "tracing"))]
+                    let _ = err;
+                }
+            }
+        }
+        Ok(())
     }
 }
 
 #[async_trait]
 pub trait Query<D: DeserializeOwned + Serialize + Send + Sync + Unpin>: DbConnect {
     /// Get a connection with read permissions on the collection.
+    ///
+    /// Fails for pools on a memory store, like the `*_with_session` operations.
     async fn read_collection(&self, api_key: Option<&str>) -> Result<mongodb::Collection<D>> {
         self.read_pool(api_key).await?.collection(self.name())
     }
 
     /// Get a connection with write permissions on the collection.
+    ///
+    /// Fails for pools on a memory store, like the `*_with_session` operations.
     async fn write_collection(&self, api_key: Option<&str>) -> Result<mongodb::Collection<D>> {
         self.write_pool(api_key).await?.collection(self.name())
     }
 
     /// Find all documents matching the given filter.
+    ///
+    /// In soft-delete mode, deleted documents are skipped.
     async fn find<'a, F, O>(
         &self,
         filter: F,
@@ -79,14 +221,34 @@ pub trait Query<D: DeserializeOwned + Serialize + Send + Sync + Unpin>: DbConnec
         F: Into<Option<bson::document::Document>> + Send + 'a,
         O: Into<Option<options::FindOptions>> + Send + 'a,
     {
-        Ok(self
-            .read_collection(api_key)
-            .await?
-            .find(filter, options)
-            .await?)
+        let filter = self.live_filter(filter.into());
+        self.find_including_deleted(filter, options, api_key).await
+    }
+
+    /// Find all documents matching the given filter, deleted ones included.
+    async fn find_including_deleted<'a, F, O>(
+        &self,
+        filter: F,
+        options: O,
+        api_key: Option<&str>,
+    ) -> Result<Cursor<D>>
+    where
+        F: Into<Option<bson::document::Document>> + Send + 'a,
+        O: Into<Option<options::FindOptions>> + Send + 'a,
+    {
+        instrument(self.name(), "find", async {
+            let filter: Option<Document> = filter.into();
+            record_filter(filter.as_ref());
+            let pool = self.read_pool(api_key).await?;
+            let options = with_max_time(options.into(), &pool)?;
+            pool.backend(self.name())?.find(filter, options).await
+        })
+        .await
     }
 
     /// Find one document.
+    ///
+    /// In soft-delete mode, deleted documents are skipped.
     async fn find_one<'a, F, O>(
         &self,
         filter: F,
@@ -97,14 +259,37 @@ pub trait Query<D: DeserializeOwned + Serialize + Send + Sync + Unpin>: DbConnec
         F: Into<Option<bson::document::Document>> + Send + 'a,
         O: Into<Option<options::FindOneOptions>> + Send + 'a,
     {
-        Ok(self
-            .read_collection(api_key)
-            .await?
-            .find_one(filter, options)
-            .await?)
+        let filter = self.live_filter(filter.into());
+        self.find_one_including_deleted(filter, options, api_key)
+            .await
     }
 
-    ///Delete one document.
+    /// Find one document, deleted ones included.
+    async fn find_one_including_deleted<'a, F, O>(
+        &self,
+        filter: F,
+        options: O,
+        api_key: Option<&str>,
+    ) -> Result<Option<D>>
+    where
+        F: Into<Option<bson::document::Document>> + Send + 'a,
+        O: Into<Option<options::FindOneOptions>> + Send + 'a,
+    {
+        instrument(self.name(), "find_one", async {
+            let filter: Option<Document> = filter.into();
+            record_filter(filter.as_ref());
+            let pool = self.read_pool(api_key).await?;
+            let options = with_max_time(options.into(), &pool)?;
+            pool.backend::<D>(self.name())?
+                .find_one(filter, options)
+                .await
+        })
+        .await
+    }
+
+    /// Delete one document.
+    ///
+    /// In soft-delete mode, the document is marked deleted instead.
     async fn delete_one<'a, O>(
         &self,
         query: Document,
@@ -114,11 +299,34 @@ pub trait Query<D: DeserializeOwned + Serialize + Send + Sync + Unpin>: DbConnec
     where
         O: Into<Option<options::DeleteOptions>> + Send + 'a,
     {
-        self.write_collection(api_key)
-            .await?
-            .delete_one(query, options)
-            .await?;
-        Ok(())
+        instrument(self.name(), "delete_one", async {
+            let deletion = self.soft_delete().then(mark_deleted);
+            let record = audit_record(self, "delete_one", Some(&query), deletion.as_ref())?;
+            let query = self.live_filter(Some(query)).unwrap_or_default();
+            record_filter(Some(&query));
+            let collection = self.write_pool(api_key).await?.backend::<D>(self.name())?;
+            let changed = match deletion {
+                Some(deletion) => {
+                    let options = update_options(options.into());
+                    let updated = collection
+                        .update_one(query, deletion.into(), options)
+                        .await?;
+                    updated.modified_count > 0
+                }
+                None => {
+                    collection
+                        .delete_one(query, options.into())
+                        .await?
+                        .deleted_count
+                        > 0
+                }
+            };
+            if let Some(record) = record.filter(|_| changed) {
+                self.audit(record, None, api_key).await?;
+            }
+            Ok(())
+        })
+        .await
     }
 
     /// Update up to one document matching `query` in the collection.
@@ -128,12 +336,47 @@ pub trait Query<D: DeserializeOwned + Serialize + Send + Sync + Unpin>: DbConnec
         update: impl Into<options::UpdateModifications> + Send + 'a,
         options: impl Into<Option<options::UpdateOptions>> + Send + 'a,
         api_key: Option<&str>,
-    ) -> Result<results::UpdateResult> {
-        Ok(self
-            .write_collection(api_key)
-            .await?
-            .update_one(query, update, options)
-            .await?)
+    ) -> Result<UpdateResult> {
+        instrument(self.name(), "update_one", async {
+            let update: options::UpdateModifications = update.into();
+            let record = audit_record(self, "update_one", Some(&query), Some(&update))?;
+            let query = self.live_filter(Some(query)).unwrap_or_default();
+            record_filter(Some(&query));
+            let result = self
+                .write_pool(api_key)
+                .await?
+                .backend::<D>(self.name())?
+                .update_one(query, update, options.into())
+                .await?;
+            if let Some(record) = record.filter(|_| result.changed()) {
+                self.audit(record, None, api_key).await?;
+            }
+            Ok(result)
+        })
+        .await
+    }
+
+    /// Restore up to one deleted document matching `query`, in soft-delete mode.
+    async fn restore_one(&self, query: Document, api_key: Option<&str>) -> Result<UpdateResult> {
+        instrument(self.name(), "restore_one", async {
+            let restoration: Document = Update::unset(DELETED_AT).into();
+            let record = audit_record(self, "restore_one", Some(&query), Some(&restoration))?;
+            let query: Document = Filter::from(query)
+                .and(Filter::exists(DELETED_AT, true))
+                .into();
+            record_filter(Some(&query));
+            let result = self
+                .write_pool(api_key)
+                .await?
+                .backend::<D>(self.name())?
+                .update_one(query, restoration.into(), None)
+                .await?;
+            if let Some(record) = record.filter(|_| result.changed()) {
+                self.audit(record, None, api_key).await?;
+            }
+            Ok(result)
+        })
+        .await
     }
 
     /// Insert a single document.
@@ -142,22 +385,31 @@ pub trait Query<D: DeserializeOwned + Serialize + Send + Sync + Unpin>: DbConnec
         doc: B,
         options: O,
         api_key: Option<&str>,
-    ) -> Result<results::InsertOneResult>
+    ) -> Result<InsertOneResult>
     where
         B: Borrow<D> + Sync + Send + 'a,
         O: Into<Option<options::InsertOneOptions>> + Send + 'a,
     {
-        Ok(self
-            .write_collection(api_key)
-            .await?
-            .insert_one(doc, options)
-            .await?)
+        instrument(self.name(), "insert_one", async {
+            let record = audit_record(self, "insert_one", None, Some(doc.borrow()))?;
+            let result = self
+                .write_pool(api_key)
+                .await?
+                .backend::<D>(self.name())?
+                .insert_one(doc, options.into())
+                .await?;
+            if let Some(record) = record.filter(|_| result.changed()) {
+                self.audit(record, None, api_key).await?;
+            }
+            Ok(result)
+        })
+        .await
     }
-
     /// Runs an aggregation operation.
     ///
     /// See the documentation [here](https://docs.mongodb.com/manual/aggregation/) for more
-    /// information on aggregations.
+    /// information on aggregations. Deleted documents are not skipped, see
+    /// [`Collection::live_filter`]. Fails for pools on a memory store.
     async fn aggregate<'a, P, O>(
         &self,
         pipeline: P,
@@ -168,10 +420,1602 @@ pub trait Query<D: DeserializeOwned + Serialize + Send + Sync + Unpin>: DbConnec
         P: IntoIterator<Item = Document> + Send + 'a,
         O: Into<Option<options::AggregateOptions>> + Send + 'a,
     {
-        Ok(self
-            .read_collection(api_key)
-            .await?
-            .aggregate(pipeline, options)
-            .await?)
+        instrument(self.name(), "aggregate", async {
+            let pool = self.read_pool(api_key).await?;
+            let options = with_max_time(options.into(), &pool)?;
+            Ok(pool
+                .collection::<D>(self.name())?
+                .aggregate(pipeline, options)
+                .await?
+                .into())
+        })
+        .await
+    }
+
+    /// Insert the given documents.
+    async fn insert_many<'a, I, B, O>(
+        &self,
+        docs: I,
+        options: O,
+        api_key: Option<&str>,
+    ) -> Result<InsertManyResult>
+    where
+        I: IntoIterator<Item = B> + Send + 'a,
+        I::IntoIter: Send,
+        B: Borrow<D> + Sync + Send + 'a,
+        O: Into<Option<options::InsertManyOptions>> + Send + 'a,
+    {
+        instrument(self.name(), "insert_many", async {
+            let docs: Vec<B> = docs.into_iter().collect();
+            let record = {
+                let inserted: Vec<&D> = docs.iter().map(Borrow::borrow).collect();
+                audit_record(self, "insert_many", None, Some(&inserted))?
+            };
+            let result = self
+                .write_pool(api_key)
+                .await?
+                .backend::<D>(self.name())?
+                .insert_many(docs, options.into())
+                .await?;
+            if let Some(record) = record.filter(|_| result.changed()) {
+                self.audit(record, None, api_key).await?;
+            }
+            Ok(result)
+        })
+        .await
+    }
+
+    /// Update all documents matching `query` in the collection.
+    async fn update_many<'a>(
+        &self,
+        query: Document,
+        update: impl Into<options::UpdateModifications> + Send + 'a,
+        options: impl Into<Option<options::UpdateOptions>> + Send + 'a,
+        api_key: Option<&str>,
+    ) -> Result<UpdateResult> {
+        instrument(self.name(), "update_many", async {
+            let update: options::UpdateModifications = update.into();
+            let record = audit_record(self, "update_many", Some(&query), Some(&update))?;
+            let query = self.live_filter(Some(query)).unwrap_or_default();
+            record_filter(Some(&query));
+            let result = self
+                .write_pool(api_key)
+                .await?
+                .backend::<D>(self.name())?
+                .update_many(query, update, options.into())
+                .await?;
+            if let Some(record) = record.filter(|_| result.changed()) {
+                self.audit(record, None, api_key).await?;
+            }
+            Ok(result)
+        })
+        .await
+    }
+
+    /// Delete all documents matching `query`.
+    ///
+    /// In soft-delete mode, the documents are marked deleted instead, see
+    /// [`Query::purge_many`] to remove them.
+    async fn delete_many<'a, O>(
+        &self,
+        query: Document,
+        options: O,
+        api_key: Option<&str>,
+    ) -> Result<DeleteResult>
+    where
+        O: Into<Option<options::DeleteOptions>> + Send + 'a,
+    {
+        instrument(self.name(), "delete_many", async {
+            let deletion = self.soft_delete().then(mark_deleted);
+            let record = audit_record(self, "delete_many", Some(&query), deletion.as_ref())?;
+            let query = self.live_filter(Some(query)).unwrap_or_default();
+            record_filter(Some(&query));
+            let collection = self.write_pool(api_key).await?.backend::<D>(self.name())?;
+            let result = match deletion {
+                Some(deletion) => {
+                    let options = update_options(options.into());
+                    let updated = collection
+                        .update_many(query, deletion.into(), options)
+                        .await?;
+                    DeleteResult {
+                        deleted_count: updated.modified_count,
+                    }
+                }
+                None => collection.delete_many(query, options.into()).await?,
+            };
+            if let Some(record) = record.filter(|_| result.changed()) {
+                self.audit(record, None, api_key).await?;
+            }
+            Ok(result)
+        })
+        .await
+    }
+
+    /// Remove all documents matching `query`, deleted ones included.
+    ///
+    /// Unlike [`Query::delete_many`], the documents are removed even in soft-delete mode, so
+    /// that they can be purged for good.
+    async fn purge_many<'a, O>(
+        &self,
+        query: Document,
+        options: O,
+        api_key: Option<&str>,
+    ) -> Result<DeleteResult>
+    where
+        O: Into<Option<options::DeleteOptions>> + Send + 'a,
+    {
+        instrument(self.name(), "purge_many", async {
+            record_filter(Some(&query));
+            let record = audit_record(self, "purge_many", Some(&query), NO_CHANGE)?;
+            let result = self
+                .write_pool(api_key)
+                .await?
+                .backend::<D>(self.name())?
+                .delete_many(query, options.into())
+                .await?;
+            if let Some(record) = record.filter(|_| result.changed()) {
+                self.audit(record, None, api_key).await?;
+            }
+            Ok(result)
+        })
+        .await
+    }
+
+    /// Replace up to one document matching `query` with `replacement`.
+    async fn replace_one<'a, B, O>(
+        &self,
+        query: Document,
+        replacement: B,
+        options: O,
+        api_key: Option<&str>,
+    ) -> Result<UpdateResult>
+    where
+        B: Borrow<D> + Sync + Send + 'a,
+        O: Into<Option<options::ReplaceOptions>> + Send + 'a,
+    {
+        instrument(self.name(), "replace_one", async {
+            let record = audit_record(
+                self,
+                "replace_one",
+                Some(&query),
+                Some(replacement.borrow()),
+            )?;
+            let query = self.live_filter(Some(query)).unwrap_or_default();
+            record_filter(Some(&query));
+            let result = self
+                .write_pool(api_key)
+                .await?
+                .backend::<D>(self.name())?
+                .replace_one(query, replacement, options.into())
+                .await?;
+            if let Some(record) = record.filter(|_| result.changed()) {
+                self.audit(record, None, api_key).await?;
+            }
+            Ok(result)
+        })
+        .await
+    }
+
+    /// Replace up to one document matching `query` with `replacement`, deleted ones included.
+    ///
+    /// In soft-delete mode, replacing a deleted document restores it, unless the replacement
+    /// is marked deleted too.
+    async fn replace_one_including_deleted<'a, B, O>(
+        &self,
+        query: Document,
+        replacement: B,
+        options: O,
+        api_key: Option<&str>,
+    ) -> Result<UpdateResult>
+    where
+        B: Borrow<D> + Sync + Send + 'a,
+        O: Into<Option<options::ReplaceOptions>> + Send + 'a,
+    {
+        instrument(self.name(), "replace_one_including_deleted", async {
+            let record = audit_record(
+                self,
+                "replace_one_including_deleted",
+                Some(&query),
+                Some(replacement.borrow()),
+            )?;
+            record_filter(Some(&query));
+            let result = self
+                .write_pool(api_key)
+                .await?
+                .backend::<D>(self.name())?
+                .replace_one(query, replacement, options.into())
+                .await?;
+            if let Some(record) = record.filter(|_| result.changed()) {
+                self.audit(record, None, api_key).await?;
+            }
+            Ok(result)
+        })
+        .await
+    }
+
+    /// Atomically find up to one document matching `filter` and update it.
+    ///
+    /// Whether the document is returned as it was before or after the update depends on the
+    /// `return_document` option.
+    async fn find_one_and_update<'a>(
+        &self,
+        filter: Document,
+        update: impl Into<options::UpdateModifications> + Send + 'a,
+        options: impl Into<Option<options::FindOneAndUpdateOptions>> + Send + 'a,
+        api_key: Option<&str>,
+    ) -> Result<Option<D>> {
+        instrument(self.name(), "find_one_and_update", async {
+            let update: options::UpdateModifications = update.into();
+            let record = audit_record(self, "find_one_and_update", Some(&filter), Some(&update))?;
+            let filter = self.live_filter(Some(filter)).unwrap_or_default();
+            record_filter(Some(&filter));
+            let pool = self.write_pool(api_key).await?;
+            let options = with_max_time(options.into(), &pool)?;
+            let upsert = options.as_ref().and_then(|options| options.upsert) == Some(true);
+            let document = pool
+                .backend::<D>(self.name())?
+                .find_one_and_update(filter, update, options)
+                .await?;
+            if let Some(record) = record.filter(|_| document.is_some() || upsert) {
+                self.audit(record, None, api_key).await?;
+            }
+            Ok(document)
+        })
+        .await
+    }
+
+    /// Atomically find up to one document matching `filter` and delete it.
+    ///
+    /// In soft-delete mode, the document is marked deleted instead, and returned as it was
+    /// before.
+    async fn find_one_and_delete<'a, O>(
+        &self,
+        filter: Document,
+        options: O,
+        api_key: Option<&str>,
+    ) -> Result<Option<D>>
+    where
+        O: Into<Option<options::FindOneAndDeleteOptions>> + Send + 'a,
+    {
+        instrument(self.name(), "find_one_and_delete", async {
+            let deletion = self.soft_delete().then(mark_deleted);
+            let record = audit_record(
+                self,
+                "find_one_and_delete",
+                Some(&filter),
+                deletion.as_ref(),
+            )?;
+            let filter = self.live_filter(Some(filter)).unwrap_or_default();
+            record_filter(Some(&filter));
+            let pool = self.write_pool(api_key).await?;
+            let options = with_max_time(options.into(), &pool)?;
+            let collection = pool.backend::<D>(self.name())?;
+            let document = match deletion {
+                Some(deletion) => {
+                    let options = find_one_and_update_options(options);
+                    collection
+                        .find_one_and_update(filter, deletion.into(), options)
+                        .await?
+                }
+                None => collection.find_one_and_delete(filter, options).await?,
+            };
+            if let Some(record) = record.filter(|_| document.is_some()) {
+                self.audit(record, None, api_key).await?;
+            }
+            Ok(document)
+        })
+        .await
+    }
+
+    /// Count the documents matching the given filter.
+    ///
+    /// In soft-delete mode, deleted documents are not counted.
+    async fn count_documents<'a, F, O>(
+        &self,
+        filter: F,
+        options: O,
+        api_key: Option<&str>,
+    ) -> Result<u64>
+    where
+        F: Into<Option<bson::document::Document>> + Send + 'a,
+        O: Into<Option<options::CountOptions>> + Send + 'a,
+    {
+        instrument(self.name(), "count_documents", async {
+            let filter = self.live_filter(filter.into());
+            record_filter(filter.as_ref());
+            let pool = self.read_pool(api_key).await?;
+            let options = with_max_time(options.into(), &pool)?;
+            pool.backend::<D>(self.name())?
+                .count_documents(filter, options)
+                .await
+        })
+        .await
+    }
+    /// Estimate the number of documents in the collection from its metadata.
+    ///
+    /// Deleted documents are counted too.
+    async fn estimated_document_count<'a, O>(
+        &self,
+        options: O,
+        api_key: Option<&str>,
+    ) -> Result<u64>
+    where
+        O: Into<Option<options::EstimatedDocumentCountOptions>> + Send + 'a,
+    {
+        instrument(self.name(), "estimated_document_count", async {
+            let pool = self.read_pool(api_key).await?;
+            let options = with_max_time(options.into(), &pool)?;
+            pool.backend::<D>(self.name())?
+                .estimated_document_count(options)
+                .await
+        })
+        .await
+    }
+
+    /// Find the distinct values of `field_name` across the documents matching the filter.
+    ///
+    /// In soft-delete mode, deleted documents are skipped.
+    async fn distinct<'a, F, O>(
+        &self,
+        field_name: &str,
+        filter: F,
+        options: O,
+        api_key: Option<&str>,
+    ) -> Result<Vec<bson::Bson>>
+    where
+        F: Into<Option<bson::document::Document>> + Send + 'a,
+        O: Into<Option<options::DistinctOptions>> + Send + 'a,
+    {
+        instrument(self.name(), "distinct", async {
+            let filter = self.live_filter(filter.into());
+            record_filter(filter.as_ref());
+            let pool = self.read_pool(api_key).await?;
+            let options = with_max_time(options.into(), &pool)?;
+            pool.backend::<D>(self.name())?
+                .distinct(field_name, filter, options)
+                .await
+        })
+        .await
+    }
+
+    /// Apply the given write operations in order, through a single write connection.
+    ///
+    /// The driver has no bulk write command, so each model is sent on its own and the first
+    /// failure aborts the operations that follow it. The models follow the soft-delete mode
+    /// and are recorded like the operations they are named after.
+    async fn bulk_write<'a, M>(&self, models: M, api_key: Option<&str>) -> Result<BulkWriteResult>
+    where
+        M: IntoIterator<Item = WriteModel<D>> + Send + 'a,
+        M::IntoIter: Send,
+        D: 'a,
+    {
+        instrument(self.name(), "bulk_write", async {
+            let collection = self.write_pool(api_key).await?.backend::<D>(self.name())?;
+            let mut result = BulkWriteResult::default();
+
+            for (index, model) in models.into_iter().enumerate() {
+                let (record, changed) = match model {
+                    WriteModel::InsertOne { document } => {
+                        let record = audit_record(self, "insert_one", None, Some(&document))?;
+                        let inserted = collection.insert_one(document, None).await?;
+                        result.inserted_ids.insert(index, inserted.inserted_id);
+                        (record, true)
+                    }
+                    WriteModel::UpdateOne { filter, update } => {
+                        let record =
+                            audit_record(self, "update_one", Some(&filter), Some(&update))?;
+                        let filter = self.live_filter(Some(filter)).unwrap_or_default();
+                        let updated = collection.update_one(filter, update, None).await?;
+                        let changed = updated.changed();
+                        result.add_update(index, updated);
+                        (record, changed)
+                    }
+                    WriteModel::UpdateMany { filter, update } => {
+                        let record =
+                            audit_record(self, "update_many", Some(&filter), Some(&update))?;
+                        let filter = self.live_filter(Some(filter)).unwrap_or_default();
+                        let updated = collection.update_many(filter, update, None).await?;
+                        let changed = updated.changed();
+                        result.add_update(index, updated);
+                        (record, changed)
+                    }
+                    WriteModel::ReplaceOne {
+                        filter,
+                        replacement,
+                    } => {
+                        let record =
+                            audit_record(self, "replace_one", Some(&filter), Some(&replacement))?;
+                        let filter = self.live_filter(Some(filter)).unwrap_or_default();
+                        let updated = collection.replace_one(filter, replacement, None).await?;
+                        let changed = updated.changed();
+                        result.add_update(index, updated);
+                        (record, changed)
+                    }
+                    WriteModel::DeleteOne { filter } => {
+                        let deletion = self.soft_delete().then(mark_deleted);
+                        let record =
+                            audit_record(self, "delete_one", Some(&filter), deletion.as_ref())?;
+                        let filter = self.live_filter(Some(filter)).unwrap_or_default();
+                        let deleted = match deletion {
+                            Some(deletion) => {
+                                let deletion = deletion.into();
+                                let updated = collection.update_one(filter, deletion, None).await?;
+                                updated.modified_count
+                            }
+                            None => collection.delete_one(filter, None).await?.deleted_count,
+                        };
+                        result.deleted_count += deleted;
+                        (record, deleted > 0)
+                    }
+                    WriteModel::DeleteMany { filter } => {
+                        let deletion = self.soft_delete().then(mark_deleted);
+                        let record =
+                            audit_record(self, "delete_many", Some(&filter), deletion.as_ref())?;
+                        let filter = self.live_filter(Some(filter)).unwrap_or_default();
+                        let deleted = match deletion {
+                            Some(deletion) => {
+                                let deletion = deletion.into();
+                                let updated =
+                                    collection.update_many(filter, deletion, None).await?;
+                                updated.modified_count
+                            }
+                            None => collection.delete_many(filter, None).await?.deleted_count,
+                        };
+                        result.deleted_count += deleted;
+                        (record, deleted > 0)
+                    }
+                };
+                if let Some(record) = record.filter(|_| changed) {
+                    self.audit(record, None, api_key).await?;
+                }
+            }
+
+            Ok(result)
+        })
+        .await
+    }
+    /// Find all documents matching the given filter, using the given session.
+    ///
+    /// In soft-delete mode, deleted documents are skipped.
+    async fn find_with_session<'a, F, O>(
+        &self,
+        filter: F,
+        options: O,
+        session: &mut ClientSession,
+        api_key: Option<&str>,
+    ) -> Result<SessionCursor<D>>
+    where
+        F: Into<Option<bson::document::Document>> + Send + 'a,
+        O: Into<Option<options::FindOptions>> + Send + 'a,
+    {
+        instrument(self.name(), "find", async {
+            let filter = self.live_filter(filter.into());
+            record_filter(filter.as_ref());
+            let pool = self.write_pool(api_key).await?;
+            let options = with_max_time(options.into(), &pool)?;
+            Ok(pool
+                .collection::<D>(self.name())?
+                .find_with_session(filter, options, session)
+                .await?)
+        })
+        .await
+    }
+
+    /// Find one document, using the given session.
+    ///
+    /// In soft-delete mode, deleted documents are skipped.
+    async fn find_one_with_session<'a, F, O>(
+        &self,
+        filter: F,
+        options: O,
+        session: &mut ClientSession,
+        api_key: Option<&str>,
+    ) -> Result<Option<D>>
+    where
+        F: Into<Option<bson::document::Document>> + Send + 'a,
+        O: Into<Option<options::FindOneOptions>> + Send + 'a,
+    {
+        instrument(self.name(), "find_one", async {
+            let filter = self.live_filter(filter.into());
+            record_filter(filter.as_ref());
+            let pool = self.write_pool(api_key).await?;
+            let options = with_max_time(options.into(), &pool)?;
+            Ok(pool
+                .collection::<D>(self.name())?
+                .find_one_with_session(filter, options, session)
+                .await?)
+        })
+        .await
+    }
+
+    /// Delete one document, using the given session.
+    ///
+    /// In soft-delete mode, the document is marked deleted instead.
+    async fn delete_one_with_session<'a, O>(
+        &self,
+        query: Document,
+        options: O,
+        session: &mut ClientSession,
+        api_key: Option<&str>,
+    ) -> Result<()>
+    where
+        O: Into<Option<options::DeleteOptions>> + Send + 'a,
+    {
+        instrument(self.name(), "delete_one", async {
+            let deletion = self.soft_delete().then(mark_deleted);
+            let record = audit_record(self, "delete_one", Some(&query), deletion.as_ref())?;
+            let query = self.live_filter(Some(query)).unwrap_or_default();
+            record_filter(Some(&query));
+            let collection = self.write_collection(api_key).await?;
+            let changed = match deletion {
+                Some(deletion) => {
+                    let options = update_options(options.into());
+                    let updated = collection
+                        .update_one_with_session(query, deletion, options, session)
+                        .await?;
+                    updated.modified_count > 0
+                }
+                None => {
+                    let deleted = collection
+                        .delete_one_with_session(query, options, session)
+                        .await?;
+                    deleted.deleted_count > 0
+                }
+            };
+            if let Some(record) = record.filter(|_| changed) {
+                self.audit(record, Some(session), api_key).await?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Delete all documents matching `query`, using the given session.
+    ///
+    /// In soft-delete mode, the documents are marked deleted instead.
+    async fn delete_many_with_session<'a, O>(
+        &self,
+        query: Document,
+        options: O,
+        session: &mut ClientSession,
+        api_key: Option<&str>,
+    ) -> Result<DeleteResult>
+    where
+        O: Into<Option<options::DeleteOptions>> + Send + 'a,
+    {
+        instrument(self.name(), "delete_many", async {
+            let deletion = self.soft_delete().then(mark_deleted);
+            let record = audit_record(self, "delete_many", Some(&query), deletion.as_ref())?;
+            let query = self.live_filter(Some(query)).unwrap_or_default();
+            record_filter(Some(&query));
+            let collection = self.write_collection(api_key).await?;
+            let result = match deletion {
+                Some(deletion) => {
+                    let options = update_options(options.into());
+                    let updated = collection
+                        .update_many_with_session(query, deletion, options, session)
+                        .await?;
+                    DeleteResult {
+                        deleted_count: updated.modified_count,
+                    }
+                }
+                None => collection
+                    .delete_many_with_session(query, options, session)
+                    .await?
+                    .into(),
+            };
+            if let Some(record) = record.filter(|_| result.changed()) {
+                self.audit(record, Some(session), api_key).await?;
+            }
+            Ok(result)
+        })
+        .await
+    }
+
+    /// Update up to one document matching `query`, using the given session.
+    async fn update_one_with_session<'a>(
+        &self,
+        query: Document,
+        update: impl Into<options::UpdateModifications> + Send + 'a,
+        options: impl Into<Option<options::UpdateOptions>> + Send + 'a,
+        session: &mut ClientSession,
+        api_key: Option<&str>,
+    ) -> Result<UpdateResult> {
+        instrument(self.name(), "update_one", async {
+            let update: options::UpdateModifications = update.into();
+            let record = audit_record(self, "update_one", Some(&query), Some(&update))?;
+            let query = self.live_filter(Some(query)).unwrap_or_default();
+            record_filter(Some(&query));
+            let result: UpdateResult = self
+                .write_collection(api_key)
+                .await?
+                .update_one_with_session(query, update, options, session)
+                .await?
+                .into();
+            if let Some(record) = record.filter(|_| result.changed()) {
+                self.audit(record, Some(session), api_key).await?;
+            }
+            Ok(result)
+        })
+        .await
+    }
+
+    /// Update all documents matching `query`, using the given session.
+    async fn update_many_with_session<'a>(
+        &self,
+        query: Document,
+        update: impl Into<options::UpdateModifications> + Send + 'a,
+        options: impl Into<Option<options::UpdateOptions>> + Send + 'a,
+        session: &mut ClientSession,
+        api_key: Option<&str>,
+    ) -> Result<UpdateResult> {
+        instrument(self.name(), "update_many", async {
+            let update: options::UpdateModifications = update.into();
+            let record = audit_record(self, "update_many", Some(&query), Some(&update))?;
+            let query = self.live_filter(Some(query)).unwrap_or_default();
+            record_filter(Some(&query));
+            let result: UpdateResult = self
+                .write_collection(api_key)
+                .await?
+                .update_many_with_session(query, update, options, session)
+                .await?
+                .into();
+            if let Some(record) = record.filter(|_| result.changed()) {
+                self.audit(record, Some(session), api_key).await?;
+            }
+            Ok(result)
+        })
+        .await
+    }
+
+    /// Replace up to one document matching `query`, using the given session.
+    async fn replace_one_with_session<'a, B, O>(
+        &self,
+        query: Document,
+        replacement: B,
+        options: O,
+        session: &mut ClientSession,
+        api_key: Option<&str>,
+    ) -> Result<UpdateResult>
+    where
+        B: Borrow<D> + Sync + Send + 'a,
+        O: Into<Option<options::ReplaceOptions>> + Send + 'a,
+    {
+        instrument(self.name(), "replace_one", async {
+            let record = audit_record(
+                self,
+                "replace_one",
+                Some(&query),
+                Some(replacement.borrow()),
+            )?;
+            let query = self.live_filter(Some(query)).unwrap_or_default();
+            record_filter(Some(&query));
+            let result: UpdateResult = self
+                .write_collection(api_key)
+                .await?
+                .replace_one_with_session(query, replacement, options, session)
+                .await?
+                .into();
+            if let Some(record) = record.filter(|_| result.changed()) {
+                self.audit(record, Some(session), api_key).await?;
+            }
+            Ok(result)
+        })
+        .await
+    }
+
+    /// Insert a single document, using the given session.
+    async fn insert_one_with_session<'a, B, O>(
+        &self,
+        doc: B,
+        options: O,
+        session: &mut ClientSession,
+        api_key: Option<&str>,
+    ) -> Result<InsertOneResult>
+    where
+        B: Borrow<D> + Sync + Send + 'a,
+        O: Into<Option<options::InsertOneOptions>> + Send + 'a,
+    {
+        instrument(self.name(), "insert_one", async {
+            let record = audit_record(self, "insert_one", None, Some(doc.borrow()))?;
+            let result: InsertOneResult = self
+                .write_collection(api_key)
+                .await?
+                .insert_one_with_session(doc, options, session)
+                .await?
+                .into();
+            if let Some(record) = record.filter(|_| result.changed()) {
+                self.audit(record, Some(session), api_key).await?;
+            }
+            Ok(result)
+        })
+        .await
+    }
+
+    /// Insert the given documents, using the given session.
+    async fn insert_many_with_session<'a, I, B, O>(
+        &self,
+        docs: I,
+        options: O,
+        session: &mut ClientSession,
+        api_key: Option<&str>,
+    ) -> Result<InsertManyResult>
+    where
+        I: IntoIterator<Item = B> + Send + 'a,
+        I::IntoIter: Send,
+        B: Borrow<D> + Sync + Send + 'a,
+        O: Into<Option<options::InsertManyOptions>> + Send + 'a,
+    {
+        instrument(self.name(), "insert_many", async {
+            let docs: Vec<B> = docs.into_iter().collect();
+            let record = {
+                let inserted: Vec<&D> = docs.iter().map(Borrow::borrow).collect();
+                audit_record(self, "insert_many", None, Some(&inserted))?
+            };
+            let result: InsertManyResult = self
+                .write_collection(api_key)
+                .await?
+                .insert_many_with_session(docs, options, session)
+                .await?
+                .into();
+            if let Some(record) = record.filter(|_| result.changed()) {
+                self.audit(record, Some(session), api_key).await?;
+            }
+            Ok(result)
+        })
+        .await
+    }
+
+    /// Atomically find up to one document matching `filter` and update it, using the given
+    /// session.
+    async fn find_one_and_update_with_session<'a>(
+        &self,
+        filter: Document,
+        update: impl Into<options::UpdateModifications> + Send + 'a,
+        options: impl Into<Option<options::FindOneAndUpdateOptions>> + Send + 'a,
+        session: &mut ClientSession,
+        api_key: Option<&str>,
+    ) -> Result<Option<D>> {
+        instrument(self.name(), "find_one_and_update", async {
+            let update: options::UpdateModifications = update.into();
+            let record = audit_record(self, "find_one_and_update", Some(&filter), Some(&update))?;
+            let filter = self.live_filter(Some(filter)).unwrap_or_default();
+            record_filter(Some(&filter));
+            let pool = self.write_pool(api_key).await?;
+            let options = with_max_time(options.into(), &pool)?;
+            let upsert = options.as_ref().and_then(|options| options.upsert) == Some(true);
+            let document = pool
+                .collection::<D>(self.name())?
+                .find_one_and_update_with_session(filter, update, options, session)
+                .await?;
+            if let Some(record) = record.filter(|_| document.is_some() || upsert) {
+                self.audit(record, Some(session), api_key).await?;
+            }
+            Ok(document)
+        })
+        .await
+    }
+
+    /// Atomically find up to one document matching `filter` and delete it, using the given
+    /// session.
+    ///
+    /// In soft-delete mode, the document is marked deleted instead, and returned as it was
+    /// before.
+    async fn find_one_and_delete_with_session<'a, O>(
+        &self,
+        filter: Document,
+        options: O,
+        session: &mut ClientSession,
+        api_key: Option<&str>,
+    ) -> Result<Option<D>>
+    where
+        O: Into<Option<options::FindOneAndDeleteOptions>> + Send + 'a,
+    {
+        instrument(self.name(), "find_one_and_delete", async {
+            let deletion = self.soft_delete().then(mark_deleted);
+            let record = audit_record(
+                self,
+                "find_one_and_delete",
+                Some(&filter),
+                deletion.as_ref(),
+            )?;
+            let filter = self.live_filter(Some(filter)).unwrap_or_default();
+            record_filter(Some(&filter));
+            let pool = self.write_pool(api_key).await?;
+            let options = with_max_time(options.into(), &pool)?;
+            let collection = pool.collection::<D>(self.name())?;
+            let document = match deletion {
+                Some(deletion) => {
+                    let options = find_one_and_update_options(options);
+                    collection
+                        .find_one_and_update_with_session(filter, deletion, options, session)
+                        .await?
+                }
+                None => {
+                    collection
+                        .find_one_and_delete_with_session(filter, options, session)
+                        .await?
+                }
+            };
+            if let Some(record) = record.filter(|_| document.is_some()) {
+                self.audit(record, Some(session), api_key).await?;
+            }
+            Ok(document)
+        })
+        .await
+    }
+
+    /// Count the documents matching the given filter, using the given session.
+    ///
+    /// In soft-delete mode, deleted documents are not counted.
+    async fn count_documents_with_session<'a, F, O>(
+        &self,
+        filter: F,
+        options: O,
+        session: &mut ClientSession,
+        api_key: Option<&str>,
+    ) -> Result<u64>
+    where
+        F: Into<Option<bson::document::Document>> + Send + 'a,
+        O: Into<Option<options::CountOptions>> + Send + 'a,
+    {
+        instrument(self.name(), "count_documents", async {
+            let filter = self.live_filter(filter.into());
+            record_filter(filter.as_ref());
+            let pool = self.write_pool(api_key).await?;
+            let options = with_max_time(options.into(), &pool)?;
+            Ok(pool
+                .collection::<D>(self.name())?
+                .count_documents_with_session(filter, options, session)
+                .await?)
+        })
+        .await
+    }
+    /// Runs an aggregation operation, using the given session.
+    async fn aggregate_with_session<'a, P, O>(
+        &self,
+        pipeline: P,
+        options: O,
+        session: &mut ClientSession,
+        api_key: Option<&str>,
+    ) -> Result<SessionCursor<Document>>
+    where
+        P: IntoIterator<Item = Document> + Send + 'a,
+        O: Into<Option<options::AggregateOptions>> + Send + 'a,
+    {
+        instrument(self.name(), "aggregate", async {
+            let pool = self.write_pool(api_key).await?;
+            let options = with_max_time(options.into(), &pool)?;
+            Ok(pool
+                .collection::<D>(self.name())?
+                .aggregate_with_session(pipeline, options, session)
+                .await?)
+        })
+        .await
+    }
+
+    /// Opens a change stream on the collection.
+    ///
+    /// The `pipeline` filters or reshapes the events before they reach the client. To pick up
+    /// an interrupted feed, pass the [`ChangeFeed::resume_token`] it last reported as the
+    /// `resume_after` or `start_after` option.
+    ///
+    /// See the documentation [here](https://docs.mongodb.com/manual/changeStreams/) for more
+    /// information on change streams.
+    async fn watch<'a, P, O>(
+        &self,
+        pipeline: P,
+        options: O,
+        api_key: Option<&str>,
+    ) -> Result<ChangeFeed<D>>
+    where
+        P: IntoIterator<Item = Document> + Send + 'a,
+        O: Into<Option<options::ChangeStreamOptions>> + Send + 'a,
+    {
+        instrument(self.name(), "watch", async {
+            let stream = self
+                .read_collection(api_key)
+                .await?
+                .watch(pipeline, options)
+                .await?;
+            Ok(ChangeFeed { stream })
+        })
+        .await
+    }
+}
+
+/// Options of the operations that take a time limit.
+trait MaxTime: Default {
+    fn max_time(&mut self) -> &mut Option<Duration>;
+}
+
+macro_rules! impl_max_time {
+    ($($options:ty),*) => {
+        $(
+            impl MaxTime for $options {
+                fn max_time(&mut self) -> &mut Option<Duration> {
+                    &mut self.max_time
+                }
+            }
+        )*
+    };
+}
+
+impl_max_time!(
+    options::FindOptions,
+    options::FindOneOptions,
+    options::AggregateOptions,
+    options::CountOptions,
+    options::EstimatedDocumentCountOptions,
+    options::DistinctOptions,
+    options::FindOneAndUpdateOptions,
+    options::FindOneAndDeleteOptions
+);
+
+/// Apply the `maxTimeMS` option of the pool, unless the operation sets its own time limit.
+fn with_max_time<O: MaxTime>(options: Option<O>, pool: &Pool) -> Result<Option<O>> {
+    let max_time = match pool.options().max_time()? {
+        Some(max_time) => max_time,
+        None => return Ok(options),
+    };
+    let mut options = options.unwrap_or_default();
+    options.max_time().get_or_insert(max_time);
+    Ok(Some(options))
+}
+
+/// Run an operation of `collection`, recording its telemetry when enabled.
+///
+/// With the `tracing` feature, the operation runs in a `bongo.query` span. The permission
+/// and configuration path of the pool are recorded on the span once resolved, as is the
+/// redacted shape of the filter of the operation.
+#[cfg_attr(
+    not(any(feature = "metrics", feature = "tracing")),
+    allow(unused_variables)
+)]
+async fn instrument<T>(
+    collection: &str,
+    operation: &'static str,
+    future: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    #[cfg(feature = "tracing")]
+    let future = {
+        use ::tracing::{field::Empty, Instrument};
+        let span = ::tracing::info_span!(
+            "bongo.query",
+            collection,
+            operation,
+            permission = Empty,
+            config_path = Empty,
+            filter = Empty,
+        );
+        future.instrument(span)
+    };
+    #[cfg(feature = "metrics")]
+    let start = std::time::Instant::now();
+    let result = future.await;
+    #[cfg(feature = "metrics")]
+    crate::metrics::record_operation(collection, operation, start.elapsed(), &result);
+    result
+}
+
+/// Record the redacted shape of the filter of an operation on its span.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+fn record_filter(filter: Option<&Document>) {
+    #[cfg(feature = "tracing")]
+    if let Some(filter) = filter {
+        let shape = crate::tracing::filter_shape(filter);
+        ::tracing::Span::current().record("filter", ::tracing::field::display(shape));
+    }
+}
+
+/// No change to record, for the deletes that remove documents.
+const NO_CHANGE: Option<&Document> = None;
+
+/// The outcome of a write, which is recorded only if it changed a document.
+trait Changed {
+    fn changed(&self) -> bool;
+}
+
+impl Changed for InsertOneResult {
+    fn changed(&self) -> bool {
+        true
+    }
+}
+
+impl Changed for InsertManyResult {
+    fn changed(&self) -> bool {
+        !self.inserted_ids.is_empty()
+    }
+}
+
+impl Changed for UpdateResult {
+    fn changed(&self) -> bool {
+        self.modified_count > 0 || self.upserted_id.is_some()
+    }
+}
+
+impl Changed for DeleteResult {
+    fn changed(&self) -> bool {
+        self.deleted_count > 0
+    }
+}
+
+/// Build the record of a write, for collections with an audit collection.
+///
+/// The record is built before the write consumes its arguments, and kept only if the write
+/// [`Changed`] a document.
+fn audit_record<C, T>(
+    collection: &C,
+    operation: &str,
+    filter: Option<&Document>,
+    change: Option<&T>,
+) -> Result<Option<AuditRecord>>
+where
+    C: Collection + ?Sized,
+    T: Serialize + ?Sized,
+{
+    if collection.audit_collection().is_none() {
+        return Ok(None);
+    }
+    let change = change.map(audit::change).transpose()?;
+    Ok(Some(AuditRecord::new(
+        collection.name(),
+        operation,
+        filter.cloned(),
+        change,
+    )))
+}
+
+/// The update marking documents deleted, in soft-delete mode.
+fn mark_deleted() -> Document {
+    Update::set(DELETED_AT, bson::DateTime::now()).into()
+}
+
+/// The options of the update marking a document deleted, from the ones of the delete.
+fn update_options(options: Option<options::DeleteOptions>) -> Option<options::UpdateOptions> {
+    options.map(|options| {
+        options::UpdateOptions::builder()
+            .collation(options.collation)
+            .hint(options.hint)
+            .write_concern(options.write_concern)
+            .let_vars(options.let_vars)
+            .comment(options.comment)
+            .build()
+    })
+}
+
+/// The options of the update marking a document deleted, from the ones of the
+/// find-and-delete.
+fn find_one_and_update_options(
+    options: Option<options::FindOneAndDeleteOptions>,
+) -> Option<options::FindOneAndUpdateOptions> {
+    options.map(|options| {
+        options::FindOneAndUpdateOptions::builder()
+            .max_time(options.max_time)
+            .projection(options.projection)
+            .sort(options.sort)
+            .write_concern(options.write_concern)
+            .collation(options.collation)
+            .hint(options.hint)
+            .let_vars(options.let_vars)
+            .comment(options.comment)
+            .build()
+    })
+}
+
+/// A write operation applied by [`Query::bulk_write`].
+#[derive(Clone, Debug)]
+pub enum WriteModel<D> {
+    InsertOne {
+        document: D,
+    },
+    UpdateOne {
+        filter: Document,
+        update: options::UpdateModifications,
+    },
+    UpdateMany {
+        filter: Document,
+        update: options::UpdateModifications,
+    },
+    ReplaceOne {
+        filter: Document,
+        replacement: D,
+    },
+    DeleteOne {
+        filter: Document,
+    },
+    DeleteMany {
+        filter: Document,
+    },
+}
+
+/// The combined outcome of the operations of a [`Query::bulk_write`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BulkWriteResult {
+    /// The `_id` of the inserted documents, by the index of their model.
+    pub inserted_ids: HashMap<usize, bson::Bson>,
+    /// The `_id` of the upserted documents, by the index of their model.
+    pub upserted_ids: HashMap<usize, bson::Bson>,
+    pub matched_count: u64,
+    pub modified_count: u64,
+    pub deleted_count: u64,
+}
+
+impl BulkWriteResult {
+    fn add_update(&mut self, index: usize, result: UpdateResult) {
+        self.matched_count += result.matched_count;
+        self.modified_count += result.modified_count;
+        if let Some(id) = result.upserted_id {
+            self.upserted_ids.insert(index, id);
+        }
+    }
+}
+
+/// A stream of the documents found by [`Query::find`] or [`Query::aggregate`].
+#[derive(Debug)]
+pub struct Cursor<D> {
+    documents: Documents<D>,
+}
+
+#[derive(Debug)]
+enum Documents<D> {
+    Mongo(Box<mongodb::Cursor<D>>),
+    /// Documents found in a memory store, see [`crate::testing`].
+    Memory(std::vec::IntoIter<D>),
+}
+
+impl<D> From<mongodb::Cursor<D>> for Cursor<D> {
+    fn from(cursor: mongodb::Cursor<D>) -> Self {
+        Self {
+            documents: Documents::Mongo(Box::new(cursor)),
+        }
+    }
+}
+
+impl<D> From<Vec<D>> for Cursor<D> {
+    fn from(documents: Vec<D>) -> Self {
+        Self {
+            documents: Documents::Memory(documents.into_iter()),
+        }
+    }
+}
+
+impl<D> Stream for Cursor<D>
+where
+    D: DeserializeOwned + Send + Sync + Unpin,
+{
+    type Item = Result<D>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match &mut self.documents {
+            Documents::Mongo(cursor) => Pin::new(cursor.as_mut())
+                .poll_next(cx)
+                .map(|document| document.map(|document| document.map_err(BongoError::from))),
+            Documents::Memory(documents) => Poll::Ready(documents.next().map(Ok)),
+        }
+    }
+}
+
+/// A change to a document of a watched collection.
+#[derive(Debug, PartialEq)]
+pub enum ChangeEvent<D> {
+    /// A document was inserted.
+    Insert { key: Document, document: D },
+    /// A document was updated.
+    ///
+    /// The `document` is only present when the feed was opened with the `full_document`
+    /// option set to `UpdateLookup`.
+    Update {
+        key: Document,
+        description: UpdateDescription,
+        document: Option<D>,
+    },
+    /// A document was replaced.
+    Replace { key: Document, document: D },
+    /// A document was deleted.
+    Delete { key: Document },
+    /// A change to the collection itself, such as a drop or a rename.
+    Other(OperationType),
+}
+
+impl<D> TryFrom<ChangeStreamEvent<D>> for ChangeEvent<D> {
+    type Error = BongoError;
+
+    fn try_from(event: ChangeStreamEvent<D>) -> Result<Self> {
+        let key = || {
+            event
+                .document_key
+                .clone()
+                .ok_or_else(|| DaoError("Change event without a document key".into()))
+        };
+        let document = |document: Option<D>| {
+            document.ok_or_else(|| DaoError("Change event without a full document".into()))
+        };
+
+        Ok(match event.operation_type {
+            OperationType::Insert => Self::Insert {
+                key: key()?,
+                document: document(event.full_document)?,
+            },
+            OperationType::Update => Self::Update {
+                key: key()?,
+                description: event
+                    .update_description
+                    .ok_or_else(|| DaoError("Update event without a description".into()))?,
+                document: event.full_document,
+            },
+            OperationType::Replace => Self::Replace {
+                key: key()?,
+                document: document(event.full_document)?,
+            },
+            OperationType::Delete => Self::Delete { key: key()? },
+            other => Self::Other(other),
+        })
+    }
+}
+
+/// A stream of the changes to a collection, opened by [`Query::watch`].
+pub struct ChangeFeed<D>
+where
+    D: DeserializeOwned + Send + Sync + Unpin,
+{
+    stream: ChangeStream<ChangeStreamEvent<D>>,
+}
+
+impl<D> ChangeFeed<D>
+where
+    D: DeserializeOwned + Send + Sync + Unpin,
+{
+    /// The token to resume the feed after the last event it yielded.
+    pub fn resume_token(&self) -> Option<ResumeToken> {
+        self.stream.resume_token()
+    }
+
+    /// Check if the feed may still yield events.
+    ///
+    /// A feed is closed once the collection is dropped or renamed.
+    pub fn is_alive(&self) -> bool {
+        self.stream.is_alive()
+    }
+}
+
+impl<D> Stream for ChangeFeed<D>
+where
+    D: DeserializeOwned + Send + Sync + Unpin,
+{
+    type Item = Result<ChangeEvent<D>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.stream).poll_next(cx).map(|event| {
+            event.map(|event| {
+                event
+                    .map_err(BongoError::from)
+                    .and_then(ChangeEvent::try_from)
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::bson::doc;
+
+    struct Users {
+        soft_delete: bool,
+        audit: Option<&'static str>,
+        pool_manager: PoolManager,
+    }
+
+    impl Users {
+        fn new(soft_delete: bool, audit: Option<&'static str>) -> Self {
+            Self {
+                soft_delete,
+                audit,
+                pool_manager: PoolManager::default(),
+            }
+        }
+    }
+
+    impl Collection for Users {
+        fn name(&self) -> &str {
+            "users"
+        }
+
+        fn soft_delete(&self) -> bool {
+            self.soft_delete
+        }
+
+        fn audit_collection(&self) -> Option<&str> {
+            self.audit
+        }
+    }
+
+    impl DbConnect for Users {
+        fn pool_manager(&self) -> &PoolManager {
+            &self.pool_manager
+        }
+    }
+
+    impl Query<Document> for Users {}
+
+    #[test]
+    fn live_filter_skips_deleted_documents() {
+        let users = Users::new(false, None);
+        assert_eq!(users.live_filter(None), None);
+        assert_eq!(
+            users.live_filter(Some(doc! {"id": "1"})),
+            Some(doc! {"id": "1"})
+        );
+
+        let users = Users::new(true, None);
+        assert_eq!(
+            users.live_filter(None),
+            Some(doc! {"deletedAt": {"$exists": false}})
+        );
+        assert_eq!(
+            users.live_filter(Some(doc! {"id": "1"})),
+            Some(doc! {"id": "1", "deletedAt": {"$exists": false}})
+        );
+        assert_eq!(
+            users.live_filter(Some(doc! {"deletedAt": {"$lt": 1}})),
+            Some(doc! {"$and": [{"deletedAt": {"$lt": 1}}, {"deletedAt": {"$exists": false}}]})
+        );
+    }
+
+    #[test]
+    fn audit_records_of_audited_collections() {
+        let filter = doc! {"id": "1"};
+        let users = Users::new(true, None);
+        let record = audit_record(&users, "delete_one", Some(&filter), NO_CHANGE).unwrap();
+        assert_eq!(record, None);
+
+        let users = Users::new(true, Some("audit"));
+        let deletion = mark_deleted();
+        let record = audit_record(&users, "delete_one", Some(&filter), Some(&deletion))
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.collection, "users");
+        assert_eq!(record.operation, "delete_one");
+        assert_eq!(record.filter, Some(filter));
+        assert_eq!(record.change, Some(bson::Bson::Document(deletion)));
+    }
+
+    #[test]
+    fn bulk_write_result_adds_updates() {
+        let mut result = BulkWriteResult::default();
+        let update = |matched_count, modified_count, upserted_id| UpdateResult {
+            matched_count,
+            modified_count,
+            upserted_id,
+        };
+        result.add_update(0, update(2, 1, None));
+        result.add_update(3, update(0, 0, Some(bson::Bson::Int32(7))));
+        result.add_update(4, update(1, 1, None));
+
+        assert_eq!(
+            result,
+            BulkWriteResult {
+                upserted_ids: HashMap::from([(3, bson::Bson::Int32(7))]),
+                matched_count: 3,
+                modified_count: 2,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[cfg(feature = "testing")]
+    fn memory_users(soft_delete: bool) -> Users {
+        audited_memory_users(&crate::testing::MemoryStore::new(), soft_delete)
+    }
+
+    #[cfg(feature = "testing")]
+    fn audited_memory_users(store: &crate::testing::MemoryStore, soft_delete: bool) -> Users {
+        let config = ::config::Config::default();
+        Users {
+            pool_manager: PoolManager::with_memory_store(config, store.clone()).unwrap(),
+            ..Users::new(soft_delete, Some("audit"))
+        }
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn delete_many_marks_documents_in_soft_delete_mode() {
+        let store = crate::testing::MemoryStore::new();
+        let users = audited_memory_users(&store, true);
+        let documents = [doc! {"_id": 1, "age": 20}, doc! {"_id": 2, "age": 30}];
+        users.insert_many(documents, None, None).await.unwrap();
+
+        let deleted = users.delete_many(doc! {}, None, None).await.unwrap();
+        assert_eq!(deleted.deleted_count, 2);
+        assert_eq!(users.count_documents(None, None, None).await.unwrap(), 0);
+        let stored = store.documents(None, "users");
+        assert!(stored
+            .iter()
+            .all(|document| document.contains_key(DELETED_AT)));
+
+        let purged = users.purge_many(doc! {"_id": 1}, None, None).await.unwrap();
+        assert_eq!(purged.deleted_count, 1);
+        assert_eq!(store.documents(None, "users").len(), 1);
+
+        let operations: Vec<_> = store
+            .documents(None, "audit")
+            .iter()
+            .map(|record| record.get_str("operation").unwrap().to_owned())
+            .collect();
+        assert_eq!(operations, ["insert_many", "delete_many", "purge_many"]);
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn writes_changing_nothing_are_not_audited() {
+        let store = crate::testing::MemoryStore::new();
+        let users = audited_memory_users(&store, true);
+        users.delete_one(doc! {"_id": 1}, None, None).await.unwrap();
+        users.delete_many(doc! {}, None, None).await.unwrap();
+        users.purge_many(doc! {}, None, None).await.unwrap();
+        let update = doc! {"$set": {"age": 1}};
+        users
+            .update_one(doc! {"_id": 1}, update.clone(), None, None)
+            .await
+            .unwrap();
+        users
+            .update_many(doc! {}, update.clone(), None, None)
+            .await
+            .unwrap();
+        users
+            .find_one_and_update(doc! {}, update, None, None)
+            .await
+            .unwrap();
+        users
+            .find_one_and_delete(doc! {}, None, None)
+            .await
+            .unwrap();
+        users.restore_one(doc! {"_id": 1}, None).await.unwrap();
+
+        assert!(store.documents(None, "audit").is_empty());
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn bulk_write_merges_results() {
+        let users = memory_users(false);
+        let models = vec![
+            WriteModel::InsertOne {
+                document: doc! {"_id": 1, "age": 20},
+            },
+            WriteModel::UpdateMany {
+                filter: doc! {},
+                update: doc! {"$inc": {"age": 1}}.into(),
+            },
+            WriteModel::InsertOne {
+                document: doc! {"_id": 2, "age": 30},
+            },
+            WriteModel::UpdateOne {
+                filter: doc! {"_id": 2},
+                update: doc! {"$set": {"age": 30}}.into(),
+            },
+            WriteModel::ReplaceOne {
+                filter: doc! {"_id": 1},
+                replacement: doc! {"age": 40},
+            },
+            WriteModel::DeleteOne {
+                filter: doc! {"_id": 3},
+            },
+            WriteModel::DeleteMany {
+                filter: doc! {"age": {"$gte": 30}},
+            },
+        ];
+        let result = users.bulk_write(models, None).await.unwrap();
+
+        assert_eq!(
+            result,
+            BulkWriteResult {
+                inserted_ids: HashMap::from([(0, bson::Bson::Int32(1)), (2, bson::Bson::Int32(2))]),
+                upserted_ids: HashMap::new(),
+                matched_count: 3,
+                modified_count: 2,
+                deleted_count: 2,
+            }
+        );
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn bulk_write_counts_soft_deletes() {
+        let users = memory_users(true);
+        let models = vec![
+            WriteModel::InsertOne {
+                document: doc! {"_id": 1},
+            },
+            WriteModel::DeleteOne {
+                filter: doc! {"_id": 1},
+            },
+            WriteModel::DeleteOne {
+                filter: doc! {"_id": 1},
+            },
+        ];
+        let result = users.bulk_write(models, None).await.unwrap();
+
+        assert_eq!(result.deleted_count, 1);
+        assert_eq!((result.matched_count, result.modified_count), (0, 0));
+        let found = users.find_one_including_deleted(doc! {"_id": 1}, None, None);
+        assert!(found.await.unwrap().unwrap().contains_key(DELETED_AT));
+    }
+
+    fn event(event: Document) -> ChangeEvent<Document> {
+        let event: ChangeStreamEvent<Document> = bson::from_document(event).unwrap();
+        ChangeEvent::try_from(event).unwrap()
+    }
+
+    #[test]
+    fn insert_event() {
+        let change = event(doc! {
+            "_id": {"_data": "1"},
+            "operationType": "insert",
+            "documentKey": {"_id": 1},
+            "fullDocument": {"_id": 1, "name": "Maria"},
+        });
+        assert_eq!(
+            change,
+            ChangeEvent::Insert {
+                key: doc! {"_id": 1},
+                document: doc! {"_id": 1, "name": "Maria"},
+            }
+        );
+    }
+
+    #[test]
+    fn update_event_without_lookup() {
+        let change = event(doc! {
+            "_id": {"_data": "2"},
+            "operationType": "update",
+            "documentKey": {"_id": 1},
+            "updateDescription": {"updatedFields": {"age": 41}, "removedFields": []},
+        });
+        match change {
+            ChangeEvent::Update {
+                key,
+                description,
+                document,
+            } => {
+                assert_eq!(key, doc! {"_id": 1});
+                assert_eq!(description.updated_fields, doc! {"age": 41});
+                assert_eq!(document, None);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+
+    #[test]
+    fn delete_event() {
+        let change = event(doc! {
+            "_id": {"_data": "3"},
+            "operationType": "delete",
+            "documentKey": {"_id": 1},
+        });
+        assert_eq!(
+            change,
+            ChangeEvent::Delete {
+                key: doc! {"_id": 1}
+            }
+        );
+    }
+
+    #[test]
+    fn collection_event() {
+        let change = event(doc! {"_id": {"_data": "4"}, "operationType": "drop"});
+        assert_eq!(change, ChangeEvent::Other(OperationType::Drop));
+    }
+
+    #[test]
+    fn insert_event_without_document() {
+        let event: ChangeStreamEvent<Document> = bson::from_document(doc! {
+            "_id": {"_data": "5"},
+            "operationType": "insert",
+            "documentKey": {"_id": 1},
+        })
+        .unwrap();
+        assert!(ChangeEvent::try_from(event).is_err());
     }
 }
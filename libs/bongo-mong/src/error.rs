@@ -1,4 +1,6 @@
 //! Library specific error types.
+use crate::config::path::TraversalPath;
+use std::path::PathBuf;
 
 #[derive(thiserror::Error, Debug)]
 pub enum BongoError {
@@ -12,8 +14,81 @@ pub enum BongoError {
     DaoError(String),
     #[error("option {0} not supported")]
     UnsupportedOption(String),
+    #[error("option {option}: expected {expected}, found {found}")]
+    InvalidOption {
+        option: &'static str,
+        expected: &'static str,
+        found: String,
+    },
     #[error("unknown permission type {0}")]
     UnknownPermission(String),
+    #[error("invalid configuration: {}", join(.0))]
+    InvalidConfig(Vec<ConfigIssue>),
+    #[error("missing configuration for {0}")]
+    MissingConfig(TraversalPath),
+    #[error("no default database in the connection string")]
+    MissingDatabase,
+    #[error("update changes {0} more than once")]
+    ConflictingUpdate(String),
+    #[error("failed to read secret file {}: {source}", .path.display())]
+    SecretFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+/// A problem found by [`BongoConfig::validate`](crate::config::BongoConfig::validate), at the
+/// path of the offending key.
+#[derive(thiserror::Error, Clone, Debug, PartialEq)]
+pub enum ConfigIssue {
+    #[error("{path}: unknown option{}", suggest(.suggestion))]
+    UnknownOption {
+        path: TraversalPath,
+        suggestion: Option<&'static str>,
+    },
+    #[error("{path}: expected {expected}, found {found}")]
+    InvalidValue {
+        path: TraversalPath,
+        expected: &'static str,
+        found: String,
+    },
+    #[error("{path}: expected a table of {expected}")]
+    InvalidSection {
+        path: TraversalPath,
+        expected: &'static str,
+    },
+    #[error("{path}: unknown permission type")]
+    UnknownPermission { path: TraversalPath },
+    #[error("{path}: no baseUri in the section or the ones it inherits from")]
+    MissingBaseUri { path: TraversalPath },
+}
+
+impl ConfigIssue {
+    /// The path of the offending key.
+    pub fn path(&self) -> &TraversalPath {
+        match self {
+            Self::UnknownOption { path, .. }
+            | Self::InvalidValue { path, .. }
+            | Self::InvalidSection { path, .. }
+            | Self::UnknownPermission { path }
+            | Self::MissingBaseUri { path } => path,
+        }
+    }
+}
+
+fn join(issues: &[ConfigIssue]) -> String {
+    issues
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn suggest(suggestion: &Option<&'static str>) -> String {
+    match suggestion {
+        Some(suggestion) => format!(", did you mean {}?", suggestion),
+        None => String::new(),
+    }
 }
 
 pub type Result<T> = std::result::Result<T, BongoError>;
@@ -0,0 +1,187 @@
+//! Utilities to monitor the health of cached pools.
+use crate::cache::Cache;
+use crate::config::{
+    options::{LooseOptions, MongoDbUri},
+    path::ConfigPath,
+    BongoConfig,
+};
+use crate::error::BongoError;
+use crate::Pool;
+use mongodb::bson::doc;
+use parking_lot::Mutex;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+
+/// Settings of the health monitor of a [`PoolManager`](crate::PoolManager).
+#[derive(Clone, Debug)]
+pub struct HealthCheck {
+    /// How often the cached pools are checked.
+    pub interval: Duration,
+    /// How long a ping may take before it counts as failed.
+    pub timeout: Duration,
+    /// How many consecutive failed pings evict a pool.
+    pub max_failures: u32,
+    /// How long a pool may go unused before it is evicted, if ever.
+    pub idle_ttl: Option<Duration>,
+}
+
+impl Default for HealthCheck {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(30),
+            timeout: Duration::from_secs(5),
+            max_failures: 3,
+            idle_ttl: None,
+        }
+    }
+}
+
+/// The state of a cached pool.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PoolStatus {
+    /// Whether the last ping succeeded, or no ping was sent yet.
+    pub healthy: bool,
+    /// The number of failed pings since the last successful one.
+    pub consecutive_failures: u32,
+    /// The error of the last failed ping.
+    pub last_error: Option<String>,
+    /// The time since the pool was last accessed.
+    pub idle: Duration,
+    /// The time since the pool was last pinged, if ever.
+    pub last_checked: Option<Duration>,
+}
+
+/// A pool stored in the cache of a pool manager, along with its health.
+#[derive(Debug)]
+pub(crate) struct CachedPool {
+    pool: Pool,
+    path: ConfigPath,
+    uri: MongoDbUri,
+    /// The configuration the pool is known to be up to date with.
+    config: Mutex<Weak<BongoConfig>>,
+    health: Mutex<Health>,
+}
+
+#[derive(Debug)]
+struct Health {
+    last_used: Instant,
+    last_checked: Option<Instant>,
+    failures: u32,
+    last_error: Option<String>,
+}
+
+impl CachedPool {
+    pub(crate) fn new(
+        pool: Pool,
+        path: ConfigPath,
+        uri: MongoDbUri,
+        config: &Arc<BongoConfig>,
+    ) -> Self {
+        Self {
+            pool,
+            path,
+            uri,
+            config: Mutex::new(Arc::downgrade(config)),
+            health: Mutex::new(Health {
+                last_used: Instant::now(),
+                last_checked: None,
+                failures: 0,
+                last_error: None,
+            }),
+        }
+    }
+
+    /// Get the pool, marking it as used.
+    pub(crate) fn pool(&self) -> Pool {
+        self.health.lock().last_used = Instant::now();
+        self.pool.clone()
+    }
+
+    /// The path of the configuration the pool was built from.
+    pub(crate) fn path(&self) -> &ConfigPath {
+        &self.path
+    }
+
+    /// The loose options of the pool.
+    pub(crate) fn options(&self) -> &LooseOptions {
+        self.pool.options()
+    }
+
+    /// The connection string of the client of the pool.
+    pub(crate) fn uri(&self) -> &MongoDbUri {
+        &self.uri
+    }
+
+    /// Check if the pool was built from `config`, or found up to date with it.
+    pub(crate) fn is_built_with(&self, config: &Arc<BongoConfig>) -> bool {
+        self.config.lock().ptr_eq(&Arc::downgrade(config))
+    }
+
+    /// Check if the pool is the one `config` builds, remembering it when it is.
+    pub(crate) async fn is_up_to_date(&self, config: &Arc<BongoConfig>) -> bool {
+        if self.is_built_with(config) {
+            return true;
+        }
+        let up_to_date = match config.to_opts(&self.path).await {
+            Ok(opts) => opts.uri == self.uri && &opts.other == self.options(),
+            Err(_) => false,
+        };
+        if up_to_date {
+            *self.config.lock() = Arc::downgrade(config);
+        }
+        up_to_date
+    }
+
+    pub(crate) fn status(&self) -> PoolStatus {
+        let health = self.health.lock();
+        PoolStatus {
+            healthy: health.failures == 0,
+            consecutive_failures: health.failures,
+            last_error: health.last_error.clone(),
+            idle: health.last_used.elapsed(),
+            last_checked: health.last_checked.map(|checked| checked.elapsed()),
+        }
+    }
+
+    /// Ping the pool, returning the number of consecutive failures.
+    async fn ping(&self, timeout: Duration) -> u32 {
+        let ping = async {
+            let admin = self.pool.connected_client()?.database("admin");
+            admin.run_command(doc! {"ping": 1}, None).await?;
+            Ok::<_, BongoError>(())
+        };
+        let error = match tokio::time::timeout(timeout, ping).await {
+            Ok(Ok(_)) => None,
+            Ok(Err(err)) => Some(err.to_string()),
+            Err(_) => Some(format!("ping timed out after {:?}", timeout)),
+        };
+
+        let mut health = self.health.lock();
+        health.last_checked = Some(Instant::now());
+        match error {
+            None => health.failures = 0,
+            Some(_) => health.failures += 1,
+        }
+        health.last_error = error.or_else(|| health.last_error.take());
+        health.failures
+    }
+}
+
+/// Check every pool of the cache once, evicting the idle and failing ones.
+pub(crate) async fn check(cache: &Cache, options: &HealthCheck) {
+    for (key, slot) in cache.slots() {
+        // Pools still being built have nothing to check
+        let entry = match slot.get() {
+            Some(entry) => entry,
+            None => continue,
+        };
+        let idle = entry.health.lock().last_used.elapsed();
+        if matches!(options.idle_ttl, Some(ttl) if idle >= ttl) {
+            cache.evict(&key, &slot, false);
+            continue;
+        }
+        if entry.ping(options.timeout).await >= options.max_failures {
+            cache.evict(&key, &slot, true);
+        }
+    }
+}
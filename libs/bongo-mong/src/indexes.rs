@@ -0,0 +1,192 @@
+//! Utilities to keep the indexes of collections in line with their declarations.
+use mongodb::{bson::Bson, IndexModel};
+
+/// The name Mongo Db gives to the index on `_id` of every collection.
+const ID_INDEX: &str = "_id_";
+
+/// How the indexes of a collection compare to the ones it declares.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IndexReport {
+    /// The name of the collection.
+    pub collection: String,
+    /// The api key of the app the collection belongs to, if any.
+    pub api_key: Option<String>,
+    /// Declared indexes that did not exist and were created.
+    pub created: Vec<String>,
+    /// Declared indexes that exist on the same keys with different options.
+    ///
+    /// These are left as they are, since rebuilding an index can be expensive.
+    pub drifted: Vec<String>,
+    /// Existing indexes that are not declared.
+    pub undeclared: Vec<String>,
+}
+
+impl IndexReport {
+    /// Check if the existing indexes differ from the declared ones.
+    pub fn has_drift(&self) -> bool {
+        !self.drifted.is_empty() || !self.undeclared.is_empty()
+    }
+}
+
+/// The declared indexes missing from `existing`, with the report of the comparison.
+///
+/// The `created` field of the report lists the missing indexes, which are yet to be created.
+pub(crate) fn compare(
+    collection: &str,
+    declared: Vec<IndexModel>,
+    existing: &[IndexModel],
+) -> (Vec<IndexModel>, IndexReport) {
+    let undeclared = existing
+        .iter()
+        .filter(|other| declared.iter().all(|index| index.keys != other.keys))
+        .map(name)
+        .filter(|name| name != ID_INDEX)
+        .collect();
+    let mut report = IndexReport {
+        collection: collection.to_string(),
+        undeclared,
+        ..Default::default()
+    };
+    let mut missing = Vec::new();
+
+    for index in declared {
+        match existing.iter().find(|other| other.keys == index.keys) {
+            Some(other) if !same_options(&index, other) => report.drifted.push(name(&index)),
+            Some(_) => {}
+            None => {
+                report.created.push(name(&index));
+                missing.push(index);
+            }
+        }
+    }
+
+    (missing, report)
+}
+
+/// The name of the index, either given in its options or generated from its keys as
+/// Mongo Db does.
+pub(crate) fn name(index: &IndexModel) -> String {
+    if let Some(name) = index
+        .options
+        .as_ref()
+        .and_then(|options| options.name.clone())
+    {
+        return name;
+    }
+    index
+        .keys
+        .iter()
+        .map(|(key, value)| match value {
+            // Without the quotes of its `Display` output
+            Bson::String(value) => format!("{}_{}", key, value),
+            value => format!("{}_{}", key, value),
+        })
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// Check if two indexes have the same options, apart from their name and version.
+fn same_options(index: &IndexModel, other: &IndexModel) -> bool {
+    let options = index.options.clone().unwrap_or_default();
+    let other = other.options.clone().unwrap_or_default();
+
+    options.unique.unwrap_or(false) == other.unique.unwrap_or(false)
+        && options.sparse.unwrap_or(false) == other.sparse.unwrap_or(false)
+        && options.expire_after == other.expire_after
+        && options.partial_filter_expression == other.partial_filter_expression
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::{bson::doc, options::IndexOptions};
+
+    fn index(keys: mongodb::bson::Document, unique: bool) -> IndexModel {
+        IndexModel::builder()
+            .keys(keys)
+            .options(IndexOptions::builder().unique(unique).build())
+            .build()
+    }
+
+    fn existing(keys: mongodb::bson::Document, name: &str, unique: bool) -> IndexModel {
+        IndexModel::builder()
+            .keys(keys)
+            .options(
+                IndexOptions::builder()
+                    .name(name.to_string())
+                    .unique(unique)
+                    .build(),
+            )
+            .build()
+    }
+
+    #[test]
+    fn index_name_from_keys() {
+        assert_eq!(name(&index(doc! {"id": 1}, true)), "id_1");
+        assert_eq!(
+            name(&index(doc! {"wappierId": 1, "apiKey": -1}, true)),
+            "wappierId_1_apiKey_-1"
+        );
+        assert_eq!(name(&existing(doc! {"id": 1}, "by_id", true)), "by_id");
+    }
+
+    #[test]
+    fn compare_missing_index() {
+        let (missing, report) = compare(
+            "users",
+            vec![index(doc! {"id": 1}, true)],
+            &[existing(doc! {"_id": 1}, "_id_", false)],
+        );
+        assert_eq!(missing.len(), 1);
+        assert_eq!(report.created, vec!["id_1"]);
+        assert!(!report.has_drift());
+    }
+
+    #[test]
+    fn compare_existing_index() {
+        let (missing, report) = compare(
+            "users",
+            vec![index(doc! {"id": 1}, true)],
+            &[
+                existing(doc! {"_id": 1}, "_id_", false),
+                existing(doc! {"id": 1}, "id_1", true),
+            ],
+        );
+        assert!(missing.is_empty());
+        assert_eq!(
+            report,
+            IndexReport {
+                collection: "users".into(),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn compare_drifted_index() {
+        let (missing, report) = compare(
+            "users",
+            vec![index(doc! {"id": 1}, true)],
+            &[existing(doc! {"id": 1}, "id_1", false)],
+        );
+        assert!(missing.is_empty());
+        assert_eq!(report.drifted, vec!["id_1"]);
+        assert!(report.undeclared.is_empty());
+        assert!(report.has_drift());
+    }
+
+    #[test]
+    fn compare_undeclared_index() {
+        let (missing, report) = compare(
+            "users",
+            vec![],
+            &[
+                existing(doc! {"_id": 1}, "_id_", false),
+                existing(doc! {"name": 1}, "name_1", false),
+            ],
+        );
+        assert!(missing.is_empty());
+        assert_eq!(report.undeclared, vec!["name_1"]);
+        assert!(report.has_drift());
+    }
+}
@@ -1,9 +1,25 @@
+pub mod audit;
+mod backend;
+mod cache;
 #[cfg(feature = "collections")]
 pub mod collections;
 pub mod config;
 pub mod dao;
 pub mod error;
+pub mod health;
+pub mod indexes;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 pub mod pools;
+pub mod query;
+pub mod results;
+pub mod session;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "tracing")]
+pub mod tracing;
 
 pub use mongodb;
+// Lets the code generated by `bongo-mong-derive` name this crate from within it
+extern crate self as bongo_mong;
 pub use pools::{Pool, PoolManager};
@@ -0,0 +1,168 @@
+//! Metrics of the operations and pools of the library.
+//!
+//! The metrics are reported through the [`metrics`](::metrics) facade, and reach whichever
+//! recorder the application installs, like a Prometheus exporter. Call [`describe`] once
+//! the recorder is installed to document them.
+use crate::config::path::ConfigPath;
+use crate::error::{BongoError, Result};
+use ::metrics::{
+    decrement_gauge, describe_counter, describe_gauge, describe_histogram, histogram,
+    increment_counter, increment_gauge, Unit,
+};
+use mongodb::event::cmap::{
+    CmapEventHandler, ConnectionCheckedOutEvent, ConnectionCheckoutFailedEvent,
+    ConnectionClosedEvent, ConnectionCreatedEvent, PoolClearedEvent,
+};
+use std::time::Duration;
+
+/// Latency of dao operations, by collection and operation.
+pub const OPERATION_DURATION: &str = "bongo_operation_duration_seconds";
+/// Failed dao operations, by collection, operation and error.
+pub const OPERATION_ERRORS: &str = "bongo_operation_errors_total";
+/// Pools requested from pool managers, by configuration path kind and cache result.
+pub const POOL_REQUESTS: &str = "bongo_pool_requests_total";
+/// Cached pools, by configuration path kind.
+pub const POOLS: &str = "bongo_pools";
+/// Connections checked out of driver pools, by server address.
+pub const CONNECTION_CHECKOUTS: &str = "bongo_connection_checkouts_total";
+/// Failed connection checkouts, by server address and reason.
+pub const CONNECTION_CHECKOUT_FAILURES: &str = "bongo_connection_checkout_failures_total";
+/// Time spent waiting for a connection, by server address.
+pub const CONNECTION_CHECKOUT_DURATION: &str = "bongo_connection_checkout_duration_seconds";
+/// Open connections, by server address.
+pub const CONNECTIONS: &str = "bongo_connections";
+/// Cleared driver pools, by server address.
+pub const CONNECTION_POOLS_CLEARED: &str = "bongo_connection_pools_cleared_total";
+
+/// Describe the metrics of the library to the installed recorder.
+pub fn describe() {
+    describe_histogram!(
+        OPERATION_DURATION,
+        Unit::Seconds,
+        "How long dao operations take to complete"
+    );
+    describe_counter!(OPERATION_ERRORS, "How many dao operations failed");
+    describe_counter!(
+        POOL_REQUESTS,
+        "How many pools were requested, and whether they were cached"
+    );
+    describe_gauge!(POOLS, "How many pools are cached");
+    describe_counter!(
+        CONNECTION_CHECKOUTS,
+        "How many connections were checked out"
+    );
+    describe_counter!(
+        CONNECTION_CHECKOUT_FAILURES,
+        "How many connection checkouts failed"
+    );
+    describe_histogram!(
+        CONNECTION_CHECKOUT_DURATION,
+        Unit::Seconds,
+        "How long checking out a connection takes"
+    );
+    describe_gauge!(CONNECTIONS, "How many connections are open");
+    describe_counter!(
+        CONNECTION_POOLS_CLEARED,
+        "How many times connection pools were cleared"
+    );
+}
+
+/// Report the connection pool events of a client.
+///
+/// Set on every client built by a [`PoolManager`](crate::PoolManager).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CmapMetrics;
+
+impl CmapEventHandler for CmapMetrics {
+    fn handle_connection_created_event(&self, event: ConnectionCreatedEvent) {
+        increment_gauge!(CONNECTIONS, 1.0, "address" => event.address.to_string());
+    }
+
+    fn handle_connection_closed_event(&self, event: ConnectionClosedEvent) {
+        decrement_gauge!(CONNECTIONS, 1.0, "address" => event.address.to_string());
+    }
+
+    fn handle_connection_checked_out_event(&self, event: ConnectionCheckedOutEvent) {
+        let labels = [("address", event.address.to_string())];
+        increment_counter!(CONNECTION_CHECKOUTS, &labels);
+        histogram!(
+            CONNECTION_CHECKOUT_DURATION,
+            event.duration.as_secs_f64(),
+            &labels
+        );
+    }
+
+    fn handle_connection_checkout_failed_event(&self, event: ConnectionCheckoutFailedEvent) {
+        let address = event.address.to_string();
+        increment_counter!(
+            CONNECTION_CHECKOUT_FAILURES,
+            "address" => address.clone(),
+            "reason" => format!("{:?}", event.reason)
+        );
+        histogram!(
+            CONNECTION_CHECKOUT_DURATION,
+            event.duration.as_secs_f64(),
+            "address" => address
+        );
+    }
+
+    fn handle_pool_cleared_event(&self, event: PoolClearedEvent) {
+        increment_counter!(CONNECTION_POOLS_CLEARED, "address" => event.address.to_string());
+    }
+}
+
+/// Record the latency and outcome of a dao operation.
+pub(crate) fn record_operation<T>(
+    collection: &str,
+    operation: &'static str,
+    elapsed: Duration,
+    result: &Result<T>,
+) {
+    histogram!(
+        OPERATION_DURATION,
+        elapsed.as_secs_f64(),
+        "collection" => collection.to_string(),
+        "operation" => operation
+    );
+    if let Err(err) = result {
+        increment_counter!(
+            OPERATION_ERRORS,
+            "collection" => collection.to_string(),
+            "operation" => operation,
+            "error" => error_label(err)
+        );
+    }
+}
+
+/// Record a request for the pool of `path`.
+pub(crate) fn record_pool_request(path: &ConfigPath, cached: bool) {
+    let result = if cached { "hit" } else { "miss" };
+    increment_counter!(POOL_REQUESTS, "kind" => path.kind(), "result" => result);
+}
+
+/// Record a pool added to the cache of a pool manager.
+pub(crate) fn record_pool_added(path: &ConfigPath) {
+    increment_gauge!(POOLS, 1.0, "kind" => path.kind());
+}
+
+/// Record a pool removed from the cache of a pool manager.
+pub(crate) fn record_pool_removed(path: &ConfigPath) {
+    decrement_gauge!(POOLS, 1.0, "kind" => path.kind());
+}
+
+/// The name of the variant of the error.
+fn error_label(err: &BongoError) -> &'static str {
+    match err {
+        BongoError::MongoDbUriCreate(_) => "MongoDbUriCreate",
+        BongoError::MongoDbError(_) => "MongoDbError",
+        BongoError::ConfigError(_) => "ConfigError",
+        BongoError::DaoError(_) => "DaoError",
+        BongoError::UnsupportedOption(_) => "UnsupportedOption",
+        BongoError::InvalidOption { .. } => "InvalidOption",
+        BongoError::UnknownPermission(_) => "UnknownPermission",
+        BongoError::InvalidConfig(_) => "InvalidConfig",
+        BongoError::MissingConfig(_) => "MissingConfig",
+        BongoError::MissingDatabase => "MissingDatabase",
+        BongoError::SecretFile { .. } => "SecretFile",
+    }
+}
@@ -1,34 +1,88 @@
 //! Utilities to manage Mongo Db pools.
+use crate::backend::Backend;
+use crate::cache::Cache;
 use crate::config::{
     options::{LooseOption, LooseOptions, PoolPermissionType},
-    path, BongoConfig,
+    path::{self, ConfigPath},
+    BongoConfig,
 };
+use crate::dao::Collection;
 use crate::error::{BongoError, Result};
+use crate::health::{self, CachedPool, HealthCheck, PoolStatus};
+use crate::indexes::{self, IndexReport};
+#[cfg(feature = "testing")]
+use crate::testing::{MemoryDatabase, MemoryStore};
 use config::{Config, Value};
-use mongodb::Client;
+use mongodb::{
+    bson::Document, error::ErrorKind, options::SessionOptions, Client, ClientSession, Database,
+};
 use parking_lot::RwLock;
 use serde::{de::DeserializeOwned, Serialize};
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, SystemTime};
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
 
 /// Represent a pool of connections to MongoDb.
 ///
 /// The value wraps additional ad-hoc options supported by the library.
 #[derive(Clone, Debug)]
 pub struct Pool {
-    client: Client,
+    connection: Connection,
     options: LooseOptions,
 }
 
+/// What the operations on a pool go through.
+#[derive(Clone, Debug)]
+enum Connection {
+    Client(Client),
+    /// The database of an API key in a memory store, see [`PoolManager::with_memory_store`].
+    #[cfg(feature = "testing")]
+    Memory(MemoryDatabase),
+}
+
 impl Pool {
     /// Create a new pool.
     pub fn new(client: Client, options: LooseOptions) -> Self {
-        Self { client, options }
+        Self {
+            connection: Connection::Client(client),
+            options,
+        }
+    }
+
+    /// Create a pool on the database of an API key in a memory store.
+    #[cfg(feature = "testing")]
+    pub(crate) fn memory(database: MemoryDatabase, options: LooseOptions) -> Self {
+        Self {
+            connection: Connection::Memory(database),
+            options,
+        }
     }
 
     /// Get the client.
+    ///
+    /// # Panics
+    ///
+    /// Pools on a memory store, available with the `testing` feature, have no client.
     pub fn client(&self) -> &Client {
-        &self.client
+        match self.connected_client() {
+            Ok(client) => client,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Get the client, failing on pools of a memory store rather than panicking.
+    pub(crate) fn connected_client(&self) -> Result<&Client> {
+        match &self.connection {
+            Connection::Client(client) => Ok(client),
+            #[cfg(feature = "testing")]
+            Connection::Memory(_) => Err(BongoError::DaoError(
+                "pools on a memory store have no client".into(),
+            )),
+        }
     }
 
     /// Get loose options.
@@ -36,24 +90,80 @@ impl Pool {
         &self.options
     }
 
+    /// Start a session on the pool.
+    ///
+    /// A session can only be used with collections of the pool it was started on.
+    pub async fn start_session(
+        &self,
+        options: impl Into<Option<SessionOptions>>,
+    ) -> Result<ClientSession> {
+        Ok(self.connected_client()?.start_session(options).await?)
+    }
+
+    /// Get the database of the pool.
+    ///
+    /// The method looks for a database name in `options()`, and uses the default database of the
+    /// connection string as fallback.
+    pub fn database(&self) -> Result<Database> {
+        match self.options().database()? {
+            Some(database) => Ok(self.connected_client()?.database(&database)),
+            None => self
+                .connected_client()?
+                .default_database()
+                .ok_or(BongoError::MissingDatabase),
+        }
+    }
+
     /// Get a connection to a collection.
     ///
     /// The method looks for a collection name in `options()`, and uses the given name as fallback.
+    /// The read preference, read concern and write concern of `options()` apply to the operations
+    /// on the collection.
     pub fn collection<D>(&self, name: impl AsRef<str>) -> Result<mongodb::Collection<D>>
     where
         D: DeserializeOwned + Serialize + Send + Sync + Unpin,
     {
-        let collection =
-            if let Some(collection) = self.options().get(&LooseOption::Collection).cloned() {
-                collection.into_string()?
-            } else {
-                name.as_ref().to_string()
-            };
+        self.named_collection(&self.collection_name(name)?)
+    }
+
+    /// Get a connection to the collection named `name`, whatever the name in `options()`.
+    pub(crate) fn named_collection<D>(&self, name: &str) -> Result<mongodb::Collection<D>>
+    where
+        D: DeserializeOwned + Serialize + Send + Sync + Unpin,
+    {
         Ok(self
-            .client()
-            .default_database()
-            .ok_or_else(|| config::ConfigError::Message("No read database configured".into()))?
-            .collection(&collection))
+            .database()?
+            .collection_with_options(name, self.options().collection_options()?))
+    }
+
+    /// Get the collection the operations of [`Query`](crate::dao::Query) run on.
+    ///
+    /// The name is looked up like in [`Pool::collection`].
+    pub(crate) fn backend<D>(&self, name: impl AsRef<str>) -> Result<Backend<D>>
+    where
+        D: DeserializeOwned + Serialize + Send + Sync + Unpin,
+    {
+        self.named_backend(&self.collection_name(name)?)
+    }
+
+    /// Get the collection named `name`, whatever the name in `options()`.
+    pub(crate) fn named_backend<D>(&self, name: &str) -> Result<Backend<D>>
+    where
+        D: DeserializeOwned + Serialize + Send + Sync + Unpin,
+    {
+        match &self.connection {
+            Connection::Client(_) => Ok(Backend::Mongo(self.named_collection(name)?)),
+            #[cfg(feature = "testing")]
+            Connection::Memory(database) => Ok(Backend::Memory(database.collection(name))),
+        }
+    }
+
+    /// The name of the collection in `options()`, or the given one.
+    fn collection_name(&self, name: impl AsRef<str>) -> Result<String> {
+        match self.options().get(&LooseOption::Collection).cloned() {
+            Some(collection) => Ok(collection.into_string()?),
+            None => Ok(name.as_ref().to_string()),
+        }
     }
 }
 
@@ -62,8 +172,26 @@ impl Pool {
 /// Uses `Arc` internally, so it can be used between threads safely.
 #[derive(Clone, Debug, Default)]
 pub struct PoolManager {
-    pools: Arc<RwLock<HashMap<String, Pool>>>,
-    config: Arc<BongoConfig>,
+    cache: Arc<Cache>,
+    config: Arc<RwLock<Arc<BongoConfig>>>,
+    created: Arc<AtomicUsize>,
+}
+
+/// A pool manager that does not keep its pools alive.
+struct WeakPoolManager {
+    cache: Weak<Cache>,
+    config: Weak<RwLock<Arc<BongoConfig>>>,
+    created: Weak<AtomicUsize>,
+}
+
+impl WeakPoolManager {
+    fn upgrade(&self) -> Option<PoolManager> {
+        Some(PoolManager {
+            cache: self.cache.upgrade()?,
+            config: self.config.upgrade()?,
+            created: self.created.upgrade()?,
+        })
+    }
 }
 
 impl PoolManager {
@@ -71,69 +199,379 @@ impl PoolManager {
     pub fn new(config: Config) -> Result<Self> {
         let config = Arc::new(BongoConfig::new(config)?);
         Ok(Self {
-            config,
+            config: Arc::new(RwLock::new(config)),
             ..Default::default()
         })
     }
 
+    /// Validate the current configuration.
+    ///
+    /// See [`BongoConfig::validate`].
+    pub fn validate(&self) -> Result<()> {
+        self.config().validate()
+    }
+
+    /// Check if the current configuration has a section for the application of `api_key`.
+    ///
+    /// Pools of other api keys fall back to the collection or global options, see
+    /// [`BongoConfig::resolve_path`].
+    pub fn has_app(&self, api_key: &str) -> bool {
+        self.config().has_app(api_key)
+    }
+
+    /// The current configuration.
+    fn config(&self) -> Arc<BongoConfig> {
+        self.config.read().clone()
+    }
+
+    fn downgrade(&self) -> WeakPoolManager {
+        WeakPoolManager {
+            cache: Arc::downgrade(&self.cache),
+            config: Arc::downgrade(&self.config),
+            created: Arc::downgrade(&self.created),
+        }
+    }
+
     /// The current size of the cache.
     ///
     /// Used for introspection.
     pub fn cache_size(&self) -> usize {
-        let cache = self.pools.read();
+        let cache = self.cache.pools.read();
         cache.len()
     }
 
-    fn read_from_cache(&self, key: &str) -> Option<Pool> {
-        let cache = self.pools.read();
-        cache.get(key).cloned()
+    /// The number of distinct clients shared by the cached pools.
+    ///
+    /// Pools whose options resolve to the same connection string share a client.
+    pub fn client_count(&self) -> usize {
+        self.cache.client_count()
+    }
+
+    /// The number of pools the manager has created, including evicted ones.
+    ///
+    /// Used for introspection.
+    pub fn pools_created(&self) -> usize {
+        self.created.load(Ordering::Relaxed)
+    }
+
+    /// The state of every cached pool, by the path of its configuration.
+    pub fn pool_status(&self) -> HashMap<String, PoolStatus> {
+        let cache = self.cache.pools.read();
+        cache
+            .iter()
+            .filter_map(|(key, slot)| Some((key.clone(), slot.get()?.status())))
+            .collect()
+    }
+
+    /// Check the health of every cached pool once.
+    ///
+    /// Pools that went unused for longer than the idle TTL, or failed too many pings in a row,
+    /// are evicted from the cache, and rebuilt on their next access.
+    pub async fn check_health(&self, options: &HealthCheck) {
+        health::check(&self.cache, options).await
+    }
+
+    /// Spawn a task checking the health of the cached pools periodically.
+    ///
+    /// The task stops once every clone of the manager is dropped.
+    pub fn spawn_health_monitor(&self, options: HealthCheck) -> JoinHandle<()> {
+        let cache = Arc::downgrade(&self.cache);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(options.interval);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                match cache.upgrade() {
+                    Some(cache) => health::check(&cache, &options).await,
+                    None => break,
+                }
+            }
+        })
+    }
+
+    /// Create a pool manager running the operations of the `dao` traits on a memory store.
+    ///
+    /// Pools are resolved with `config` as usual, and take the loose options of the
+    /// configuration when it has them, but they are not cached and never connect. Available
+    /// only if the `testing` feature is enabled, see [`crate::testing`].
+    #[cfg(feature = "testing")]
+    pub fn with_memory_store(config: Config, store: MemoryStore) -> Result<Self> {
+        let config = Arc::new(BongoConfig::new(config)?);
+        Ok(Self {
+            cache: Arc::new(Cache::with_memory_store(store)),
+            config: Arc::new(RwLock::new(config)),
+            ..Default::default()
+        })
+    }
+
+    /// Get the pool on the memory store of the manager, if it has one.
+    ///
+    /// Memory stores need no connection string, so paths without a configuration get a pool
+    /// without options.
+    #[cfg(feature = "testing")]
+    async fn memory_pool(
+        &self,
+        config: &BongoConfig,
+        path: &ConfigPath,
+        api_key: Option<&str>,
+    ) -> Result<Option<Pool>> {
+        let memory = match self.cache.memory.as_ref() {
+            Some(memory) => memory,
+            None => return Ok(None),
+        };
+        let options = match config.to_opts(path).await {
+            Ok(opts) => opts.other,
+            Err(BongoError::MissingConfig(_)) => LooseOptions(HashMap::new()),
+            Err(err) => return Err(err),
+        };
+        Ok(Some(Pool::memory(memory.database(api_key), options)))
     }
 
-    fn write_to_cache(&self, key: &str, pool: Pool) {
-        let mut cache = self.pools.write();
-        cache.insert(key.to_string(), pool);
+    /// Get the pool cached for `path`, or build it from the current configuration.
+    ///
+    /// Concurrent callers missing the same path wait for a single pool to be built, and pools
+    /// built with the same connection string share their client. The loose options stay
+    /// specific to each pool.
+    ///
+    /// A reload can replace the configuration while the pool is built. A pool that does not
+    /// match the configuration current once it is built is replaced, so that it is never
+    /// cached with stale options.
+    async fn cached_pool(&self, config: &Arc<BongoConfig>, path: ConfigPath) -> Result<Pool> {
+        let key = path.to_string();
+        let mut config = config.clone();
+        loop {
+            let slot = self.cache.slot(key.clone());
+            #[cfg(feature = "metrics")]
+            crate::metrics::record_pool_request(&path, slot.initialized());
+            let entry = slot
+                .get_or_try_init(|| async {
+                    let opts = config.to_opts(&path).await?;
+                    let client = self.cache.client(&opts.uri, opts.connection)?;
+                    self.created.fetch_add(1, Ordering::Relaxed);
+                    #[cfg(feature = "metrics")]
+                    crate::metrics::record_pool_added(&path);
+                    let pool = Pool::new(client, opts.other);
+                    Ok::<_, BongoError>(CachedPool::new(pool, path.clone(), opts.uri, &config))
+                })
+                .await?;
+
+            config = self.config();
+            if entry.is_up_to_date(&config).await {
+                return Ok(entry.pool());
+            }
+            self.cache.evict(&key, &slot, false);
+        }
     }
 
     /// Get the global pool for the given permission type.
     ///
     /// First we check the inner cache for an existing pool, otherwise
-    /// we create a new pool and store into the cache.
+    /// we create a new pool and store into the cache, once for all concurrent callers.
     pub async fn global_pool(&self, permission: PoolPermissionType) -> Result<Pool> {
-        let path = path::PermissionPath::new(permission);
-        let key = path.to_string();
-
-        if let Some(hit) = self.read_from_cache(&key) {
-            Ok(hit)
-        } else {
-            let opts = self.config.to_global_opts(&path).await?;
-            let client = Client::with_options(opts.connection)?;
-            self.write_to_cache(&key, Pool::new(client, opts.other));
-            Ok(self.read_from_cache(&key).unwrap())
+        let config = self.config();
+        let path = path::PermissionPath::new(permission).into();
+        #[cfg(feature = "testing")]
+        if let Some(pool) = self.memory_pool(&config, &path, None).await? {
+            return Ok(pool);
         }
+        self.cached_pool(&config, path).await
     }
 
     /// Get the collection pool for the given permission type.
     ///
     /// First we check the inner cache for an existing pool, otherwise
-    /// we create a new pool and store into the cache.
+    /// we create a new pool and store into the cache, once for all concurrent callers.
     pub async fn collection_pool(
         &self,
         permission: PoolPermissionType,
         collection: impl AsRef<str>,
         api_key: Option<&str>,
     ) -> Result<Pool> {
-        let path = self.config.resolve_path(api_key, collection, permission);
-        let key = path.to_string();
+        let config = self.config();
+        let path = config.resolve_path(api_key, collection, permission);
+        #[cfg(feature = "tracing")]
+        {
+            use ::tracing::field::display;
+            let span = ::tracing::Span::current();
+            span.record("permission", display(permission));
+            span.record("config_path", display(&path));
+        }
+        #[cfg(feature = "testing")]
+        if let Some(pool) = self.memory_pool(&config, &path, api_key).await? {
+            return Ok(pool);
+        }
+        self.cached_pool(&config, path).await
+    }
+}
 
-        if let Some(hit) = self.read_from_cache(&key) {
-            Ok(hit)
-        } else {
-            let opts = self.config.to_opts(&path).await?;
-            let client = Client::with_options(opts.connection)?;
-            self.write_to_cache(&key, Pool::new(client, opts.other));
-            Ok(self.read_from_cache(&key).unwrap())
+impl PoolManager {
+    /// Replace the configuration of the manager, returning the paths of the replaced pools.
+    ///
+    /// Only the cached pools whose connection string or loose options changed are replaced,
+    /// or dropped if their configuration was removed. Clones of the replaced pools keep
+    /// working until dropped, which lets in-flight operations drain.
+    ///
+    /// Pools are resolved with the new configuration from then on, so a collection or app
+    /// section added by the reload is picked up on the next access.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`BongoError::InvalidConfig`] if the new configuration does not validate,
+    /// in which case the current configuration stays active.
+    pub async fn reload(&self, config: Config) -> Result<Vec<ConfigPath>> {
+        let config = BongoConfig::new(config)?;
+        config.validate()?;
+        let config = Arc::new(config);
+        *self.config.write() = config.clone();
+
+        let mut replaced = Vec::new();
+        for (key, slot) in self.cache.slots() {
+            // Pools still being built are checked against the new configuration once built
+            let entry = match slot.get() {
+                Some(entry) => entry,
+                None => continue,
+            };
+            if entry.is_up_to_date(&config).await {
+                continue;
+            }
+            let path = entry.path().clone();
+
+            self.cache.evict(&key, &slot, false);
+            // Paths that no longer resolve are built again only when accessed
+            let _ = self.cached_pool(&config, path.clone()).await;
+            replaced.push(path);
         }
+        Ok(replaced)
     }
+
+    /// Spawn a task reloading the configuration whenever one of `files` is modified.
+    ///
+    /// The files are polled every `interval`. When one of them changes, `load` builds the
+    /// new configuration and `on_reload` receives the outcome of the reload. The task stops
+    /// once every clone of the manager is dropped.
+    pub fn spawn_config_watcher<L, R>(
+        &self,
+        files: Vec<PathBuf>,
+        interval: Duration,
+        load: L,
+        on_reload: R,
+    ) -> JoinHandle<()>
+    where
+        L: Fn() -> Result<Config> + Send + 'static,
+        R: Fn(Result<Vec<ConfigPath>>) + Send + 'static,
+    {
+        let manager = self.downgrade();
+        tokio::spawn(async move {
+            let mut modified = modified_times(&files);
+            let mut interval = tokio::time::interval(interval);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                let manager = match manager.upgrade() {
+                    Some(manager) => manager,
+                    None => break,
+                };
+                let current = modified_times(&files);
+                if current == modified {
+                    continue;
+                }
+                modified = current;
+
+                let result = match load() {
+                    Ok(config) => manager.reload(config).await,
+                    Err(err) => Err(err),
+                };
+                on_reload(result);
+            }
+        })
+    }
+}
+
+/// The last modification time of each file, if it exists.
+fn modified_times(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    files
+        .iter()
+        .map(|file| {
+            std::fs::metadata(file)
+                .and_then(|meta| meta.modified())
+                .ok()
+        })
+        .collect()
+}
+
+impl PoolManager {
+    /// Create the indexes the given collections declare, if missing.
+    ///
+    /// Indexes are created through the write pool of each collection, for the default pools
+    /// and for the pools of every app of the configuration. Existing indexes are never
+    /// changed or dropped; the returned reports list the ones that differ from the
+    /// declarations instead.
+    pub async fn ensure_indexes(
+        &self,
+        collections: &[&(dyn Collection + Sync)],
+    ) -> Result<Vec<IndexReport>> {
+        let mut api_keys: Vec<Option<String>> = vec![None];
+        if let Some(apps) = self.config().app() {
+            let mut apps: Vec<_> = apps.keys().cloned().map(Some).collect();
+            apps.sort();
+            api_keys.extend(apps);
+        }
+
+        let mut reports = Vec::with_capacity(collections.len() * api_keys.len());
+        for collection in collections {
+            for api_key in &api_keys {
+                let mut report = self
+                    .ensure_collection_indexes(*collection, api_key.as_deref())
+                    .await?;
+                report.api_key = api_key.clone();
+                reports.push(report);
+            }
+        }
+        Ok(reports)
+    }
+
+    /// Create the indexes `collection` declares in the write pool of `api_key`, if missing.
+    async fn ensure_collection_indexes(
+        &self,
+        collection: &(dyn Collection + Sync),
+        api_key: Option<&str>,
+    ) -> Result<IndexReport> {
+        let pool = self
+            .collection_pool(PoolPermissionType::Write, collection.name(), api_key)
+            .await?;
+        #[cfg(feature = "testing")]
+        if let Connection::Memory(database) = &pool.connection {
+            let name = pool.collection_name(collection.name())?;
+            return Ok(database.ensure_indexes(&name, collection.indexes()));
+        }
+        let connection = pool.collection::<Document>(collection.name())?;
+
+        let mut existing = Vec::new();
+        match connection.list_indexes(None).await {
+            Ok(mut cursor) => {
+                while cursor.advance().await? {
+                    existing.push(cursor.deserialize_current()?);
+                }
+            }
+            // The collection does not exist yet
+            Err(err) if is_namespace_not_found(&err) => {}
+            Err(err) => return Err(err.into()),
+        }
+
+        let (missing, report) =
+            indexes::compare(collection.name(), collection.indexes(), &existing);
+        if !missing.is_empty() {
+            connection.create_indexes(missing, None).await?;
+        }
+        Ok(report)
+    }
+}
+
+/// Check if the driver error reports a missing collection.
+fn is_namespace_not_found(err: &mongodb::error::Error) -> bool {
+    const NAMESPACE_NOT_FOUND: i32 = 26;
+
+    matches!(&*err.kind, ErrorKind::Command(err) if err.code == NAMESPACE_NOT_FOUND)
 }
 
 impl TryFrom<Value> for PoolManager {
@@ -236,4 +674,300 @@ mod test {
         }
         assert_eq!(pool_manager.cache_size(), 2);
     }
+
+    fn health_config(base_uri: &str) -> Config {
+        let source = format!(
+            r#"{{
+                "users": {{
+                    "read" : {{
+                        "baseUri": "{}",
+                        "serverSelectionTimeoutMS": "10"
+                    }}
+                }}
+            }}"#,
+            base_uri
+        );
+        Config::builder()
+            .add_source(File::from_str(&source, FileFormat::Json))
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn pool_manager_evicts_idle_pools() {
+        let pool_manager = PoolManager::new(health_config("mongodb://wat.com/users")).unwrap();
+        pool_manager
+            .collection_pool(PoolPermissionType::Read, "users", None)
+            .await
+            .unwrap();
+        assert_eq!(pool_manager.pool_status().len(), 1);
+
+        let options = HealthCheck {
+            idle_ttl: Some(std::time::Duration::ZERO),
+            ..Default::default()
+        };
+        pool_manager.check_health(&options).await;
+        assert_eq!(pool_manager.cache_size(), 0);
+
+        // Rebuilt on the next access
+        pool_manager
+            .collection_pool(PoolPermissionType::Read, "users", None)
+            .await
+            .unwrap();
+        assert_eq!(pool_manager.cache_size(), 1);
+    }
+
+    #[tokio::test]
+    async fn pool_manager_evicts_failing_pools() {
+        // Nothing listens on port 1, so pings fail fast
+        let pool_manager = PoolManager::new(health_config("mongodb://127.0.0.1:1/users")).unwrap();
+        pool_manager
+            .collection_pool(PoolPermissionType::Read, "users", None)
+            .await
+            .unwrap();
+
+        let options = HealthCheck {
+            max_failures: 2,
+            ..Default::default()
+        };
+        pool_manager.check_health(&options).await;
+        let status = pool_manager.pool_status();
+        let status = status.values().next().unwrap();
+        assert!(!status.healthy);
+        assert_eq!(status.consecutive_failures, 1);
+        assert!(status.last_error.is_some());
+
+        pool_manager.check_health(&options).await;
+        assert_eq!(pool_manager.cache_size(), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn pool_manager_builds_one_client_per_key() {
+        let pool_manager = PoolManager::new(health_config("mongodb://wat.com/users")).unwrap();
+        let tasks: Vec<_> = (0..500)
+            .map(|_| {
+                let manager = pool_manager.clone();
+                tokio::spawn(async move {
+                    manager
+                        .collection_pool(PoolPermissionType::Read, "users", None)
+                        .await
+                        .unwrap()
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(pool_manager.pools_created(), 1);
+        assert_eq!(pool_manager.client_count(), 1);
+        assert_eq!(pool_manager.cache_size(), 1);
+    }
+
+    #[tokio::test]
+    async fn pool_manager_shares_clients() {
+        let source = r#"{
+            "read" : {
+                "baseUri": "mongodb://wat.com/db",
+                "maxPoolSize": "15",
+                "connectTimeoutMS": "15"
+            },
+            "redemptions": {
+                "read" : {
+                    "connectTimeoutMS": "15",
+                    "maxPoolSize": "15",
+                    "collection": "redemptions_v2"
+                }
+            },
+            "installations": {
+                "read" : {
+                    "maxPoolSize": "30"
+                }
+            }
+        }
+        "#;
+        let config = Config::builder()
+            .add_source(File::from_str(source, FileFormat::Json))
+            .build()
+            .unwrap();
+        let pool_manager = PoolManager::new(config).unwrap();
+
+        let global = pool_manager
+            .global_pool(PoolPermissionType::Read)
+            .await
+            .unwrap();
+        let redemptions = pool_manager
+            .collection_pool(PoolPermissionType::Read, "redemptions", None)
+            .await
+            .unwrap();
+        pool_manager
+            .collection_pool(PoolPermissionType::Read, "installations", None)
+            .await
+            .unwrap();
+
+        assert_eq!(pool_manager.cache_size(), 3);
+        // The installations pool has a different `maxPoolSize`
+        assert_eq!(pool_manager.client_count(), 2);
+        assert!(global.options().get(&LooseOption::Collection).is_none());
+        assert!(redemptions
+            .options()
+            .get(&LooseOption::Collection)
+            .is_some());
+    }
+
+    fn reload_config(installations_pool_size: &str, tenant: bool) -> Config {
+        let tenant = if tenant {
+            r#""mongodbPerApp": {
+                "wat": {
+                    "installations": {
+                        "read" : {
+                            "baseUri": "mongodb://wat.com/wat_installations"
+                        }
+                    }
+                }
+            },"#
+        } else {
+            ""
+        };
+        let source = format!(
+            r#"{{
+                {}
+                "read" : {{
+                    "baseUri": "mongodb://wat.com/db"
+                }},
+                "redemptions": {{
+                    "read" : {{
+                        "maxPoolSize": "15"
+                    }}
+                }},
+                "installations": {{
+                    "read" : {{
+                        "maxPoolSize": "{}"
+                    }}
+                }}
+            }}"#,
+            tenant, installations_pool_size
+        );
+        Config::builder()
+            .add_source(File::from_str(&source, FileFormat::Json))
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn pool_manager_reload_replaces_changed_pools() {
+        let pool_manager = PoolManager::new(reload_config("30", false)).unwrap();
+        for collection in ["redemptions", "installations"] {
+            pool_manager
+                .collection_pool(PoolPermissionType::Read, collection, None)
+                .await
+                .unwrap();
+        }
+        pool_manager
+            .global_pool(PoolPermissionType::Read)
+            .await
+            .unwrap();
+        assert_eq!(pool_manager.pools_created(), 3);
+
+        let replaced = pool_manager
+            .reload(reload_config("40", false))
+            .await
+            .unwrap();
+        assert_eq!(
+            replaced,
+            vec![ConfigPath::from(path::CollectionPath::new(
+                "installations",
+                PoolPermissionType::Read
+            ))]
+        );
+        assert_eq!(pool_manager.pools_created(), 4);
+        assert_eq!(pool_manager.cache_size(), 3);
+        assert_eq!(pool_manager.client_count(), 3);
+
+        // Nothing changed
+        let replaced = pool_manager
+            .reload(reload_config("40", false))
+            .await
+            .unwrap();
+        assert!(replaced.is_empty());
+        assert_eq!(pool_manager.pools_created(), 4);
+    }
+
+    #[tokio::test]
+    async fn pool_manager_reload_keeps_the_config_if_invalid() {
+        let pool_manager = PoolManager::new(reload_config("30", false)).unwrap();
+        let path = ConfigPath::from(path::CollectionPath::new(
+            "installations",
+            PoolPermissionType::Read,
+        ));
+        pool_manager
+            .cached_pool(&pool_manager.config(), path.clone())
+            .await
+            .unwrap();
+
+        let reloaded = pool_manager.reload(reload_config("lots", false)).await;
+        assert!(matches!(reloaded, Err(BongoError::InvalidConfig(_))));
+        let opts = pool_manager.config().to_opts(&path).await.unwrap();
+        assert_eq!(opts.connection.max_pool_size, Some(30));
+        assert_eq!(pool_manager.pools_created(), 1);
+    }
+
+    #[tokio::test]
+    async fn pool_manager_reload_replaces_pools_being_built() {
+        let pool_manager = PoolManager::new(reload_config("30", false)).unwrap();
+        let path = ConfigPath::from(path::CollectionPath::new(
+            "installations",
+            PoolPermissionType::Read,
+        ));
+
+        // A build that captured the configuration before the reload, and caches its pool after
+        let stale = pool_manager.config();
+        let replaced = pool_manager
+            .reload(reload_config("40", false))
+            .await
+            .unwrap();
+        assert!(replaced.is_empty());
+        pool_manager
+            .cached_pool(&stale, path.clone())
+            .await
+            .unwrap();
+
+        let config = pool_manager.config();
+        let opts = config.to_opts(&path).await.unwrap();
+        let slot = pool_manager.cache.slot(path.to_string());
+        assert_eq!(slot.get().unwrap().uri(), &opts.uri);
+        assert_eq!(pool_manager.pools_created(), 2);
+        assert_eq!(pool_manager.cache_size(), 1);
+        assert_eq!(pool_manager.client_count(), 1);
+
+        // The replacement is up to date
+        pool_manager
+            .collection_pool(PoolPermissionType::Read, "installations", None)
+            .await
+            .unwrap();
+        assert_eq!(pool_manager.pools_created(), 2);
+    }
+
+    #[tokio::test]
+    async fn pool_manager_reload_adds_apps() {
+        let pool_manager = PoolManager::new(reload_config("30", false)).unwrap();
+        let pool = pool_manager
+            .collection_pool(PoolPermissionType::Read, "installations", Some("wat"))
+            .await
+            .unwrap();
+        assert_eq!(pool.client().default_database().unwrap().name(), "db");
+
+        let replaced = pool_manager
+            .reload(reload_config("30", true))
+            .await
+            .unwrap();
+        assert!(replaced.is_empty());
+        let pool = pool_manager
+            .collection_pool(PoolPermissionType::Read, "installations", Some("wat"))
+            .await
+            .unwrap();
+        assert_eq!(
+            pool.client().default_database().unwrap().name(),
+            "wat_installations"
+        );
+    }
 }
@@ -0,0 +1,505 @@
+//! Builders of filters and updates, checked against the fields of the models.
+//!
+//! Fields can be named by their path, or by the constants `#[derive(Fields)]` generates from
+//! the serde names of a model, which also check the types of the values compared with them:
+//!
+//! ```ignore
+//! use bongo_mong::query::{Fields, Filter, Update};
+//!
+//! #[derive(Deserialize, Serialize, Fields)]
+//! #[serde(rename_all = "camelCase")]
+//! pub struct User {
+//!     pub id: String,
+//!     pub language_id: String,
+//!     pub age: u8,
+//! }
+//!
+//! let filter = User::ID.eq("1").and(User::AGE.gte(18));
+//! let update = User::LANGUAGE_ID.set("en").and(Update::inc("visits", 1))?;
+//! collection.update_one(filter.into(), update, None, None).await?;
+//! ```
+use crate::error::{BongoError, Result};
+use mongodb::{
+    bson::{doc, oid::ObjectId, Bson, DateTime, Document},
+    options::UpdateModifications,
+};
+use std::borrow::Cow;
+use std::fmt;
+use std::marker::PhantomData;
+
+/// Derive a constant for every serialized field of a model, as `User::LANGUAGE_ID`.
+///
+/// Available only if the `derive` feature is enabled.
+#[cfg(feature = "derive")]
+pub use bongo_mong_derive::Fields;
+
+/// The dotted path of a field in a document.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct FieldPath(Cow<'static, str>);
+
+impl FieldPath {
+    /// The path of a field embedded in this one, as `sessions.<id>`.
+    pub fn key(self, key: impl AsRef<str>) -> Self {
+        Self(format!("{}.{}", self.0, key.as_ref()).into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for FieldPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<&'static str> for FieldPath {
+    fn from(path: &'static str) -> Self {
+        Self(path.into())
+    }
+}
+
+impl From<String> for FieldPath {
+    fn from(path: String) -> Self {
+        Self(path.into())
+    }
+}
+
+impl<M, T> From<Field<M, T>> for FieldPath {
+    fn from(field: Field<M, T>) -> Self {
+        Self(field.name.into())
+    }
+}
+
+/// A field of the model `M` holding values of type `T`, as it is named when serialized.
+pub struct Field<M, T> {
+    name: &'static str,
+    types: PhantomData<fn() -> (M, T)>,
+}
+
+// Implemented by hand, as derives would require the model and the value to be `Copy`
+impl<M, T> Clone for Field<M, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<M, T> Copy for Field<M, T> {}
+
+impl<M, T> fmt::Debug for Field<M, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Field").field(&self.name).finish()
+    }
+}
+
+impl<M, T> Field<M, T> {
+    /// Name a field of the model, as done by `#[derive(Fields)]`.
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            types: PhantomData,
+        }
+    }
+
+    /// The serialized name of the field.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The path of a field embedded in this one, as `sessions.<id>`.
+    pub fn key(self, key: impl AsRef<str>) -> FieldPath {
+        FieldPath::from(self).key(key)
+    }
+
+    /// Match the documents where the field is missing or not.
+    pub fn exists(self, exists: bool) -> Filter {
+        Filter::exists(self, exists)
+    }
+
+    /// Remove the field.
+    pub fn unset(self) -> Update {
+        Update::unset(self)
+    }
+}
+
+impl<M, T: IntoBson> Field<M, T> {
+    /// Match the documents where the field equals `value`.
+    pub fn eq(self, value: impl Into<T>) -> Filter {
+        Filter::eq(self, value.into())
+    }
+
+    /// Match the documents where the field does not equal `value`.
+    pub fn ne(self, value: impl Into<T>) -> Filter {
+        Filter::ne(self, value.into())
+    }
+
+    /// Match the documents where the field is greater than `value`.
+    pub fn gt(self, value: impl Into<T>) -> Filter {
+        Filter::gt(self, value.into())
+    }
+
+    /// Match the documents where the field is greater than or equal to `value`.
+    pub fn gte(self, value: impl Into<T>) -> Filter {
+        Filter::gte(self, value.into())
+    }
+
+    /// Match the documents where the field is less than `value`.
+    pub fn lt(self, value: impl Into<T>) -> Filter {
+        Filter::lt(self, value.into())
+    }
+
+    /// Match the documents where the field is less than or equal to `value`.
+    pub fn lte(self, value: impl Into<T>) -> Filter {
+        Filter::lte(self, value.into())
+    }
+
+    /// Match the documents where the field equals any of `values`.
+    pub fn is_in<V: Into<T>>(self, values: impl IntoIterator<Item = V>) -> Filter {
+        Filter::is_in(self, values.into_iter().map(Into::into))
+    }
+
+    /// Set the field to `value`.
+    pub fn set(self, value: impl Into<T>) -> Update {
+        Update::set(self, value.into())
+    }
+
+    /// Increment the field by `value`.
+    pub fn inc(self, value: impl Into<T>) -> Update {
+        Update::inc(self, value.into())
+    }
+}
+
+/// Values that can be compared with fields, or stored in them.
+pub trait IntoBson {
+    fn into_bson(self) -> Bson;
+}
+
+macro_rules! impl_into_bson {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl IntoBson for $ty {
+                fn into_bson(self) -> Bson {
+                    self.into()
+                }
+            }
+        )*
+    };
+}
+
+impl_into_bson!(Bson, bool, f64, i32, i64, String, &str, Document, ObjectId, DateTime);
+
+// Mongo Db has no unsigned integers, so store them in the smallest signed type that fits
+impl IntoBson for u8 {
+    fn into_bson(self) -> Bson {
+        Bson::Int32(self.into())
+    }
+}
+
+impl IntoBson for u16 {
+    fn into_bson(self) -> Bson {
+        Bson::Int32(self.into())
+    }
+}
+
+impl IntoBson for u32 {
+    fn into_bson(self) -> Bson {
+        Bson::Int64(self.into())
+    }
+}
+
+impl<T: IntoBson> IntoBson for Option<T> {
+    fn into_bson(self) -> Bson {
+        self.map_or(Bson::Null, IntoBson::into_bson)
+    }
+}
+
+impl<T: IntoBson> IntoBson for Vec<T> {
+    fn into_bson(self) -> Bson {
+        Bson::Array(self.into_iter().map(IntoBson::into_bson).collect())
+    }
+}
+
+/// A filter of the documents of a collection.
+///
+/// The default filter matches every document.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Filter(Document);
+
+impl Filter {
+    /// Match the documents where the field equals `value`.
+    pub fn eq(field: impl Into<FieldPath>, value: impl IntoBson) -> Self {
+        let mut filter = Document::new();
+        filter.insert(field.into().0, value.into_bson());
+        Self(filter)
+    }
+
+    /// Match the documents where the field does not equal `value`.
+    pub fn ne(field: impl Into<FieldPath>, value: impl IntoBson) -> Self {
+        Self::operator(field, "$ne", value.into_bson())
+    }
+
+    /// Match the documents where the field is greater than `value`.
+    pub fn gt(field: impl Into<FieldPath>, value: impl IntoBson) -> Self {
+        Self::operator(field, "$gt", value.into_bson())
+    }
+
+    /// Match the documents where the field is greater than or equal to `value`.
+    pub fn gte(field: impl Into<FieldPath>, value: impl IntoBson) -> Self {
+        Self::operator(field, "$gte", value.into_bson())
+    }
+
+    /// Match the documents where the field is less than `value`.
+    pub fn lt(field: impl Into<FieldPath>, value: impl IntoBson) -> Self {
+        Self::operator(field, "$lt", value.into_bson())
+    }
+
+    /// Match the documents where the field is less than or equal to `value`.
+    pub fn lte(field: impl Into<FieldPath>, value: impl IntoBson) -> Self {
+        Self::operator(field, "$lte", value.into_bson())
+    }
+
+    /// Match the documents where the field equals any of `values`.
+    pub fn is_in<V: IntoBson>(
+        field: impl Into<FieldPath>,
+        values: impl IntoIterator<Item = V>,
+    ) -> Self {
+        let values = values.into_iter().map(IntoBson::into_bson).collect();
+        Self::operator(field, "$in", Bson::Array(values))
+    }
+
+    /// Match the documents where the field is missing or not.
+    pub fn exists(field: impl Into<FieldPath>, exists: bool) -> Self {
+        Self::operator(field, "$exists", Bson::Boolean(exists))
+    }
+
+    fn operator(field: impl Into<FieldPath>, operator: &str, value: Bson) -> Self {
+        let mut condition = Document::new();
+        condition.insert(operator, value);
+        Self::eq(field, condition)
+    }
+
+    /// Match the documents matching both filters.
+    ///
+    /// Filters on different fields are merged, others are combined with `$and`.
+    pub fn and(self, other: Filter) -> Self {
+        let disjoint = self
+            .0
+            .keys()
+            .chain(other.0.keys())
+            .all(|key| !key.starts_with('$'))
+            && other.0.keys().all(|key| !self.0.contains_key(key));
+        if disjoint {
+            let mut filter = self.0;
+            filter.extend(other.0);
+            return Self(filter);
+        }
+        Self(doc! {"$and": self.clauses("$and").chain(other.clauses("$and")).collect::<Vec<_>>()})
+    }
+
+    /// Match the documents matching either filter.
+    pub fn or(self, other: Filter) -> Self {
+        Self(doc! {"$or": self.clauses("$or").chain(other.clauses("$or")).collect::<Vec<_>>()})
+    }
+
+    /// The clauses of the filter, flattening the ones of a lone `operator`.
+    fn clauses(self, operator: &str) -> impl Iterator<Item = Bson> {
+        let clauses = match self.0.get_array(operator) {
+            Ok(clauses) if self.0.len() == 1 => clauses.clone(),
+            _ => vec![Bson::Document(self.0)],
+        };
+        clauses.into_iter()
+    }
+
+    /// Check if the filter matches every document.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<Document> for Filter {
+    fn from(filter: Document) -> Self {
+        Self(filter)
+    }
+}
+
+impl From<Filter> for Document {
+    fn from(filter: Filter) -> Self {
+        filter.0
+    }
+}
+
+impl From<Filter> for Option<Document> {
+    fn from(filter: Filter) -> Self {
+        Some(filter.0)
+    }
+}
+
+/// An update of the documents of a collection, made of update operators.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Update(Document);
+
+impl Update {
+    /// Set the field to `value`.
+    pub fn set(field: impl Into<FieldPath>, value: impl IntoBson) -> Self {
+        Self::operator("$set", field, value.into_bson())
+    }
+
+    /// Remove the field.
+    pub fn unset(field: impl Into<FieldPath>) -> Self {
+        Self::operator("$unset", field, Bson::String(String::new()))
+    }
+
+    /// Increment the field by `value`.
+    pub fn inc(field: impl Into<FieldPath>, value: impl IntoBson) -> Self {
+        Self::operator("$inc", field, value.into_bson())
+    }
+
+    fn operator(operator: &str, field: impl Into<FieldPath>, value: Bson) -> Self {
+        let mut fields = Document::new();
+        fields.insert(field.into().0, value);
+        let mut update = Document::new();
+        update.insert(operator, fields);
+        Self(update)
+    }
+
+    /// Apply both updates.
+    ///
+    /// # Errors
+    ///
+    /// Mongo rejects updates changing a field more than once, or along with one of its
+    /// parents or children, so combining such updates fails with
+    /// [`BongoError::ConflictingUpdate`].
+    pub fn and(mut self, other: Update) -> Result<Self> {
+        for (operator, fields) in other.0 {
+            if let Bson::Document(fields) = &fields {
+                if let Some(path) = self.conflict(fields) {
+                    return Err(BongoError::ConflictingUpdate(path));
+                }
+            }
+            match (self.0.get_document_mut(&operator), fields) {
+                (Ok(existing), Bson::Document(fields)) => existing.extend(fields),
+                (_, fields) => {
+                    self.0.insert(operator, fields);
+                }
+            }
+        }
+        Ok(self)
+    }
+
+    /// The first field changed by the update conflicting with one of `fields`.
+    fn conflict(&self, fields: &Document) -> Option<String> {
+        self.0
+            .values()
+            .filter_map(|changed| changed.as_document())
+            .flat_map(|changed| changed.keys())
+            .find(|path| fields.keys().any(|field| overlaps(path, field)))
+            .cloned()
+    }
+
+    /// Check if the update changes nothing.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Check if one of the dotted paths is the other or one of its parents.
+fn overlaps(path: &str, other: &str) -> bool {
+    let (short, long) = if path.len() <= other.len() {
+        (path, other)
+    } else {
+        (other, path)
+    };
+    long.starts_with(short) && matches!(long.as_bytes().get(short.len()), None | Some(b'.'))
+}
+
+impl From<Update> for Document {
+    fn from(update: Update) -> Self {
+        update.0
+    }
+}
+
+impl From<Update> for UpdateModifications {
+    fn from(update: Update) -> Self {
+        UpdateModifications::Document(update.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct User;
+
+    impl User {
+        const ID: Field<User, String> = Field::new("id");
+        const AGE: Field<User, u8> = Field::new("age");
+        const SESSIONS: Field<User, Document> = Field::new("sessions");
+    }
+
+    #[test]
+    fn filter_builds_documents() {
+        let filter = User::ID.eq("1").and(User::AGE.gte(18));
+        assert_eq!(
+            Document::from(filter),
+            doc! {"id": "1", "age": {"$gte": 18}}
+        );
+
+        let filter = User::AGE
+            .gt(18)
+            .and(User::AGE.lt(30))
+            .and(User::ID.is_in(["1", "2"]));
+        assert_eq!(
+            Document::from(filter),
+            doc! {"$and": [{"age": {"$gt": 18}}, {"age": {"$lt": 30}}, {"id": {"$in": ["1", "2"]}}]}
+        );
+
+        let filter =
+            Filter::eq(User::SESSIONS.key("s1").key("active"), true).or(User::ID.exists(false));
+        assert_eq!(
+            Document::from(filter),
+            doc! {"$or": [{"sessions.s1.active": true}, {"id": {"$exists": false}}]}
+        );
+    }
+
+    #[test]
+    fn update_builds_documents() {
+        let update = User::AGE
+            .set(30)
+            .and(Update::set("name", "Ann"))
+            .and_then(|update| update.and(User::SESSIONS.unset()))
+            .and_then(|update| update.and(Update::inc("visits", 1)))
+            .unwrap();
+        assert_eq!(
+            Document::from(update),
+            doc! {
+                "$set": {"age": 30, "name": "Ann"},
+                "$unset": {"sessions": ""},
+                "$inc": {"visits": 1}
+            }
+        );
+        assert!(Update::default().is_empty());
+    }
+
+    #[test]
+    fn update_rejects_conflicting_fields() {
+        let conflict = |update: Result<Update>| match update {
+            Err(BongoError::ConflictingUpdate(path)) => path,
+            other => panic!("expected a conflict, got {:?}", other),
+        };
+        assert_eq!(conflict(User::AGE.set(30).and(User::AGE.inc(1))), "age");
+
+        // The parents and children of a changed field conflict too, unlike its siblings
+        let update = Update::set("sessions.s1.active", true)
+            .and(Update::set("sessions.s10.active", true))
+            .unwrap();
+        assert_eq!(
+            conflict(update.clone().and(Update::unset(User::SESSIONS.key("s1")))),
+            "sessions.s1.active"
+        );
+        assert_eq!(
+            conflict(update.and(Update::set("sessions.s10.active.at", 1))),
+            "sessions.s10.active"
+        );
+    }
+}
@@ -0,0 +1,78 @@
+//! The results of the write operations of [`Query`](crate::dao::Query).
+//!
+//! The results of the driver can not be built outside of it, so the library has its own, which
+//! lets every backend of the `dao` traits return the same types. They serialize like the ones
+//! of the driver.
+use mongodb::{bson::Bson, results};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// The result of an `insert_one` operation.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InsertOneResult {
+    /// The `_id` field of the document inserted.
+    pub inserted_id: Bson,
+}
+
+/// The result of an `insert_many` operation.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InsertManyResult {
+    /// The `_id` field of the documents inserted, by their index.
+    pub inserted_ids: HashMap<usize, Bson>,
+}
+
+/// The result of the update and replace operations.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateResult {
+    /// The number of documents that matched the filter.
+    pub matched_count: u64,
+    /// The number of documents that were modified by the operation.
+    pub modified_count: u64,
+    /// The `_id` field of the upserted document.
+    pub upserted_id: Option<Bson>,
+}
+
+/// The result of the delete operations.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteResult {
+    /// The number of documents deleted by the operation.
+    pub deleted_count: u64,
+}
+
+impl From<results::InsertOneResult> for InsertOneResult {
+    fn from(result: results::InsertOneResult) -> Self {
+        Self {
+            inserted_id: result.inserted_id,
+        }
+    }
+}
+
+impl From<results::InsertManyResult> for InsertManyResult {
+    fn from(result: results::InsertManyResult) -> Self {
+        Self {
+            inserted_ids: result.inserted_ids,
+        }
+    }
+}
+
+impl From<results::UpdateResult> for UpdateResult {
+    fn from(result: results::UpdateResult) -> Self {
+        Self {
+            matched_count: result.matched_count,
+            modified_count: result.modified_count,
+            upserted_id: result.upserted_id,
+        }
+    }
+}
+
+impl From<results::DeleteResult> for DeleteResult {
+    fn from(result: results::DeleteResult) -> Self {
+        Self {
+            deleted_count: result.deleted_count,
+        }
+    }
+}
@@ -0,0 +1,263 @@
+//! Utilities to run operations in sessions and transactions.
+//!
+//! A session belongs to the client it was started on, and can only take part in operations of
+//! that client. The collections of a [`PoolManager`](crate::PoolManager) whose pools connect
+//! with the same connection string share a client, but collections of distinct pool managers,
+//! or on distinct clusters, never do, so a transaction can not span them.
+use crate::error::{BongoError, Result};
+use async_trait::async_trait;
+use futures_core::future::BoxFuture;
+use mongodb::{
+    error::{TRANSIENT_TRANSACTION_ERROR, UNKNOWN_TRANSACTION_COMMIT_RESULT},
+    options::TransactionOptions,
+    ClientSession,
+};
+use std::time::{Duration, Instant};
+
+/// How long a transaction is retried for before giving up.
+const TRANSACTION_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Run `callback` in a transaction on `session` and commit it.
+///
+/// The operations of the callback must run on the client of the session, see the
+/// [module documentation](self).
+///
+/// Transactions failing with a transient error are retried from the start, and commits with
+/// an unknown result are retried on their own, for up to two minutes. The `callback` may thus
+/// run more than once, and must neither commit nor abort the transaction itself.
+///
+/// As with [`ClientSession::with_transaction`], the `context` is handed to the callback along
+/// with the session, which lets it borrow values across retries:
+///
+/// ```no_run
+/// # use bongo_mong::{dao::Query, error::Result, session::with_transaction};
+/// # use mongodb::bson::{doc, Document};
+/// # async fn wrapper(users: impl Query<Document> + Sync) -> Result<()> {
+/// let mut session = users.start_session(None, None).await?;
+/// with_transaction(
+///     &mut session,
+///     &users,
+///     |session, users| {
+///         Box::pin(async move {
+///             let update = doc! {"$set": {"age": 40}};
+///             users
+///                 .update_one_with_session(doc! {"id": "1"}, update, None, session, None)
+///                 .await?;
+///             users
+///                 .delete_one_with_session(doc! {"id": "2"}, None, session, None)
+///                 .await
+///         })
+///     },
+///     None,
+/// )
+/// .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn with_transaction<T, C, F>(
+    session: &mut ClientSession,
+    mut context: C,
+    mut callback: F,
+    options: impl Into<Option<TransactionOptions>>,
+) -> Result<T>
+where
+    F: for<'a> FnMut(&'a mut ClientSession, &'a mut C) -> BoxFuture<'a, Result<T>>,
+{
+    run_transaction(session, &mut context, &mut callback, options.into()).await
+}
+
+/// The transaction operations of a session, which [`with_transaction`] retries.
+#[async_trait]
+trait Transaction: Send {
+    async fn start(&mut self, options: Option<TransactionOptions>) -> mongodb::error::Result<()>;
+
+    async fn abort(&mut self) -> mongodb::error::Result<()>;
+
+    async fn commit(&mut self) -> mongodb::error::Result<()>;
+}
+
+#[async_trait]
+impl Transaction for ClientSession {
+    async fn start(&mut self, options: Option<TransactionOptions>) -> mongodb::error::Result<()> {
+        self.start_transaction(options).await
+    }
+
+    async fn abort(&mut self) -> mongodb::error::Result<()> {
+        self.abort_transaction().await
+    }
+
+    async fn commit(&mut self) -> mongodb::error::Result<()> {
+        self.commit_transaction().await
+    }
+}
+
+async fn run_transaction<S, T, C, F>(
+    session: &mut S,
+    context: &mut C,
+    callback: &mut F,
+    options: Option<TransactionOptions>,
+) -> Result<T>
+where
+    S: Transaction,
+    F: for<'a> FnMut(&'a mut S, &'a mut C) -> BoxFuture<'a, Result<T>>,
+{
+    let start = Instant::now();
+
+    'transaction: loop {
+        session.start(options.clone()).await?;
+        let value = match callback(session, context).await {
+            Ok(value) => value,
+            Err(err) => {
+                // Fails only when the transaction is already over
+                let _ = session.abort().await;
+                if has_label(&err, TRANSIENT_TRANSACTION_ERROR)
+                    && start.elapsed() < TRANSACTION_TIMEOUT
+                {
+                    continue 'transaction;
+                }
+                return Err(err);
+            }
+        };
+
+        loop {
+            match session.commit().await {
+                Ok(()) => return Ok(value),
+                Err(err) if start.elapsed() >= TRANSACTION_TIMEOUT => return Err(err.into()),
+                Err(err) if err.contains_label(UNKNOWN_TRANSACTION_COMMIT_RESULT) => continue,
+                Err(err) if err.contains_label(TRANSIENT_TRANSACTION_ERROR) => {
+                    continue 'transaction
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+}
+
+/// Check if the error is a driver error carrying the given label.
+fn has_label(err: &BongoError, label: &str) -> bool {
+    match err {
+        BongoError::MongoDbError(err) => err.contains_label(label),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::{
+        bson::{self, doc},
+        error::{Error, ErrorKind, WriteConcernError, WriteFailure},
+    };
+    use std::collections::VecDeque;
+
+    /// A session whose commits fail with the queued errors.
+    #[derive(Default)]
+    struct Session {
+        calls: Vec<&'static str>,
+        commits: VecDeque<Error>,
+    }
+
+    #[async_trait]
+    impl Transaction for Session {
+        async fn start(&mut self, _: Option<TransactionOptions>) -> mongodb::error::Result<()> {
+            self.calls.push("start");
+            Ok(())
+        }
+
+        async fn abort(&mut self) -> mongodb::error::Result<()> {
+            self.calls.push("abort");
+            Ok(())
+        }
+
+        async fn commit(&mut self) -> mongodb::error::Result<()> {
+            self.calls.push("commit");
+            self.commits.pop_front().map_or(Ok(()), Err)
+        }
+    }
+
+    fn labelled(label: &str) -> Error {
+        let failure = doc! {"code": 91, "errmsg": "failure", "errorLabels": [label]};
+        let failure = bson::from_document::<WriteConcernError>(failure).unwrap();
+        Error::from(ErrorKind::Write(WriteFailure::WriteConcernError(failure)))
+    }
+
+    /// The context of a callback failing with the queued errors before it succeeds.
+    #[derive(Default)]
+    struct Callback {
+        errors: VecDeque<Error>,
+        runs: u32,
+    }
+
+    fn callback<'a>(_: &'a mut Session, context: &'a mut Callback) -> BoxFuture<'a, Result<u32>> {
+        context.runs += 1;
+        let result = match context.errors.pop_front() {
+            Some(err) => Err(err.into()),
+            None => Ok(context.runs),
+        };
+        Box::pin(async move { result })
+    }
+
+    async fn transaction(session: &mut Session, errors: Vec<Error>) -> (Result<u32>, u32) {
+        let mut context = Callback {
+            errors: errors.into(),
+            ..Default::default()
+        };
+        let result = run_transaction(session, &mut context, &mut callback, None).await;
+        (result, context.runs)
+    }
+
+    #[tokio::test]
+    async fn with_transaction_commits() {
+        let mut session = Session::default();
+        let (result, runs) = transaction(&mut session, vec![]).await;
+
+        assert_eq!((result.unwrap(), runs), (1, 1));
+        assert_eq!(session.calls, ["start", "commit"]);
+    }
+
+    #[tokio::test]
+    async fn with_transaction_retries_transient_errors() {
+        let mut session = Session::default();
+        let errors = vec![labelled(TRANSIENT_TRANSACTION_ERROR)];
+        let (result, runs) = transaction(&mut session, errors).await;
+
+        assert_eq!((result.unwrap(), runs), (2, 2));
+        assert_eq!(session.calls, ["start", "abort", "start", "commit"]);
+    }
+
+    #[tokio::test]
+    async fn with_transaction_retries_commits() {
+        let mut session = Session {
+            commits: VecDeque::from([
+                labelled(UNKNOWN_TRANSACTION_COMMIT_RESULT),
+                labelled(TRANSIENT_TRANSACTION_ERROR),
+            ]),
+            ..Default::default()
+        };
+        let (result, runs) = transaction(&mut session, vec![]).await;
+
+        assert_eq!((result.unwrap(), runs), (2, 2));
+        assert_eq!(
+            session.calls,
+            ["start", "commit", "commit", "start", "commit"]
+        );
+    }
+
+    #[tokio::test]
+    async fn with_transaction_fails_on_other_errors() {
+        let mut session = Session::default();
+        let errors = vec![labelled("OtherLabel")];
+        let (result, runs) = transaction(&mut session, errors).await;
+
+        assert!(matches!(result, Err(BongoError::MongoDbError(_))));
+        assert_eq!(runs, 1);
+        assert_eq!(session.calls, ["start", "abort"]);
+
+        session.calls.clear();
+        session.commits.push_back(labelled("OtherLabel"));
+        let (result, _) = transaction(&mut session, vec![]).await;
+
+        assert!(result.is_err());
+        assert_eq!(session.calls, ["start", "commit"]);
+    }
+}
@@ -0,0 +1,860 @@
+//! An in-memory backend for tests, behind the same [`Query`](crate::dao::Query) surface as
+//! Mongo Db.
+//!
+//! Available only if the `testing` feature is enabled.
+//!
+//! To enable use:
+//! ```toml
+//! bongo_mong = {version = "0.3", features = ["testing"] }
+//! ```
+//! in the `dev-dependencies` section of your `Cargo.toml`.
+//!
+//! A pool manager built with [`PoolManager::with_memory_store`](crate::PoolManager::with_memory_store)
+//! runs the operations of the `dao` traits on a [`MemoryStore`] instead of connecting to
+//! Mongo Db, so collections, and the code using them, are tested unchanged:
+//!
+//! ```ignore
+//! let store = MemoryStore::new();
+//! let users = Users::new(PoolManager::with_memory_store(config, store.clone())?);
+//! users.insert_one(user, None, None).await?;
+//! assert_eq!(store.documents(None, "users").len(), 1);
+//! ```
+//!
+//! Filters support implicit equality, `$eq`, `$ne`, `$in`, `$nin`, `$gt`, `$gte`, `$lt`,
+//! `$lte`, `$exists`, `$and`, `$or`, `$nor` and dotted paths. Updates support `$set`,
+//! `$unset` and `$inc`. Of the options, only sorting, paging, `upsert` and
+//! `return_document` are honoured. The unique indexes created by
+//! [`PoolManager::ensure_indexes`](crate::PoolManager::ensure_indexes) are enforced, with the
+//! duplicate key error of the driver. Sessions, aggregations and change streams are not
+//! supported.
+mod query;
+
+use crate::error::{BongoError, BongoError::DaoError, Result};
+use crate::indexes::{self, IndexReport};
+use crate::results::{DeleteResult, InsertManyResult, InsertOneResult, UpdateResult};
+use mongodb::{
+    bson::{self, doc, oid::ObjectId, Bson, Document},
+    error::{ErrorKind, WriteError, WriteFailure},
+    options, IndexModel,
+};
+use parking_lot::Mutex;
+use serde::{de::DeserializeOwned, Serialize};
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/// The API key and the name of a collection.
+type Namespace = (Option<String>, String);
+
+/// A set of in-memory collections.
+///
+/// Uses `Arc` internally, so clones share the same documents. Documents are kept apart by
+/// API key, as if every app had its own database.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStore {
+    collections: Arc<Mutex<HashMap<Namespace, Stored>>>,
+}
+
+/// The documents of a collection, and its indexes.
+#[derive(Debug, Default)]
+struct Stored {
+    documents: Vec<Document>,
+    indexes: Vec<IndexModel>,
+}
+
+impl MemoryStore {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get a copy of the documents of a collection, as stored.
+    pub fn documents(&self, api_key: Option<&str>, collection: &str) -> Vec<Document> {
+        let namespace = (api_key.map(String::from), collection.to_string());
+        self.collections
+            .lock()
+            .get(&namespace)
+            .map(|stored| stored.documents.clone())
+            .unwrap_or_default()
+    }
+
+    /// Get the database of the given API key.
+    pub(crate) fn database(&self, api_key: Option<&str>) -> MemoryDatabase {
+        MemoryDatabase {
+            store: self.clone(),
+            api_key: api_key.map(String::from),
+        }
+    }
+}
+
+/// The collections of a store under an API key.
+#[derive(Clone, Debug)]
+pub(crate) struct MemoryDatabase {
+    store: MemoryStore,
+    api_key: Option<String>,
+}
+
+impl MemoryDatabase {
+    /// Get the collection with the given name.
+    pub(crate) fn collection<D>(&self, name: &str) -> MemoryCollection<D> {
+        MemoryCollection {
+            namespace: (self.api_key.clone(), name.to_string()),
+            store: self.store.clone(),
+            document: PhantomData,
+        }
+    }
+
+    /// Create the declared indexes of a collection, if missing.
+    ///
+    /// See [`PoolManager::ensure_indexes`](crate::PoolManager::ensure_indexes).
+    pub(crate) fn ensure_indexes(&self, name: &str, declared: Vec<IndexModel>) -> IndexReport {
+        let mut collections = self.store.collections.lock();
+        let namespace = (self.api_key.clone(), name.to_string());
+        let stored = collections.entry(namespace).or_default();
+        let (missing, report) = indexes::compare(name, declared, &stored.indexes);
+        stored.indexes.extend(missing);
+        report
+    }
+}
+
+/// An in-memory collection of documents of type `D`.
+///
+/// The methods follow the ones of [`mongodb::Collection`].
+pub(crate) struct MemoryCollection<D> {
+    namespace: Namespace,
+    store: MemoryStore,
+    document: PhantomData<fn() -> D>,
+}
+
+impl<D> fmt::Debug for MemoryCollection<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryCollection")
+            .field("namespace", &self.namespace)
+            .finish()
+    }
+}
+
+/// How the documents matched by an update change.
+enum Change<'a> {
+    Update(&'a Document),
+    Replace(&'a Document),
+}
+
+/// The outcome of an update, with the last updated document before and after the update.
+#[derive(Default)]
+struct Modified {
+    result: UpdateResult,
+    before: Option<Document>,
+    after: Option<Document>,
+}
+
+impl<D> MemoryCollection<D>
+where
+    D: DeserializeOwned + Serialize,
+{
+    pub(crate) fn find(
+        &self,
+        filter: Option<Document>,
+        options: Option<options::FindOptions>,
+    ) -> Result<Vec<D>> {
+        let filter = filter.unwrap_or_default();
+        let options = options.unwrap_or_default();
+        self.with_stored(|stored| {
+            let skip = options.skip.unwrap_or(0) as usize;
+            let limit = match options.limit {
+                Some(limit) if limit != 0 => limit.unsigned_abs() as usize,
+                _ => usize::MAX,
+            };
+            stored
+                .select(&filter, options.sort.as_ref())?
+                .into_iter()
+                .skip(skip)
+                .take(limit)
+                .map(|index| decode(&stored.documents[index]))
+                .collect()
+        })
+    }
+
+    pub(crate) fn find_one(
+        &self,
+        filter: Option<Document>,
+        options: Option<options::FindOneOptions>,
+    ) -> Result<Option<D>> {
+        let filter = filter.unwrap_or_default();
+        let options = options.unwrap_or_default();
+        self.with_stored(|stored| {
+            stored
+                .select(&filter, options.sort.as_ref())?
+                .into_iter()
+                .nth(options.skip.unwrap_or(0) as usize)
+                .map(|index| decode(&stored.documents[index]))
+                .transpose()
+        })
+    }
+
+    pub(crate) fn insert_one(&self, doc: &D) -> Result<InsertOneResult> {
+        let document = encode(doc)?;
+        self.with_stored(|stored| {
+            Ok(InsertOneResult {
+                inserted_id: stored.insert(&self.namespace.1, document)?,
+            })
+        })
+    }
+
+    /// Insert the documents in order, stopping at the first failure.
+    pub(crate) fn insert_many<B: Borrow<D>>(
+        &self,
+        docs: impl IntoIterator<Item = B>,
+    ) -> Result<InsertManyResult> {
+        let docs = docs
+            .into_iter()
+            .map(|doc| encode(doc.borrow()))
+            .collect::<Result<Vec<_>>>()?;
+        self.with_stored(|stored| {
+            let mut result = InsertManyResult::default();
+            for (index, document) in docs.into_iter().enumerate() {
+                let id = stored.insert(&self.namespace.1, document)?;
+                result.inserted_ids.insert(index, id);
+            }
+            Ok(result)
+        })
+    }
+
+    pub(crate) fn update_one(
+        &self,
+        query: Document,
+        update: options::UpdateModifications,
+        options: Option<options::UpdateOptions>,
+    ) -> Result<UpdateResult> {
+        let update = update_document(update)?;
+        let upsert = options.and_then(|options| options.upsert);
+        self.with_stored(|stored| {
+            let change = Change::Update(&update);
+            let modified = stored.modify(&self.namespace.1, &query, change, upsert, false, None)?;
+            Ok(modified.result)
+        })
+    }
+
+    pub(crate) fn update_many(
+        &self,
+        query: Document,
+        update: options::UpdateModifications,
+        options: Option<options::UpdateOptions>,
+    ) -> Result<UpdateResult> {
+        let update = update_document(update)?;
+        let upsert = options.and_then(|options| options.upsert);
+        self.with_stored(|stored| {
+            let change = Change::Update(&update);
+            let modified = stored.modify(&self.namespace.1, &query, change, upsert, true, None)?;
+            Ok(modified.result)
+        })
+    }
+
+    pub(crate) fn replace_one(
+        &self,
+        query: Document,
+        replacement: &D,
+        options: Option<options::ReplaceOptions>,
+    ) -> Result<UpdateResult> {
+        let replacement = encode(replacement)?;
+        let upsert = options.and_then(|options| options.upsert);
+        self.with_stored(|stored| {
+            let change = Change::Replace(&replacement);
+            let modified = stored.modify(&self.namespace.1, &query, change, upsert, false, None)?;
+            Ok(modified.result)
+        })
+    }
+
+    pub(crate) fn delete_one(&self, query: Document) -> Result<DeleteResult> {
+        self.with_stored(|stored| {
+            let selected = stored.select(&query, None)?;
+            if let Some(index) = selected.first() {
+                stored.documents.remove(*index);
+            }
+            Ok(DeleteResult {
+                deleted_count: selected.len().min(1) as u64,
+            })
+        })
+    }
+
+    pub(crate) fn delete_many(&self, query: Document) -> Result<DeleteResult> {
+        self.with_stored(|stored| {
+            let selected = stored.select(&query, None)?;
+            for index in selected.iter().rev() {
+                stored.documents.remove(*index);
+            }
+            Ok(DeleteResult {
+                deleted_count: selected.len() as u64,
+            })
+        })
+    }
+
+    /// Whether the document is returned as it was before or after the update depends on the
+    /// `return_document` option.
+    pub(crate) fn find_one_and_update(
+        &self,
+        filter: Document,
+        update: options::UpdateModifications,
+        options: Option<options::FindOneAndUpdateOptions>,
+    ) -> Result<Option<D>> {
+        let update = update_document(update)?;
+        let options = options.unwrap_or_default();
+        self.with_stored(|stored| {
+            let change = Change::Update(&update);
+            let sort = options.sort.as_ref();
+            let modified = stored.modify(
+                &self.namespace.1,
+                &filter,
+                change,
+                options.upsert,
+                false,
+                sort,
+            )?;
+            let document = match options.return_document {
+                Some(options::ReturnDocument::After) => modified.after,
+                _ => modified.before,
+            };
+            document.as_ref().map(decode).transpose()
+        })
+    }
+
+    pub(crate) fn find_one_and_delete(
+        &self,
+        filter: Document,
+        options: Option<options::FindOneAndDeleteOptions>,
+    ) -> Result<Option<D>> {
+        let options = options.unwrap_or_default();
+        self.with_stored(
+            |stored| match stored.select(&filter, options.sort.as_ref())?.first() {
+                Some(index) => decode(&stored.documents.remove(*index)).map(Some),
+                None => Ok(None),
+            },
+        )
+    }
+
+    pub(crate) fn count_documents(&self, filter: Option<Document>) -> Result<u64> {
+        let filter = filter.unwrap_or_default();
+        self.with_stored(|stored| Ok(stored.select(&filter, None)?.len() as u64))
+    }
+
+    pub(crate) fn estimated_document_count(&self) -> Result<u64> {
+        self.with_stored(|stored| Ok(stored.documents.len() as u64))
+    }
+
+    pub(crate) fn distinct(&self, field_name: &str, filter: Option<Document>) -> Result<Vec<Bson>> {
+        let filter = filter.unwrap_or_default();
+        self.with_stored(|stored| {
+            let mut values: Vec<Bson> = Vec::new();
+            for index in stored.select(&filter, None)? {
+                let value = match query::lookup(&stored.documents[index], field_name) {
+                    Some(Bson::Array(items)) => items.clone(),
+                    Some(value) => vec![value.clone()],
+                    None => continue,
+                };
+                for value in value {
+                    if !values.contains(&value) {
+                        values.push(value);
+                    }
+                }
+            }
+            Ok(values)
+        })
+    }
+
+    /// Run `operation` on the documents and indexes of the collection.
+    fn with_stored<T>(&self, operation: impl FnOnce(&mut Stored) -> Result<T>) -> Result<T> {
+        let mut collections = self.store.collections.lock();
+        operation(collections.entry(self.namespace.clone()).or_default())
+    }
+}
+
+impl Stored {
+    /// The indexes of the documents matching the filter, in the given order if any.
+    fn select(&self, filter: &Document, sort: Option<&Document>) -> Result<Vec<usize>> {
+        let mut selected = Vec::new();
+        for (index, document) in self.documents.iter().enumerate() {
+            if query::matches(document, filter)? {
+                selected.push(index);
+            }
+        }
+        if let Some(sort) = sort {
+            selected.sort_by(|a, b| order(&self.documents[*a], &self.documents[*b], sort));
+        }
+        Ok(selected)
+    }
+
+    /// Update the first, or every, document matching `filter`.
+    ///
+    /// With `upsert`, a document is inserted when none matches, built from the equalities of
+    /// the filter and the change.
+    fn modify(
+        &mut self,
+        collection: &str,
+        filter: &Document,
+        change: Change,
+        upsert: Option<bool>,
+        many: bool,
+        sort: Option<&Document>,
+    ) -> Result<Modified> {
+        let mut selected = self.select(filter, sort)?;
+        if !many {
+            selected.truncate(1);
+        }
+
+        let mut modified = Modified::default();
+        for index in selected {
+            let mut document = self.documents[index].clone();
+            let changed = match change {
+                Change::Update(update) => query::apply_update(&mut document, update)?,
+                Change::Replace(replacement) => {
+                    let mut replacement = replacement.clone();
+                    if let Some(id) = document.get("_id") {
+                        replacement.insert("_id", id.clone());
+                    }
+                    let changed = document != replacement;
+                    document = replacement;
+                    changed
+                }
+            };
+            self.check_unique(collection, &document, Some(index))?;
+            modified.before = Some(std::mem::replace(
+                &mut self.documents[index],
+                document.clone(),
+            ));
+            modified.result.matched_count += 1;
+            modified.result.modified_count += changed as u64;
+            modified.after = Some(document);
+        }
+
+        if modified.result.matched_count == 0 && upsert == Some(true) {
+            let mut document = query::equalities(filter)?;
+            match change {
+                Change::Update(update) => {
+                    query::apply_update(&mut document, update)?;
+                }
+                Change::Replace(replacement) => document.extend(replacement.clone()),
+            }
+            let id = self.insert(collection, document)?;
+            modified.after = self.documents.last().cloned();
+            modified.result.upserted_id = Some(id);
+        }
+        Ok(modified)
+    }
+
+    /// Store the document, returning its `_id`, generated if missing.
+    fn insert(&mut self, collection: &str, mut document: Document) -> Result<Bson> {
+        let id = match document.get("_id") {
+            Some(id) => id.clone(),
+            None => {
+                let id = Bson::ObjectId(ObjectId::new());
+                document.insert("_id", id.clone());
+                id
+            }
+        };
+        self.check_unique(collection, &document, None)?;
+        self.documents.push(document);
+        Ok(id)
+    }
+
+    /// Check that storing `document`, in place of the one at `replaced` if any, keeps the
+    /// `_id` and the keys of the unique indexes unique.
+    fn check_unique(
+        &self,
+        collection: &str,
+        document: &Document,
+        replaced: Option<usize>,
+    ) -> Result<()> {
+        let id = IndexModel::builder()
+            .keys(doc! {"_id": 1})
+            .options(
+                options::IndexOptions::builder()
+                    .name("_id_".to_string())
+                    .unique(true)
+                    .build(),
+            )
+            .build();
+        let unique = self.indexes.iter().filter(
+            |index| matches!(&index.options, Some(options) if options.unique == Some(true)),
+        );
+
+        for index in std::iter::once(&id).chain(unique) {
+            let key = match index_key(index, document)? {
+                Some(key) => key,
+                None => continue,
+            };
+            for (other, stored) in self.documents.iter().enumerate() {
+                if Some(other) != replaced && index_key(index, stored)?.as_ref() == Some(&key) {
+                    return Err(duplicate_key(collection, index, &key));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The values of the keys of the index in the document, if the index covers it.
+///
+/// Missing fields are indexed as `null`, and documents not matching the partial filter of the
+/// index are not covered.
+fn index_key(index: &IndexModel, document: &Document) -> Result<Option<Vec<Bson>>> {
+    let filter = index
+        .options
+        .as_ref()
+        .and_then(|options| options.partial_filter_expression.as_ref());
+    if let Some(filter) = filter {
+        if !query::matches(document, filter)? {
+            return Ok(None);
+        }
+    }
+    let key = index
+        .keys
+        .keys()
+        .map(|path| query::lookup(document, path).cloned().unwrap_or(Bson::Null))
+        .collect();
+    Ok(Some(key))
+}
+
+/// The error of the driver for a write violating a unique index.
+fn duplicate_key(collection: &str, index: &IndexModel, key: &[Bson]) -> BongoError {
+    const DUPLICATE_KEY: i32 = 11000;
+
+    let message = format!(
+        "E11000 duplicate key error collection: {} index: {} dup key: {:?}",
+        collection,
+        indexes::name(index),
+        key
+    );
+    let error = doc! {"code": DUPLICATE_KEY, "codeName": "DuplicateKey", "errmsg": message};
+    match bson::from_document::<WriteError>(error) {
+        Ok(error) => {
+            mongodb::error::Error::from(ErrorKind::Write(WriteFailure::WriteError(error))).into()
+        }
+        Err(err) => DaoError(err.to_string()),
+    }
+}
+
+/// Compare documents on the fields of a sort specification, missing fields first.
+fn order(document: &Document, other: &Document, sort: &Document) -> Ordering {
+    for (path, direction) in sort {
+        let order = match (query::lookup(document, path), query::lookup(other, path)) {
+            (Some(value), Some(other)) => query::compare(value, other).unwrap_or(Ordering::Equal),
+            (value, other) => value.is_some().cmp(&other.is_some()),
+        };
+        let order = match direction {
+            Bson::Int32(-1) | Bson::Int64(-1) => order.reverse(),
+            _ => order,
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+    Ordering::Equal
+}
+
+fn update_document(update: options::UpdateModifications) -> Result<Document> {
+    match update {
+        options::UpdateModifications::Document(update) => Ok(update),
+        _ => Err(DaoError("update pipelines not supported in memory".into())),
+    }
+}
+
+fn encode<D: Serialize>(document: &D) -> Result<Document> {
+    bson::to_document(document).map_err(|err| DaoError(err.to_string()))
+}
+
+fn decode<D: DeserializeOwned>(document: &Document) -> Result<D> {
+    bson::from_document(document.clone()).map_err(|err| DaoError(err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dao::{Collection, DbConnect, Query};
+    use crate::PoolManager;
+    use ::config::Config;
+    use futures::TryStreamExt;
+    use serde::Deserialize;
+
+    #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+    struct User {
+        id: String,
+        age: i32,
+    }
+
+    fn user(id: &str, age: i32) -> User {
+        User { id: id.into(), age }
+    }
+
+    struct Users {
+        pool_manager: PoolManager,
+        audit: Option<&'static str>,
+    }
+
+    impl Collection for Users {
+        fn name(&self) -> &str {
+            "users"
+        }
+
+        fn indexes(&self) -> Vec<IndexModel> {
+            let options = options::IndexOptions::builder().unique(true).build();
+            vec![IndexModel::builder()
+                .keys(doc! {"id": 1})
+                .options(options)
+                .build()]
+        }
+
+        fn audit_collection(&self) -> Option<&str> {
+            self.audit
+        }
+    }
+
+    impl DbConnect for Users {
+        fn pool_manager(&self) -> &PoolManager {
+            &self.pool_manager
+        }
+    }
+
+    impl Query<User> for Users {}
+
+    fn users(store: &MemoryStore) -> Users {
+        Users {
+            pool_manager: PoolManager::with_memory_store(Config::default(), store.clone()).unwrap(),
+            audit: None,
+        }
+    }
+
+    fn audited_users(store: &MemoryStore, config: Config) -> Users {
+        Users {
+            pool_manager: PoolManager::with_memory_store(config, store.clone()).unwrap(),
+            audit: Some("audit"),
+        }
+    }
+
+    #[tokio::test]
+    async fn query_runs_in_memory() {
+        let store = MemoryStore::new();
+        let users = users(&store);
+        users
+            .insert_many([user("1", 20), user("2", 30), user("3", 40)], None, None)
+            .await
+            .unwrap();
+
+        let found: Vec<User> = users
+            .find(doc! {"age": {"$gte": 30}}, None, None)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(found, vec![user("2", 30), user("3", 40)]);
+
+        let updated = users
+            .update_one(doc! {"id": "1"}, doc! {"$inc": {"age": 1}}, None, None)
+            .await
+            .unwrap();
+        assert_eq!((updated.matched_count, updated.modified_count), (1, 1));
+        let found = users.find_one(doc! {"id": "1"}, None, None).await.unwrap();
+        assert_eq!(found, Some(user("1", 21)));
+
+        let deleted = users
+            .delete_many(doc! {"id": {"$in": ["1", "2"]}}, None, None)
+            .await
+            .unwrap();
+        assert_eq!(deleted.deleted_count, 2);
+        assert_eq!(users.count_documents(None, None, None).await.unwrap(), 1);
+        assert_eq!(store.documents(None, "users").len(), 1);
+    }
+
+    #[tokio::test]
+    async fn audit_ignores_the_collection_option() {
+        let source = r#"{
+            "users": {
+                "write": {
+                    "baseUri": "mongodb://localhost/db",
+                    "collection": "users_v2"
+                }
+            }
+        }"#;
+        let config = Config::builder()
+            .add_source(::config::File::from_str(source, ::config::FileFormat::Json))
+            .build()
+            .unwrap();
+        let store = MemoryStore::new();
+        let users = audited_users(&store, config);
+        users.insert_one(user("1", 20), None, None).await.unwrap();
+
+        assert_eq!(store.documents(None, "users_v2").len(), 1);
+        assert_eq!(store.documents(None, "audit").len(), 1);
+        assert!(store.documents(None, "users").is_empty());
+    }
+
+    #[tokio::test]
+    async fn audit_failures_keep_the_write() {
+        let store = MemoryStore::new();
+        let unique = options::IndexOptions::builder().unique(true).build();
+        let index = IndexModel::builder()
+            .keys(doc! {"operation": 1})
+            .options(unique)
+            .build();
+        store.database(None).ensure_indexes("audit", vec![index]);
+
+        let users = audited_users(&store, Config::default());
+        users.insert_one(user("1", 20), None, None).await.unwrap();
+        // Recording a second insertion breaks the unique index of the audit collection
+        users.insert_one(user("2", 30), None, None).await.unwrap();
+
+        assert_eq!(store.documents(None, "users").len(), 2);
+        assert_eq!(store.documents(None, "audit").len(), 1);
+    }
+
+    #[tokio::test]
+    async fn query_upserts_in_memory() {
+        let users = users(&MemoryStore::new());
+        let options = options::FindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(options::ReturnDocument::After)
+            .build();
+        let found = users
+            .find_one_and_update(doc! {"id": "1"}, doc! {"$set": {"age": 20}}, options, None)
+            .await
+            .unwrap();
+        assert_eq!(found, Some(user("1", 20)));
+        assert_eq!(users.estimated_document_count(None, None).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn query_sorts_and_pages_in_memory() {
+        let users = users(&MemoryStore::new());
+        users
+            .insert_many([user("1", 30), user("2", 10), user("3", 20)], None, None)
+            .await
+            .unwrap();
+        let options = options::FindOptions::builder()
+            .sort(doc! {"age": -1})
+            .skip(1)
+            .limit(1)
+            .build();
+        let found: Vec<User> = users
+            .find(None, options, None)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(found, vec![user("3", 20)]);
+    }
+
+    #[tokio::test]
+    async fn memory_store_separates_api_keys() {
+        let store = MemoryStore::new();
+        let users = users(&store);
+        users
+            .insert_one(user("1", 20), None, Some("a"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            users.count_documents(None, None, Some("a")).await.unwrap(),
+            1
+        );
+        assert_eq!(
+            users.count_documents(None, None, Some("b")).await.unwrap(),
+            0
+        );
+        assert_eq!(store.documents(Some("a"), "users").len(), 1);
+    }
+
+    #[tokio::test]
+    async fn memory_store_enforces_unique_indexes() {
+        let store = MemoryStore::new();
+        let users = users(&store);
+        let reports = users.pool_manager.ensure_indexes(&[&users]).await.unwrap();
+        assert_eq!(reports[0].created, vec!["id_1"]);
+
+        users.insert_one(user("1", 20), None, None).await.unwrap();
+        users.insert_one(user("2", 30), None, None).await.unwrap();
+        let is_duplicate = |result: Result<_>| match result {
+            Err(BongoError::MongoDbError(err)) => matches!(
+                &*err.kind,
+                ErrorKind::Write(WriteFailure::WriteError(err)) if err.code == 11000
+            ),
+            _ => false,
+        };
+        assert!(is_duplicate(
+            users.insert_one(user("1", 40), None, None).await.map(drop)
+        ));
+        assert!(is_duplicate(
+            users
+                .update_one(doc! {"id": "2"}, doc! {"$set": {"id": "1"}}, None, None)
+                .await
+                .map(drop)
+        ));
+        assert_eq!(store.documents(None, "users").len(), 2);
+
+        // Other API keys have their own indexes
+        users
+            .insert_one(user("1", 40), None, Some("a"))
+            .await
+            .unwrap();
+        users
+            .insert_one(user("1", 40), None, Some("a"))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn memory_pools_report_config_errors() {
+        let source = r#"{
+            "read": {"baseUri": "localhost"}
+        }"#;
+        let config = Config::builder()
+            .add_source(::config::File::from_str(source, ::config::FileFormat::Json))
+            .build()
+            .unwrap();
+        let users = Users {
+            pool_manager: PoolManager::with_memory_store(config, MemoryStore::new()).unwrap(),
+            audit: None,
+        };
+        assert!(users.find_one(doc! {"id": "1"}, None, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn ensure_indexes_covers_every_app() {
+        let source = r#"{
+            "mongodbPerApp": {
+                "wat": {
+                    "users": {
+                        "write": {"baseUri": "mongodb://tenant/db"}
+                    }
+                }
+            }
+        }"#;
+        let config = Config::builder()
+            .add_source(::config::File::from_str(source, ::config::FileFormat::Json))
+            .build()
+            .unwrap();
+        let store = MemoryStore::new();
+        let users = Users {
+            pool_manager: PoolManager::with_memory_store(config, store.clone()).unwrap(),
+            audit: None,
+        };
+        let reports = users.pool_manager.ensure_indexes(&[&users]).await.unwrap();
+        let api_keys: Vec<_> = reports
+            .iter()
+            .map(|report| report.api_key.as_deref())
+            .collect();
+        assert_eq!(api_keys, [None, Some("wat")]);
+        assert!(reports.iter().all(|report| report.created == ["id_1"]));
+
+        users
+            .insert_one(user("1", 20), None, Some("wat"))
+            .await
+            .unwrap();
+        let duplicate = users.insert_one(user("1", 40), None, Some("wat")).await;
+        assert!(matches!(duplicate, Err(BongoError::MongoDbError(_))));
+        assert_eq!(store.documents(Some("wat"), "users").len(), 1);
+    }
+}
@@ -0,0 +1,376 @@
+//! Evaluation of filters and updates over documents.
+use crate::error::{BongoError::DaoError, Result};
+use mongodb::bson::{Bson, Document};
+use std::cmp::Ordering;
+
+/// Check if the document matches the filter.
+///
+/// Fields are compared with implicit equality, or with the `$eq`, `$ne`, `$in`, `$nin`,
+/// `$gt`, `$gte`, `$lt`, `$lte`, `$exists` and `$regex` operators, and filters combine with
+/// `$and`, `$or` and `$nor`. Dotted paths reach into embedded documents and arrays.
+pub(crate) fn matches(document: &Document, filter: &Document) -> Result<bool> {
+    for (key, condition) in filter {
+        let matched = match key.as_str() {
+            "$and" => all(document, clauses(condition)?)?,
+            "$or" => any(document, clauses(condition)?)?,
+            "$nor" => !any(document, clauses(condition)?)?,
+            operator if operator.starts_with('$') => return Err(unsupported(operator)),
+            path => matches_condition(lookup(document, path), condition)?,
+        };
+        if !matched {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn all(document: &Document, filters: Vec<&Document>) -> Result<bool> {
+    for filter in filters {
+        if !matches(document, filter)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn any(document: &Document, filters: Vec<&Document>) -> Result<bool> {
+    for filter in filters {
+        if matches(document, filter)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// The filters of a logical operator.
+fn clauses(value: &Bson) -> Result<Vec<&Document>> {
+    array(value)?
+        .iter()
+        .map(|clause| match clause {
+            Bson::Document(filter) => Ok(filter),
+            _ => Err(DaoError(
+                "logical operators expect an array of filters".into(),
+            )),
+        })
+        .collect()
+}
+
+fn matches_condition(value: Option<&Bson>, condition: &Bson) -> Result<bool> {
+    let operators = match condition {
+        Bson::Document(operators) if is_operators(operators) => operators,
+        condition => return Ok(equals(value, condition)),
+    };
+    for (operator, operand) in operators {
+        let matched = match operator.as_str() {
+            "$eq" => equals(value, operand),
+            "$ne" => !equals(value, operand),
+            "$in" => array(operand)?.iter().any(|item| equals(value, item)),
+            "$nin" => !array(operand)?.iter().any(|item| equals(value, item)),
+            "$gt" => compares(value, operand, |order| order == Ordering::Greater),
+            "$gte" => compares(value, operand, |order| order != Ordering::Less),
+            "$lt" => compares(value, operand, |order| order == Ordering::Less),
+            "$lte" => compares(value, operand, |order| order != Ordering::Greater),
+            "$exists" => value.is_some() == !matches!(operand, Bson::Boolean(false)),
+            "$regex" => matches_regex(value, operand, operators.get("$options"))?,
+            // Read along with `$regex`
+            "$options" if operators.contains_key("$regex") => true,
+            operator => return Err(unsupported(operator)),
+        };
+        if !matched {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Check if a string field, or a string in an array field, matches the pattern.
+///
+/// Only the `i` option is supported.
+fn matches_regex(value: Option<&Bson>, pattern: &Bson, options: Option<&Bson>) -> Result<bool> {
+    let (pattern, options) = match (pattern, options) {
+        (Bson::String(pattern), None) => (pattern.as_str(), ""),
+        (Bson::String(pattern), Some(Bson::String(options))) => {
+            (pattern.as_str(), options.as_str())
+        }
+        (Bson::RegularExpression(regex), None) => (regex.pattern.as_str(), regex.options.as_str()),
+        _ => return Err(DaoError("$regex expects a string pattern".into())),
+    };
+    if let Some(option) = options.chars().find(|option| *option != 'i') {
+        return Err(DaoError(format!("unsupported $regex option: {}", option)));
+    }
+    let regex = regex::RegexBuilder::new(pattern)
+        .case_insensitive(options.contains('i'))
+        .build()
+        .map_err(|err| DaoError(format!("invalid $regex: {}", err)))?;
+    let is_match = |value: &Bson| matches!(value, Bson::String(value) if regex.is_match(value));
+    Ok(match value {
+        Some(Bson::Array(items)) => items.iter().any(is_match),
+        Some(value) => is_match(value),
+        None => false,
+    })
+}
+
+/// Check if a field equals the expected value, or holds an array containing it.
+fn equals(value: Option<&Bson>, expected: &Bson) -> bool {
+    match value {
+        None => matches!(expected, Bson::Null),
+        Some(Bson::Array(items)) if !matches!(expected, Bson::Array(_)) => items
+            .iter()
+            .any(|item| compare(item, expected) == Some(Ordering::Equal)),
+        Some(value) => compare(value, expected) == Some(Ordering::Equal),
+    }
+}
+
+fn compares(value: Option<&Bson>, operand: &Bson, check: impl Fn(Ordering) -> bool) -> bool {
+    match value {
+        Some(Bson::Array(items)) => items
+            .iter()
+            .any(|item| matches!(compare(item, operand), Some(order) if check(order))),
+        Some(value) => matches!(compare(value, operand), Some(order) if check(order)),
+        None => false,
+    }
+}
+
+/// Compare values of the same type, with numbers of any type compared together.
+pub(crate) fn compare(value: &Bson, other: &Bson) -> Option<Ordering> {
+    if let (Some(value), Some(other)) = (number(value), number(other)) {
+        return value.partial_cmp(&other);
+    }
+    match (value, other) {
+        (Bson::String(value), Bson::String(other)) => Some(value.cmp(other)),
+        (Bson::Boolean(value), Bson::Boolean(other)) => Some(value.cmp(other)),
+        (Bson::DateTime(value), Bson::DateTime(other)) => Some(value.cmp(other)),
+        (Bson::ObjectId(value), Bson::ObjectId(other)) => Some(value.cmp(other)),
+        (value, other) if value == other => Some(Ordering::Equal),
+        _ => None,
+    }
+}
+
+fn number(value: &Bson) -> Option<f64> {
+    match value {
+        Bson::Int32(value) => Some(*value as f64),
+        Bson::Int64(value) => Some(*value as f64),
+        Bson::Double(value) => Some(*value),
+        _ => None,
+    }
+}
+
+fn array(value: &Bson) -> Result<&Vec<Bson>> {
+    match value {
+        Bson::Array(items) => Ok(items),
+        _ => Err(DaoError(format!("expected an array, found {}", value))),
+    }
+}
+
+/// Check if the keys of the document are operators, as in `{"$gt": 1}`.
+fn is_operators(document: &Document) -> bool {
+    matches!(document.keys().next(), Some(key) if key.starts_with('$'))
+}
+
+fn unsupported(operator: &str) -> crate::error::BongoError {
+    DaoError(format!("operator {} not supported in memory", operator))
+}
+
+/// Get the value at a dotted path, as in `sessions.1.active`.
+pub(crate) fn lookup<'a>(document: &'a Document, path: &str) -> Option<&'a Bson> {
+    let mut parts = path.split('.');
+    let mut value = document.get(parts.next()?)?;
+    for part in parts {
+        value = match value {
+            Bson::Document(document) => document.get(part)?,
+            Bson::Array(items) => items.get(part.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    Some(value)
+}
+
+/// Apply the `$set`, `$unset` and `$inc` operators of the update to the document.
+///
+/// Returns whether the document changed.
+pub(crate) fn apply_update(document: &mut Document, update: &Document) -> Result<bool> {
+    let before = document.clone();
+    for (operator, fields) in update {
+        let fields = match fields {
+            Bson::Document(fields) => fields,
+            _ => return Err(DaoError(format!("{} expects a document", operator))),
+        };
+        for (path, value) in fields {
+            match operator.as_str() {
+                "$set" => set(document, path, value.clone())?,
+                "$unset" => unset(document, path),
+                "$inc" => {
+                    let current = lookup(document, path).unwrap_or(&Bson::Int32(0));
+                    let sum = add(current, value)?;
+                    set(document, path, sum)?
+                }
+                operator => return Err(unsupported(operator)),
+            }
+        }
+    }
+    Ok(*document != before)
+}
+
+/// Set the value at a dotted path, creating the missing embedded documents.
+fn set(document: &mut Document, path: &str, value: Bson) -> Result<()> {
+    let (head, rest) = match path.split_once('.') {
+        Some(split) => split,
+        None => {
+            document.insert(path, value);
+            return Ok(());
+        }
+    };
+    if !document.contains_key(head) {
+        document.insert(head, Document::new());
+    }
+    match document.get_mut(head) {
+        Some(Bson::Document(embedded)) => set(embedded, rest, value),
+        _ => Err(DaoError(format!(
+            "cannot set {} in a non-document field",
+            path
+        ))),
+    }
+}
+
+fn unset(document: &mut Document, path: &str) {
+    match path.split_once('.') {
+        Some((head, rest)) => {
+            if let Some(Bson::Document(embedded)) = document.get_mut(head) {
+                unset(embedded, rest)
+            }
+        }
+        None => {
+            document.remove(path);
+        }
+    }
+}
+
+fn add(value: &Bson, increment: &Bson) -> Result<Bson> {
+    Ok(match (value, increment) {
+        (Bson::Int32(value), Bson::Int32(increment)) => match value.checked_add(*increment) {
+            Some(sum) => Bson::Int32(sum),
+            None => Bson::Int64(*value as i64 + *increment as i64),
+        },
+        (Bson::Int32(_) | Bson::Int64(_), Bson::Int32(_) | Bson::Int64(_)) => {
+            Bson::Int64(integer(value) + integer(increment))
+        }
+        (value, increment) => match (number(value), number(increment)) {
+            (Some(value), Some(increment)) => Bson::Double(value + increment),
+            _ => return Err(DaoError(format!("cannot increment {}", value))),
+        },
+    })
+}
+
+fn integer(value: &Bson) -> i64 {
+    match value {
+        Bson::Int32(value) => *value as i64,
+        Bson::Int64(value) => *value,
+        _ => 0,
+    }
+}
+
+/// The fields a filter sets with implicit equality, used to build upserted documents.
+pub(crate) fn equalities(filter: &Document) -> Result<Document> {
+    let mut document = Document::new();
+    for (key, value) in filter {
+        match value {
+            _ if key.starts_with('$') => {}
+            Bson::Document(operators) if is_operators(operators) => {
+                if let Some(value) = operators.get("$eq") {
+                    set(&mut document, key, value.clone())?;
+                }
+            }
+            value => set(&mut document, key, value.clone())?,
+        }
+    }
+    Ok(document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::bson::doc;
+
+    fn installation() -> Document {
+        doc! {
+            "wappierId": "w1",
+            "apiKey": "key",
+            "age": 30,
+            "tags": ["a", "b"],
+            "sessions": {"s1": {"active": true}, "s2": {"active": false}},
+        }
+    }
+
+    #[test]
+    fn matches_equality_and_dotted_paths() {
+        let document = installation();
+        assert!(matches(
+            &document,
+            &doc! {"wappierId": "w1", "sessions.s1.active": true}
+        )
+        .unwrap());
+        assert!(!matches(&document, &doc! {"sessions.s2.active": true}).unwrap());
+        assert!(!matches(&document, &doc! {"sessions.s3.active": true}).unwrap());
+        assert!(matches(&document, &doc! {"tags": "b"}).unwrap());
+        assert!(matches(&document, &doc! {"missing": null}).unwrap());
+    }
+
+    #[test]
+    fn matches_operators() {
+        let document = installation();
+        assert!(matches(&document, &doc! {"age": {"$eq": 30_i64}}).unwrap());
+        assert!(matches(&document, &doc! {"age": {"$gt": 18, "$lte": 30.0}}).unwrap());
+        assert!(!matches(&document, &doc! {"age": {"$gt": 30}}).unwrap());
+        assert!(matches(&document, &doc! {"apiKey": {"$in": ["other", "key"]}}).unwrap());
+        assert!(!matches(&document, &doc! {"apiKey": {"$nin": ["key"]}}).unwrap());
+        assert!(matches(&document, &doc! {"age": {"$exists": true}}).unwrap());
+        assert!(matches(&document, &doc! {"$or": [{"age": 1}, {"wappierId": "w1"}]}).unwrap());
+        assert!(matches(&document, &doc! {"age": {"$mod": [2, 0]}}).is_err());
+    }
+
+    #[test]
+    fn matches_regexes() {
+        let document = installation();
+        assert!(matches(&document, &doc! {"wappierId": {"$regex": "^w\\d$"}}).unwrap());
+        assert!(!matches(&document, &doc! {"wappierId": {"$regex": "W1"}}).unwrap());
+        let filter = doc! {"wappierId": {"$regex": "W1", "$options": "i"}};
+        assert!(matches(&document, &filter).unwrap());
+        assert!(matches(&document, &doc! {"tags": {"$regex": "b"}}).unwrap());
+        assert!(!matches(&document, &doc! {"age": {"$regex": "3"}}).unwrap());
+        assert!(!matches(&document, &doc! {"missing": {"$regex": ""}}).unwrap());
+        let filter = doc! {"apiKey": {"$regex": "key", "$options": "x"}};
+        assert!(matches(&document, &filter).is_err());
+        assert!(matches(&document, &doc! {"apiKey": {"$options": "i"}}).is_err());
+    }
+
+    #[test]
+    fn apply_update_operators() {
+        let mut document = installation();
+        let update = doc! {
+            "$set": {"sessions.s2.active": true, "profile.name": "Ann"},
+            "$inc": {"age": 1, "visits": 2},
+            "$unset": {"tags": ""},
+        };
+        assert!(apply_update(&mut document, &update).unwrap());
+        assert_eq!(
+            lookup(&document, "sessions.s2.active"),
+            Some(&Bson::Boolean(true))
+        );
+        assert_eq!(lookup(&document, "profile.name"), Some(&Bson::from("Ann")));
+        assert_eq!(lookup(&document, "age"), Some(&Bson::Int32(31)));
+        assert_eq!(lookup(&document, "visits"), Some(&Bson::Int32(2)));
+        assert_eq!(lookup(&document, "tags"), None);
+
+        let update = doc! {"$set": {"age": 31}};
+        assert!(!apply_update(&mut document, &update).unwrap());
+    }
+
+    #[test]
+    fn equalities_of_filter() {
+        let filter = doc! {"id": "1", "age": {"$gt": 1}, "name": {"$eq": "Ann"}, "$or": []};
+        assert_eq!(
+            equalities(&filter).unwrap(),
+            doc! {"id": "1", "name": "Ann"}
+        );
+    }
+}
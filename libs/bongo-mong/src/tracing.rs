@@ -0,0 +1,94 @@
+//! Tracing of the operations of the library.
+//!
+//! Every operation of [`Query`](crate::dao::Query) runs in a `bongo.query` span, carrying
+//! the collection, the operation, the permission and configuration path of the pool, and the
+//! shape of the filter. The commands the driver sends for the operation are reported as
+//! events of that span, so they show up under the request that ran the operation.
+use ::tracing::{debug, warn};
+use mongodb::{
+    bson::{Bson, Document},
+    event::command::{
+        CommandEventHandler, CommandFailedEvent, CommandStartedEvent, CommandSucceededEvent,
+    },
+};
+
+/// Report the command events of a client.
+///
+/// Set on every client built by a [`PoolManager`](crate::PoolManager). Commands sent while
+/// iterating a cursor are reported under the span polling the cursor instead.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CommandTracing;
+
+impl CommandEventHandler for CommandTracing {
+    fn handle_command_started_event(&self, event: CommandStartedEvent) {
+        // The command itself holds the values of the filter, so it is left out
+        debug!(
+            command = %event.command_name,
+            db = %event.db,
+            request_id = event.request_id,
+            address = %event.connection.address,
+            "command started"
+        );
+    }
+
+    fn handle_command_succeeded_event(&self, event: CommandSucceededEvent) {
+        debug!(
+            command = %event.command_name,
+            request_id = event.request_id,
+            duration = ?event.duration,
+            "command succeeded"
+        );
+    }
+
+    fn handle_command_failed_event(&self, event: CommandFailedEvent) {
+        warn!(
+            command = %event.command_name,
+            request_id = event.request_id,
+            duration = ?event.duration,
+            error = %event.failure,
+            "command failed"
+        );
+    }
+}
+
+/// The shape of a filter, with its values redacted.
+///
+/// Fields and operators are kept, so that spans tell what a query filters on without
+/// exposing the values it filters with.
+pub fn filter_shape(filter: &Document) -> Document {
+    filter
+        .iter()
+        .map(|(key, value)| (key.clone(), redact(value)))
+        .collect()
+}
+
+fn redact(value: &Bson) -> Bson {
+    match value {
+        Bson::Document(document) => Bson::Document(filter_shape(document)),
+        Bson::Array(values) => Bson::Array(values.iter().map(redact).collect()),
+        _ => Bson::String("?".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::bson::doc;
+
+    #[test]
+    fn filter_shape_redacts_values() {
+        let filter = doc! {
+            "id": "1",
+            "age": {"$gt": 18},
+            "$or": [{"name": "Ann"}, {"tags": {"$in": ["a", "b"]}}],
+        };
+        assert_eq!(
+            filter_shape(&filter),
+            doc! {
+                "id": "?",
+                "age": {"$gt": "?"},
+                "$or": [{"name": "?"}, {"tags": {"$in": ["?", "?"]}}],
+            }
+        );
+    }
+}
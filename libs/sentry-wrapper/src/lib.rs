@@ -47,6 +47,17 @@ pub fn extract_common_headers(headers: &HeaderMap) -> Vec<(&'static str, String)
     res
 }
 
+/// The header carrying the api key of the tenant a request is made for.
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Try to extract the api key of the tenant from the `x-api-key` header.
+///
+/// Unlike the common headers, values that are not visible ASCII are returned as errors rather
+/// than replaced, since the key selects where the data of the request goes.
+pub fn extract_api_key(headers: &HeaderMap) -> Option<Result<&str, http::header::ToStrError>> {
+    headers.get(API_KEY_HEADER).map(|val| val.to_str())
+}
+
 pub fn init(dsn: Option<&str>, options: ClientOptions) -> ClientInitGuard {
     sentry::init((dsn, options))
 }